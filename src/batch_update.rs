@@ -1,76 +1,226 @@
-use crate::{errors::RRDCachedClientError, now::now_timestamp, sanitisation::check_rrd_path};
+use std::ops::Range;
+
+use crate::{
+    errors::RRDCachedClientError, rrd_path::RrdPath, update_timestamp::UpdateTimestamp,
+    update_value::UpdateValue,
+};
+
+/// Default maximum length of an UPDATE line, including the newline.
+///
+/// RRDCached reads commands in fixed size buffers, longer lines are
+/// split in several UPDATE commands.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 4096;
 
 pub struct BatchUpdate {
-    path: String,
-    timestamp: Option<usize>,
-    data: Vec<f64>,
+    path: RrdPath,
+    samples: Vec<(UpdateTimestamp, Vec<UpdateValue>)>,
+}
+
+/// A sample rejected by the server during an update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedSample {
+    /// Timestamp of the rejected sample, `None` if the
+    /// reason given by the server doesn't name it.
+    pub timestamp: Option<UpdateTimestamp>,
+
+    /// Reason given by the server
+    pub reason: String,
 }
 
 impl BatchUpdate {
-    pub fn new(
-        path: &str,
-        timestamp: Option<usize>,
-        data: Vec<f64>,
+    /// Update with one sample.
+    ///
+    /// Values can be anything that converts into an [UpdateValue],
+    /// such as `f64`, or `Option<f64>` for unknown values.
+    pub fn new<V: Into<UpdateValue>>(
+        path: RrdPath,
+        timestamp: UpdateTimestamp,
+        data: Vec<V>,
     ) -> Result<BatchUpdate, RRDCachedClientError> {
         if data.is_empty() {
             return Err(RRDCachedClientError::InvalidBatchUpdate(
                 "data is empty".to_string(),
             ));
         }
-        check_rrd_path(path)?;
         Ok(BatchUpdate {
-            path: path.to_string(),
-            timestamp,
-            data,
+            path,
+            samples: vec![(timestamp, data.into_iter().map(Into::into).collect())],
         })
     }
 
-    pub fn to_command_string(&self) -> Result<String, RRDCachedClientError> {
-        let timestamp_str = match self.timestamp {
-            Some(ts) => ts.to_string(),
-            None => now_timestamp()?.to_string(),
-        };
-        let data_str = self
-            .data
+    /// Update with many samples of the same RRD in a single command.
+    ///
+    /// The samples are sorted by timestamp, as RRDtool rejects
+    /// samples older than the last update. Each sample must have
+    /// the same number of values and a unique timestamp, which
+    /// can't be [UpdateTimestamp::Now].
+    pub fn new_many<T: Into<UpdateTimestamp>, V: Into<UpdateValue>>(
+        path: RrdPath,
+        samples: Vec<(T, Vec<V>)>,
+    ) -> Result<BatchUpdate, RRDCachedClientError> {
+        if samples.is_empty() {
+            return Err(RRDCachedClientError::InvalidBatchUpdate(
+                "samples are empty".to_string(),
+            ));
+        }
+        let nb_values = samples[0].1.len();
+        if samples
             .iter()
-            .map(|f| f.to_string())
+            .any(|(_, data)| data.is_empty() || data.len() != nb_values)
+        {
+            return Err(RRDCachedClientError::InvalidBatchUpdate(
+                "all samples must have the same non-zero number of values".to_string(),
+            ));
+        }
+        let mut samples = samples
+            .into_iter()
+            .map(|(timestamp, data)| {
+                let timestamp = timestamp.into();
+                let duration = timestamp.as_duration().ok_or_else(|| {
+                    RRDCachedClientError::InvalidBatchUpdate(
+                        "timestamps must not be Now".to_string(),
+                    )
+                })?;
+                Ok((duration, timestamp, data))
+            })
+            .collect::<Result<Vec<_>, RRDCachedClientError>>()?;
+        samples.sort_by_key(|(duration, _, _)| *duration);
+        if samples.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(RRDCachedClientError::InvalidBatchUpdate(
+                "timestamps must be unique".to_string(),
+            ));
+        }
+        Ok(BatchUpdate {
+            path,
+            samples: samples
+                .into_iter()
+                .map(|(_, timestamp, data)| (timestamp, data.into_iter().map(Into::into).collect()))
+                .collect(),
+        })
+    }
+
+    /// Path of the RRD to update
+    pub fn path(&self) -> &RrdPath {
+        &self.path
+    }
+
+    /// Samples, sorted by timestamp when built with [BatchUpdate::new_many].
+    pub fn samples(&self) -> &[(UpdateTimestamp, Vec<UpdateValue>)] {
+        &self.samples
+    }
+
+    fn sample_to_string(
+        timestamp: UpdateTimestamp,
+        data: &[UpdateValue],
+    ) -> Result<String, RRDCachedClientError> {
+        let timestamp_str = timestamp.to_string();
+        let data_str = data
+            .iter()
+            .map(|value| value.to_string())
             .collect::<Vec<String>>()
             .join(":");
-        let mut command = String::with_capacity(
-            7 + self.path.len() + 5 + timestamp_str.len() + 1 + data_str.len() + 1,
-        );
+        let mut sample = String::with_capacity(timestamp_str.len() + 1 + data_str.len());
+        sample.push_str(&timestamp_str);
+        sample.push(':');
+        sample.push_str(&data_str);
+        Ok(sample)
+    }
+
+    /// Convert to a single UPDATE command, whatever the number of samples.
+    pub fn to_command_string(&self) -> Result<String, RRDCachedClientError> {
+        let samples_str = self
+            .samples
+            .iter()
+            .map(|(timestamp, data)| Self::sample_to_string(*timestamp, data))
+            .collect::<Result<Vec<String>, RRDCachedClientError>>()?
+            .join(" ");
+        let path = self.path.as_str();
+        let mut command = String::with_capacity(7 + path.len() + 1 + samples_str.len() + 1);
         command.push_str("UPDATE ");
-        command.push_str(&self.path);
-        command.push_str(".rrd ");
-        command.push_str(&timestamp_str);
-        command.push(':');
-        command.push_str(&data_str);
+        command.push_str(path);
+        command.push(' ');
+        command.push_str(&samples_str);
         command.push('\n');
 
         Ok(command)
     }
+
+    /// Convert to UPDATE commands of at most `max_line_length` bytes.
+    ///
+    /// Each command comes with the range of samples it contains.
+    pub(crate) fn to_command_lines(
+        &self,
+        max_line_length: usize,
+    ) -> Result<Vec<(String, Range<usize>)>, RRDCachedClientError> {
+        let path = self.path.as_str();
+        let prefix_length = 7 + path.len();
+        let mut lines = Vec::new();
+        let mut command = String::new();
+        let mut range_start = 0;
+
+        for (index, (timestamp, data)) in self.samples.iter().enumerate() {
+            let sample = Self::sample_to_string(*timestamp, data)?;
+            if prefix_length + 1 + sample.len() + 1 > max_line_length {
+                return Err(RRDCachedClientError::InvalidBatchUpdate(format!(
+                    "sample at index {} does not fit in {} bytes",
+                    index, max_line_length
+                )));
+            }
+            if !command.is_empty() && command.len() + 1 + sample.len() + 1 > max_line_length {
+                command.push('\n');
+                lines.push((std::mem::take(&mut command), range_start..index));
+                range_start = index;
+            }
+            if command.is_empty() {
+                // The maximum length may be large enough to mean no limit
+                let remaining_samples = self.samples.len() - index;
+                command.reserve(
+                    max_line_length.min(prefix_length + remaining_samples * (1 + sample.len()) + 1),
+                );
+                command.push_str("UPDATE ");
+                command.push_str(path);
+            }
+            command.push(' ');
+            command.push_str(&sample);
+        }
+        command.push('\n');
+        lines.push((command, range_start..self.samples.len()));
+
+        Ok(lines)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_new_with_valid_data() {
-        let path = "valid_path";
-        let timestamp = Some(123456789);
+        let path = RrdPath::new("valid_path").unwrap();
+        let timestamp = UpdateTimestamp::Seconds(123456789);
         let data = vec![1.0, 2.0, 3.0];
         let batch_update = BatchUpdate::new(path, timestamp, data).unwrap();
-        assert_eq!(batch_update.path, "valid_path");
-        assert_eq!(batch_update.timestamp, Some(123456789));
-        assert_eq!(batch_update.data, vec![1.0, 2.0, 3.0]);
+        assert_eq!(batch_update.path.name(), "valid_path");
+        assert_eq!(
+            batch_update.samples[0].0,
+            UpdateTimestamp::Seconds(123456789)
+        );
+        assert_eq!(
+            batch_update.samples[0].1,
+            vec![
+                UpdateValue::Number(1.0),
+                UpdateValue::Number(2.0),
+                UpdateValue::Number(3.0)
+            ]
+        );
     }
 
     #[test]
     fn test_new_with_empty_data() {
-        let path = "valid_path";
-        let timestamp = Some(123456789);
-        let data = vec![];
+        let path = RrdPath::new("valid_path").unwrap();
+        let timestamp = UpdateTimestamp::Seconds(123456789);
+        let data: Vec<f64> = vec![];
         let result = BatchUpdate::new(path, timestamp, data);
         assert!(matches!(
             result,
@@ -81,11 +231,169 @@ mod tests {
     #[test]
     fn test_to_command_string_with_timestamp() {
         let batch_update = BatchUpdate {
-            path: "test_path".into(),
-            timestamp: Some(1609459200), // Example timestamp
-            data: vec![1.1, 2.2, 3.3],
+            path: RrdPath::new("test_path").unwrap(),
+            samples: vec![(
+                UpdateTimestamp::Seconds(1609459200), // Example timestamp
+                vec![
+                    UpdateValue::Number(1.1),
+                    UpdateValue::Number(2.2),
+                    UpdateValue::Number(3.3),
+                ],
+            )],
         };
         let command = batch_update.to_command_string().unwrap();
         assert_eq!(command, "UPDATE test_path.rrd 1609459200:1.1:2.2:3.3\n");
     }
+
+    #[test]
+    fn test_new_many() {
+        let path = RrdPath::new("test_path").unwrap();
+        let batch_update = BatchUpdate::new_many(
+            path.clone(),
+            vec![
+                (1609459202, vec![3.0, 3.5]),
+                (1609459200, vec![1.0, 1.5]),
+                (1609459201, vec![2.0, 2.5]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            batch_update.to_command_string().unwrap(),
+            "UPDATE test_path.rrd 1609459200:1:1.5 1609459201:2:2.5 1609459202:3:3.5\n"
+        );
+
+        let result = BatchUpdate::new_many::<usize, f64>(path.clone(), vec![]);
+        assert!(result.is_err());
+
+        let result = BatchUpdate::new_many::<usize, f64>(path.clone(), vec![(1609459200, vec![])]);
+        assert!(result.is_err());
+
+        let result = BatchUpdate::new_many(
+            path.clone(),
+            vec![(1609459200, vec![1.0]), (1609459201, vec![1.0, 2.0])],
+        );
+        assert!(result.is_err());
+
+        let result =
+            BatchUpdate::new_many(path, vec![(1609459200, vec![1.0]), (1609459200, vec![2.0])]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_to_command_lines() {
+        let batch_update = BatchUpdate::new_many(
+            RrdPath::new("test").unwrap(),
+            (0..5).map(|i| (1609459200 + i, vec![i as f64])).collect(),
+        )
+        .unwrap();
+
+        // "UPDATE test.rrd" is 15 bytes, each sample is 13 bytes with its space
+        let lines = batch_update.to_command_lines(15 + 2 * 13 + 1).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (
+                    "UPDATE test.rrd 1609459200:0 1609459201:1\n".to_string(),
+                    0..2
+                ),
+                (
+                    "UPDATE test.rrd 1609459202:2 1609459203:3\n".to_string(),
+                    2..4
+                ),
+                ("UPDATE test.rrd 1609459204:4\n".to_string(), 4..5),
+            ]
+        );
+        assert!(lines.iter().all(|(line, _)| line.len() <= 15 + 2 * 13 + 1));
+
+        let lines = batch_update
+            .to_command_lines(DEFAULT_MAX_LINE_LENGTH)
+            .unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, batch_update.to_command_string().unwrap());
+        assert_eq!(lines[0].1, 0..5);
+
+        let lines = batch_update.to_command_lines(usize::MAX).unwrap();
+        assert_eq!(lines[0].0, batch_update.to_command_string().unwrap());
+
+        let result = batch_update.to_command_lines(15 + 13);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_to_command_string_with_unknown_values() {
+        let batch_update = BatchUpdate::new(
+            RrdPath::new("test_path").unwrap(),
+            UpdateTimestamp::Seconds(1609459200),
+            vec![
+                UpdateValue::Number(1.5),
+                UpdateValue::Unknown,
+                UpdateValue::Number(f64::NAN),
+                UpdateValue::Counter(9007199254740993),
+                UpdateValue::Integer(-3),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            batch_update.to_command_string().unwrap(),
+            "UPDATE test_path.rrd 1609459200:1.5:U:U:9007199254740993:-3\n"
+        );
+
+        let batch_update = BatchUpdate::new(
+            RrdPath::new("test_path").unwrap(),
+            UpdateTimestamp::Seconds(1609459200),
+            vec![Some(1.0), None],
+        )
+        .unwrap();
+        assert_eq!(
+            batch_update.to_command_string().unwrap(),
+            "UPDATE test_path.rrd 1609459200:1:U\n"
+        );
+    }
+
+    #[test]
+    fn test_to_command_string_with_timestamps() {
+        let batch_update = BatchUpdate::new(
+            RrdPath::new("test_path").unwrap(),
+            UpdateTimestamp::Now,
+            vec![4.2],
+        )
+        .unwrap();
+        // Now is resolved by the server
+        assert_eq!(
+            batch_update.to_command_string().unwrap(),
+            "UPDATE test_path.rrd N:4.2\n"
+        );
+
+        // The client time is read when building the timestamp
+        let batch_update = BatchUpdate::new(
+            RrdPath::new("test_path").unwrap(),
+            UpdateTimestamp::client_now().unwrap(),
+            vec![4.2],
+        )
+        .unwrap();
+        let command = batch_update.to_command_string().unwrap();
+        assert!(command.starts_with("UPDATE test_path.rrd 1"));
+        assert!(command.ends_with(":4.2\n"));
+        assert!(!command.contains("N:"));
+
+        let batch_update = BatchUpdate::new_many(
+            RrdPath::new("test_path").unwrap(),
+            vec![
+                (
+                    UpdateTimestamp::Precise(Duration::from_millis(1700000000500)),
+                    vec![2.0],
+                ),
+                (
+                    UpdateTimestamp::Precise(Duration::from_millis(1700000000250)),
+                    vec![1.0],
+                ),
+                (UpdateTimestamp::Seconds(1700000001), vec![3.0]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            batch_update.to_command_string().unwrap(),
+            "UPDATE test_path.rrd 1700000000.25:1 1700000000.5:2 1700000001:3\n"
+        );
+    }
 }
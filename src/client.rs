@@ -1,12 +1,23 @@
-use crate::batch_update::BatchUpdate;
-use crate::consolidation_function::ConsolidationFunction;
+use crate::batch_update::{BatchUpdate, RejectedSample, DEFAULT_MAX_LINE_LENGTH};
 use crate::create::*;
 use crate::errors::RRDCachedClientError;
-use crate::fetch::FetchResponse;
+use crate::fetch::{BinaryColumnHeader, FetchResponse};
+use crate::fetch_request::{FetchConsistency, FetchRequest};
+use crate::info::RrdInfo;
+use crate::list::{glob_list_arguments, ListEntries, ListEntry};
 use crate::parsers::*;
-use crate::sanitisation::check_rrd_path;
-use std::collections::HashMap;
+use crate::pending::PendingSample;
+use crate::pipeline::{
+    join_fetch_responses, read_responses, Pipeline, PipelineCommand, PipelineResponse,
+};
+use crate::rrd_path::RrdPath;
+use crate::sanitisation::check_rrd_directory;
+use crate::stats::ServerStats;
+use crate::update_timestamp::UpdateTimestamp;
+use crate::update_value::UpdateValue;
+use std::ops::Range;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::net::UnixStream;
 use tokio::{io::BufReader, net::TcpStream};
@@ -15,6 +26,9 @@ use tokio::{io::BufReader, net::TcpStream};
 #[derive(Debug)]
 pub struct RRDCachedClient<T = TcpStream> {
     stream: BufReader<T>,
+    max_line_length: usize,
+    /// Lines of the last response that were not read yet
+    unread_lines: usize,
 }
 
 impl RRDCachedClient<TcpStream> {
@@ -22,7 +36,11 @@ impl RRDCachedClient<TcpStream> {
     pub async fn connect_tcp(addr: &str) -> Result<Self, RRDCachedClientError> {
         let stream = TcpStream::connect(addr).await?;
         let stream = BufReader::new(stream);
-        Ok(Self { stream })
+        Ok(Self {
+            stream,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            unread_lines: 0,
+        })
     }
 }
 
@@ -31,7 +49,11 @@ impl RRDCachedClient<UnixStream> {
     pub async fn connect_unix(addr: &str) -> Result<Self, RRDCachedClientError> {
         let stream = UnixStream::connect(addr).await?;
         let stream = BufReader::new(stream);
-        Ok(Self { stream })
+        Ok(Self {
+            stream,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            unread_lines: 0,
+        })
     }
 }
 
@@ -39,6 +61,14 @@ impl<T> RRDCachedClient<T>
 where
     T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
 {
+    /// Set the maximum length of the UPDATE lines sent to the server.
+    ///
+    /// Updates with many samples are split in several lines.
+    /// Defaults to [DEFAULT_MAX_LINE_LENGTH], `usize::MAX` never splits them.
+    pub fn set_max_line_length(&mut self, max_line_length: usize) {
+        self.max_line_length = max_line_length;
+    }
+
     fn assert_response_code(&self, code: i64, message: &str) -> Result<(), RRDCachedClientError> {
         if code < 0 {
             Err(RRDCachedClientError::UnexpectedResponse(
@@ -64,12 +94,39 @@ where
         Ok(lines)
     }
 
+    /// Read the next line of a response read one line at a time,
+    /// or `None` if all its lines have been read.
+    pub(crate) async fn read_unread_line(
+        &mut self,
+    ) -> Option<Result<String, RRDCachedClientError>> {
+        if self.unread_lines == 0 {
+            return None;
+        }
+        self.unread_lines -= 1;
+        Some(self.read_line().await)
+    }
+
+    /// Discard the rest of a response that was not read entirely.
+    async fn discard_unread_lines(&mut self) -> Result<(), RRDCachedClientError> {
+        while self.unread_lines > 0 {
+            self.unread_lines -= 1;
+            self.read_line().await?;
+        }
+        Ok(())
+    }
+
+    async fn write_command(&mut self, command: &str) -> Result<(), RRDCachedClientError> {
+        self.discard_unread_lines().await?;
+        self.stream.write_all(command.as_bytes()).await?;
+        Ok(())
+    }
+
     async fn send_command(
         &mut self,
         command: &str,
     ) -> Result<(usize, String), RRDCachedClientError> {
         // Send command
-        self.stream.write_all(command.as_bytes()).await?;
+        self.write_command(command).await?;
         // Read response
         let response_line = self.read_line().await?;
         let (code, message) = parse_response_line(&response_line)?;
@@ -111,7 +168,11 @@ where
     }
 
     /// Create a new RRD
+    ///
+    /// Only what rrdtool requires is checked, so the arguments of an existing RRD
+    /// can be reused, see [CreateArguments::validate] for the layout checks.
     pub async fn create(&mut self, arguments: CreateArguments) -> Result<(), RRDCachedClientError> {
+        arguments.validate_structure()?;
         let arguments_str = arguments.to_str();
         let mut command = String::with_capacity(7 + arguments_str.len());
         command.push_str("CREATE ");
@@ -128,12 +189,12 @@ where
     }
 
     /// Flush a RRD
-    pub async fn flush(&mut self, path: &str) -> Result<(), RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(6 + path.len() + 5);
+    pub async fn flush(&mut self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(6 + path.len() + 1);
         command.push_str("FLUSH ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let _ = self.send_command(&command).await?;
         Ok(())
     }
@@ -145,28 +206,31 @@ where
     }
 
     /// Pending updates
-    pub async fn pending(&mut self, path: &str) -> Result<Vec<String>, RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(7 + path.len() + 5);
+    pub async fn pending(
+        &mut self,
+        path: &RrdPath,
+    ) -> Result<Vec<PendingSample>, RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(8 + path.len() + 1);
         command.push_str("PENDING ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let (nb_lines, _) = self.send_command(&command).await?;
         if nb_lines > 0 {
             let lines = self.read_n_lines(nb_lines).await?;
-            Ok(lines)
+            PendingSample::from_lines(lines)
         } else {
             Ok(vec![])
         }
     }
 
     /// Forget pending updates
-    pub async fn forget(&mut self, path: &str) -> Result<(), RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(7 + path.len() + 5);
+    pub async fn forget(&mut self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(7 + path.len() + 1);
         command.push_str("FORGET ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let _ = self.send_command(&command).await?;
         Ok(())
     }
@@ -186,33 +250,26 @@ where
     }
 
     /// Get the server stats
-    pub async fn stats(&mut self) -> Result<HashMap<String, i64>, RRDCachedClientError> {
+    pub async fn stats(&mut self) -> Result<ServerStats, RRDCachedClientError> {
         let (nb_lines, _message) = self.send_command("STATS\n").await?;
         let lines = self.read_n_lines(nb_lines).await?;
-        let parsed_lines = lines
-            .iter()
-            .map(|line| {
-                let (name, value) = parse_stats_line(line)?;
-                Ok((name.to_string(), value))
-            })
-            .collect::<Result<HashMap<String, i64>, RRDCachedClientError>>()?;
-        Ok(parsed_lines)
+        ServerStats::from_lines(lines)
     }
 
     /// Get the first CDP (whatever that is)
     pub async fn first(
         &mut self,
-        path: &str,
+        path: &RrdPath,
         round_robin_archive: Option<usize>,
     ) -> Result<usize, RRDCachedClientError> {
-        check_rrd_path(path)?;
+        let path = path.as_str();
         let round_robin_archive = round_robin_archive.unwrap_or(0);
         let rranum_str = round_robin_archive.to_string();
 
-        let mut command = String::with_capacity(6 + path.len() + 5 + rranum_str.len() + 1);
+        let mut command = String::with_capacity(6 + path.len() + 1 + rranum_str.len() + 1);
         command.push_str("FIRST ");
         command.push_str(path);
-        command.push_str(".rrd ");
+        command.push(' ');
         command.push_str(&rranum_str);
         command.push('\n');
         let (_, message) = self.send_command(&command).await?;
@@ -221,68 +278,114 @@ where
     }
 
     /// Retrieve the last update timestamp
-    pub async fn last(&mut self, path: &str) -> Result<usize, RRDCachedClientError> {
-        check_rrd_path(path)?;
-
-        let mut command = String::with_capacity(5 + path.len() + 5);
+    pub async fn last(&mut self, path: &RrdPath) -> Result<usize, RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(5 + path.len() + 1);
         command.push_str("LAST ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let (_, message) = self.send_command(&command).await?;
         let timestamp = parse_timestamp(&message)?;
         Ok(timestamp)
     }
 
     /// Retreive information about a RRD
-    pub async fn info(&mut self, path: &str) -> Result<Vec<String>, RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(5 + path.len() + 5);
+    pub async fn info(&mut self, path: &RrdPath) -> Result<RrdInfo, RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(5 + path.len() + 1);
         command.push_str("INFO ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let (nb_lines, _message) = self.send_command(&command).await?;
         let lines = self.read_n_lines(nb_lines).await?;
-        Ok(lines)
+        RrdInfo::from_lines(lines)
     }
 
-    /// List RRDs
+    /// List the RRDs and directories of a directory.
+    ///
+    /// The directory is relative to the base directory of RRDCached,
+    /// which is listed when it's `None`.
+    ///
+    /// The entries that can't be parsed are reported one by one,
+    /// without failing the whole listing.
     pub async fn list(
         &mut self,
         recursive: bool,
-        path: Option<&str>,
-    ) -> Result<Vec<String>, RRDCachedClientError> {
-        let path = path.unwrap_or("/");
+        directory: Option<&str>,
+    ) -> Result<Vec<Result<ListEntry, RRDCachedClientError>>, RRDCachedClientError> {
+        self.list_entries(recursive, directory)
+            .await?
+            .read_all()
+            .await
+    }
+
+    /// List the RRDs and directories of a directory, reading
+    /// the entries one by one from the server.
+    ///
+    /// Prefer this over [RRDCachedClient::list] for huge recursive listings.
+    pub async fn list_entries(
+        &mut self,
+        recursive: bool,
+        directory: Option<&str>,
+    ) -> Result<ListEntries<'_, T>, RRDCachedClientError> {
+        let directory = directory.unwrap_or("").trim_matches('/');
+        check_rrd_directory(directory)?;
+
         let mut command =
-            String::with_capacity(5 + path.len() + 1 + (if recursive { 10 } else { 0 }));
+            String::with_capacity(5 + (if recursive { 10 } else { 0 }) + 1 + directory.len() + 1);
         command.push_str("LIST ");
         if recursive {
             command.push_str("RECURSIVE ");
         }
-        command.push_str(path);
+        command.push('/');
+        command.push_str(directory);
         command.push('\n');
         let (nb_lines, _message) = self.send_command(&command).await?;
-        let lines = self.read_n_lines(nb_lines).await?;
-        Ok(lines)
+        self.unread_lines = nb_lines;
+        Ok(ListEntries::new(self, directory.to_string()))
+    }
+
+    /// List the RRDs and directories matching a glob pattern, like `hosts/*/cpu*`.
+    ///
+    /// Only the directory before the first wildcard is listed,
+    /// and the entries are filtered by the client, see [ListEntry::matches].
+    /// The entries that can't be parsed are reported one by one, as for
+    /// [RRDCachedClient::list].
+    pub async fn list_glob(
+        &mut self,
+        pattern: &str,
+    ) -> Result<Vec<Result<ListEntry, RRDCachedClientError>>, RRDCachedClientError> {
+        let (directory, recursive) = glob_list_arguments(pattern);
+        let mut entries = self
+            .list_entries(recursive, Some(directory))
+            .await?
+            .read_all()
+            .await?;
+        entries.retain(|entry| match entry {
+            Ok(entry) => entry.matches(pattern),
+            Err(_) => true,
+        });
+        Ok(entries)
     }
 
     /// Suspend a RRD
-    pub async fn suspend(&mut self, path: &str) -> Result<(), RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(8 + path.len() + 5);
+    pub async fn suspend(&mut self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(8 + path.len() + 1);
         command.push_str("SUSPEND ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let _ = self.send_command(&command).await?;
         Ok(())
     }
 
     /// Resume a RRD
-    pub async fn resume(&mut self, path: &str) -> Result<(), RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let mut command = String::with_capacity(7 + path.len() + 5);
+    pub async fn resume(&mut self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        let path = path.as_str();
+        let mut command = String::with_capacity(7 + path.len() + 1);
         command.push_str("RESUME ");
         command.push_str(path);
-        command.push_str(".rrd\n");
+        command.push('\n');
         let _ = self.send_command(&command).await?;
         Ok(())
     }
@@ -302,20 +405,24 @@ where
     /// Close the connection to the server
     pub async fn quit(&mut self) -> Result<(), RRDCachedClientError> {
         // Send directly without checking the response
-        self.stream.write_all("QUIT\n".as_bytes()).await?;
+        self.write_command("QUIT\n").await?;
         Ok(())
     }
 
     /// Update a RRD with a list of values at a specific timestamp
     ///
     /// The order is important as it must match the order of the data sources in the RRD
-    pub async fn update(
+    ///
+    /// Values can be `f64`, `Option<f64>` for unknown values, or any [UpdateValue].
+    ///
+    /// Use [UpdateTimestamp::Now] to let the server timestamp the update.
+    pub async fn update<V: Into<UpdateValue>>(
         &mut self,
-        path: &str,
-        timestamp: Option<usize>,
-        data: Vec<f64>,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: Vec<V>,
     ) -> Result<(), RRDCachedClientError> {
-        let command = BatchUpdate::new(path, timestamp, data)?;
+        let command = BatchUpdate::new(path.clone(), timestamp, data)?;
         let command_str = command.to_command_string()?;
         let _ = self.send_command(&command_str).await?;
         Ok(())
@@ -324,26 +431,113 @@ where
     /// Update a RRD with a single value at a specific timestamp.
     ///
     /// Convenient helper when a RRD contains only one data source.
-    pub async fn update_one(
+    pub async fn update_one<V: Into<UpdateValue>>(
         &mut self,
-        path: &str,
-        timestamp: Option<usize>,
-        data: f64,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: V,
     ) -> Result<(), RRDCachedClientError> {
         self.update(path, timestamp, vec![data]).await
     }
 
+    /// Update a RRD with many samples, sending as few UPDATE commands as possible.
+    ///
+    /// The samples are sorted by timestamp and packed in lines of at most
+    /// the maximum line length, see [RRDCachedClient::set_max_line_length].
+    ///
+    /// Returns the samples rejected by the server, for example because
+    /// they are older than the last update of the RRD.
+    ///
+    /// If a line fails, for example because the RRD was removed, the
+    /// error is [RRDCachedClientError::PartialUpdate] with the samples
+    /// rejected so far and the first sample that was not applied.
+    pub async fn update_many<S: Into<UpdateTimestamp>, V: Into<UpdateValue>>(
+        &mut self,
+        path: &RrdPath,
+        samples: Vec<(S, Vec<V>)>,
+    ) -> Result<Vec<RejectedSample>, RRDCachedClientError> {
+        let update = BatchUpdate::new_many(path.clone(), samples)?;
+        let mut rejected = Vec::new();
+        for (command, range) in update.to_command_lines(self.max_line_length)? {
+            let first_timestamp = update.samples()[range.start].0;
+            if let Err(error) = self
+                .update_line(&update, &command, range, &mut rejected)
+                .await
+            {
+                return Err(RRDCachedClientError::PartialUpdate(
+                    rejected,
+                    first_timestamp,
+                    Box::new(error),
+                ));
+            }
+        }
+        Ok(rejected)
+    }
+
+    /// Send a line of [RRDCachedClient::update_many], collecting its rejected samples.
+    async fn update_line(
+        &mut self,
+        update: &BatchUpdate,
+        command: &str,
+        range: Range<usize>,
+        rejected: &mut Vec<RejectedSample>,
+    ) -> Result<(), RRDCachedClientError> {
+        self.write_command(command).await?;
+        let response_line = self.read_line().await?;
+        let (code, message) = parse_response_line(&response_line)?;
+
+        // The server refuses the whole line if no sample is valid
+        if code < 0 {
+            if message != "No values updated." {
+                return Err(RRDCachedClientError::UnexpectedResponse(
+                    code,
+                    message.to_string(),
+                ));
+            }
+            rejected.extend(
+                update.samples()[range]
+                    .iter()
+                    .map(|(timestamp, _)| RejectedSample {
+                        timestamp: Some(*timestamp),
+                        reason: message.to_string(),
+                    }),
+            );
+            return Ok(());
+        }
+
+        // Otherwise it lists the rejected samples line by line,
+        // keeping the lines it doesn't recognise as they are
+        let lines = self.read_n_lines(code as usize).await?;
+        for line in lines {
+            rejected.push(match parse_rejected_update_line(&line) {
+                Ok((timestamp, reason)) => RejectedSample {
+                    timestamp: Some(UpdateTimestamp::from_secs_f64(timestamp)),
+                    reason: reason.to_string(),
+                },
+                Err(_) => RejectedSample {
+                    timestamp: None,
+                    reason: line.trim_end_matches('\n').to_string(),
+                },
+            });
+        }
+        Ok(())
+    }
+
     /// Batch updates.
     ///
     /// RRDCached presents this as a more efficient way to update multiple RRDs at once.
     /// You may want to sort the updates by timestamp ascending as RDDtool will
     /// reject updates of older timestamps.
+    ///
+    /// Updates with many samples may be split in several commands,
+    /// see [RRDCachedClient::set_max_line_length].
     pub async fn batch(&mut self, commands: Vec<BatchUpdate>) -> Result<(), RRDCachedClientError> {
         let _ = self.send_command("BATCH\n").await?;
         for command in commands {
-            let command_str = command.to_command_string()?;
-            // write the command directly
-            self.stream.write_all(command_str.as_bytes()).await?;
+            for (command_str, _) in command.to_command_lines(self.max_line_length)? {
+                // write the command directly
+                self.write_command(&command_str).await?;
+            }
         }
         // Send a dot to end the batch
         let (nb_lines, message) = self.send_command(".\n").await?;
@@ -358,76 +552,37 @@ where
         Ok(())
     }
 
+    /// Flush the RRD of a FETCH request if its consistency requires it.
+    async fn make_consistent(
+        &mut self,
+        request: &FetchRequest,
+    ) -> Result<(), RRDCachedClientError> {
+        match request.consistency {
+            FetchConsistency::Cached => Ok(()),
+            FetchConsistency::Flush => self.flush(&request.path).await,
+            FetchConsistency::FlushIfPending => {
+                if self.pending(&request.path).await?.is_empty() {
+                    Ok(())
+                } else {
+                    self.flush(&request.path).await
+                }
+            }
+        }
+    }
+
     /// Fetch the content of a Round Robin Database (RRD)
     ///
     /// Note that we use the Ascii protocol, as the binary protocol is not documented
     /// and it's unsure whether it's consitent between versions of RRDCached or
-    /// system architectures.
-    ///
+    /// system architectures. See [Self::fetch_bin] to opt in.
     ///
+    /// See [FetchConsistency] to include the updates still pending in the cache.
     pub async fn fetch(
         &mut self,
-        path: &str,
-        consolidation_function: ConsolidationFunction,
-        start: Option<i64>,
-        end: Option<i64>,
-        columns: Option<Vec<String>>,
+        request: FetchRequest,
     ) -> Result<FetchResponse, RRDCachedClientError> {
-        check_rrd_path(path)?;
-        let consolidation_function_str = consolidation_function.to_str();
-        // FETCH path.rrd CF [--start start] [--end end] [--columns columns]
-        let mut capacity = 6 + path.len() + 5 + consolidation_function_str.len() + 1;
-        let mut start_str: Option<String> = None;
-        let mut end_str: Option<String> = None;
-        let mut columns_str: Option<String> = None;
-        match start {
-            Some(start) => {
-                let start_string = start.to_string();
-                capacity += 1 + start_string.len();
-                start_str = Some(start_string);
-
-                if let Some(end) = end {
-                    let end_string = end.to_string();
-                    capacity += 1 + end_string.len();
-                    end_str = Some(end_string);
-                    if let Some(columns) = columns {
-                        let columns_string = columns.join(" ");
-                        capacity += 1 + columns_string.len();
-                        columns_str = Some(columns_string);
-                    }
-                } else if columns.is_some() {
-                    return Err(RRDCachedClientError::InvalidFetch(
-                        "end must be specified".to_string(),
-                    ));
-                }
-            }
-            None => {
-                if end.is_some() || columns.is_some() {
-                    return Err(RRDCachedClientError::InvalidFetch(
-                        "start must be specified".to_string(),
-                    ));
-                }
-            }
-        }
-        let mut command = String::with_capacity(capacity);
-        command.push_str("FETCH ");
-        command.push_str(path);
-        command.push_str(".rrd ");
-        command.push_str(consolidation_function_str);
-        if let Some(start_str) = start_str {
-            command.push(' ');
-            command.push_str(&start_str);
-            if let Some(end_str) = end_str {
-                command.push(' ');
-                command.push_str(&end_str);
-                if let Some(columns_str) = columns_str {
-                    command.push(' ');
-                    command.push_str(&columns_str);
-                }
-            }
-        }
-        command.push('\n');
-        assert!(command.len() == capacity);
+        let command = request.to_command()?;
+        self.make_consistent(&request).await?;
 
         let (nb_lines, _message) = self.send_command(&command).await?;
         let lines = self.read_n_lines(nb_lines).await?;
@@ -436,14 +591,131 @@ where
 
         Ok(response)
     }
+
+    /// Fetch several Round Robin Databases (RRDs) and join them into one
+    /// table, see [FetchResponse::join].
+    ///
+    /// The commands are pipelined, see [Pipeline::fetch]: they are sent
+    /// back-to-back, with the flushes required by their consistency,
+    /// see [Self::execute]. The data sources are prefixed by the name of their RRD,
+    /// like `cpu:user`, so the requests should target distinct RRDs.
+    pub async fn fetch_many(
+        &mut self,
+        requests: Vec<FetchRequest>,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        let responses = self.execute(Pipeline::fetch_many(&requests)).await?;
+        join_fetch_responses(&requests, responses)
+    }
+
+    /// Execute the commands of a pipeline, writing them back-to-back
+    /// without waiting for their responses, so they cost a single round-trip.
+    ///
+    /// The responses are read while the commands are written, as the server
+    /// stops reading the commands when its responses are not read.
+    ///
+    /// Returns the response of each command, in order. A command
+    /// refused by the server doesn't prevent the next ones.
+    pub async fn execute(
+        &mut self,
+        pipeline: Pipeline,
+    ) -> Result<Vec<Result<PipelineResponse, RRDCachedClientError>>, RRDCachedClientError> {
+        let commands = pipeline.into_commands()?;
+        let commands_str: String = commands
+            .iter()
+            .map(|(command, _)| command.as_str())
+            .collect();
+        let commands: Vec<PipelineCommand> =
+            commands.into_iter().map(|(_, command)| command).collect();
+
+        self.discard_unread_lines().await?;
+        let (reader, mut writer) = tokio::io::split(&mut self.stream);
+        let mut reader = BufReader::new(reader);
+        let write = async {
+            writer.write_all(commands_str.as_bytes()).await?;
+            Ok(())
+        };
+        let ((), responses) = tokio::try_join!(write, read_responses(&mut reader, &commands))?;
+        Ok(responses)
+    }
+
+    /// Fetch the content of a Round Robin Database (RRD) with the binary
+    /// protocol (FETCHBIN), avoiding the formatting of the values as text.
+    ///
+    /// The byte order and the size of the values are read from the header
+    /// of each column. It requires a version of RRDCached supporting FETCHBIN.
+    pub async fn fetch_bin(
+        &mut self,
+        request: FetchRequest,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        request.validate()?;
+        let arguments_str = request.to_str();
+        let mut command = String::with_capacity(9 + arguments_str.len() + 1);
+        command.push_str("FETCHBIN ");
+        command.push_str(&arguments_str);
+        command.push('\n');
+        self.make_consistent(&request).await?;
+
+        let (nb_lines, _message) = self.send_command(&command).await?;
+        let mut header_lines = Vec::new();
+        let mut columns = Vec::new();
+        let mut row_count = None;
+        let mut remaining_lines = nb_lines;
+        while remaining_lines > 0 {
+            let line = self.read_line().await?;
+            remaining_lines -= 1;
+            if !line.starts_with("DSName-") {
+                header_lines.push(line);
+                continue;
+            }
+            let header = BinaryColumnHeader::from_line(&line)?;
+            // The header lines come before the columns
+            let row_count = match row_count {
+                Some(row_count) => row_count,
+                None => {
+                    *row_count.insert(FetchResponse::from_lines(header_lines.clone())?.row_count())
+                }
+            };
+            header.check_count(row_count)?;
+            let mut payload = vec![0; header.payload_len()?];
+            self.stream.read_exact(&mut payload).await?;
+            // The values are followed by a newline, counted as a line
+            let end_line = self.read_line().await?;
+            remaining_lines = remaining_lines.saturating_sub(1);
+            if end_line != "\n" {
+                return Err(RRDCachedClientError::InvalidFetch(format!(
+                    "expected a newline after the values of {}",
+                    header.name
+                )));
+            }
+            columns.push((header.name.clone(), header.decode(&payload)?));
+        }
+
+        FetchResponse::from_binary(header_lines, columns)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::consolidation_function::ConsolidationFunction;
     use crate::now::now_timestamp;
 
     use super::*;
     use serial_test::serial;
+    use std::time::Duration;
+    use tokio::io::DuplexStream;
+
+    /// A client reading canned responses from a fake server,
+    /// returned to keep the connection open and read the commands.
+    async fn fake_client(responses: &[u8]) -> (RRDCachedClient<DuplexStream>, DuplexStream) {
+        let (client, mut server) = tokio::io::duplex(64 * 1024);
+        server.write_all(responses).await.unwrap();
+        let client = RRDCachedClient {
+            stream: BufReader::new(client),
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            unread_lines: 0,
+        };
+        (client, server)
+    }
 
     #[tokio::test]
     async fn test_ping_tcp() {
@@ -476,25 +748,30 @@ mod tests {
         assert!(!lines.is_empty());
     }
 
-    async fn create_simple_rrd(client: &mut RRDCachedClient<TcpStream>, name: String) {
+    async fn create_simple_rrd(client: &mut RRDCachedClient<TcpStream>, path: &RrdPath) {
         client
             .create(CreateArguments {
-                path: name,
+                path: path.clone(),
                 data_sources: vec![CreateDataSource {
                     name: "ds1".to_string(),
                     minimum: None,
                     maximum: None,
                     heartbeat: 10,
                     serie_type: CreateDataSourceType::Gauge,
+                    expression: None,
                 }],
-                round_robin_archives: vec![CreateRoundRobinArchive {
+                round_robin_archives: vec![CreateArchive::Consolidated(CreateRoundRobinArchive {
                     consolidation_function: ConsolidationFunction::Average,
                     xfiles_factor: 0.5,
                     steps: 1,
                     rows: 100,
-                }],
+                    step: None,
+                })],
                 start_timestamp: 1609459200,
                 step_seconds: 1,
+                no_overwrite: false,
+                sources: vec![],
+                template: None,
             })
             .await
             .unwrap();
@@ -508,7 +785,7 @@ mod tests {
 
         client
             .create(CreateArguments {
-                path: "test-create".to_string(),
+                path: RrdPath::new("test-create").unwrap(),
                 data_sources: vec![
                     CreateDataSource {
                         name: "ds1".to_string(),
@@ -516,6 +793,7 @@ mod tests {
                         maximum: None,
                         heartbeat: 10,
                         serie_type: CreateDataSourceType::Gauge,
+                        expression: None,
                     },
                     CreateDataSource {
                         name: "ds2".to_string(),
@@ -523,24 +801,30 @@ mod tests {
                         maximum: Some(100.0),
                         heartbeat: 10,
                         serie_type: CreateDataSourceType::Gauge,
+                        expression: None,
                     },
                 ],
                 round_robin_archives: vec![
-                    CreateRoundRobinArchive {
+                    CreateArchive::Consolidated(CreateRoundRobinArchive {
                         consolidation_function: ConsolidationFunction::Average,
                         xfiles_factor: 0.5,
                         steps: 1,
                         rows: 10,
-                    },
-                    CreateRoundRobinArchive {
+                        step: None,
+                    }),
+                    CreateArchive::Consolidated(CreateRoundRobinArchive {
                         consolidation_function: ConsolidationFunction::Average,
                         xfiles_factor: 0.5,
                         steps: 10,
                         rows: 10,
-                    },
+                        step: None,
+                    }),
                 ],
                 start_timestamp: 1609459200,
                 step_seconds: 1,
+                no_overwrite: false,
+                sources: vec![],
+                template: None,
             })
             .await
             .unwrap();
@@ -552,9 +836,14 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-update".to_string()).await;
+        let path = RrdPath::new("test-update").unwrap();
 
-        client.update_one("test-update", None, 4.2).await.unwrap();
+        create_simple_rrd(&mut client, &path).await;
+
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
     }
 
     #[tokio::test]
@@ -573,10 +862,15 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-flush".to_string()).await;
+        let path = RrdPath::new("test-flush").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
 
-        client.update_one("test-flush", None, 4.2).await.unwrap();
-        client.flush("test-flush").await.unwrap();
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
+        client.flush(&path).await.unwrap();
     }
 
     #[tokio::test]
@@ -598,20 +892,27 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-pending".to_string()).await;
+        let path = RrdPath::new("test-pending").unwrap();
 
-        client.update_one("test-pending", None, 4.2).await.unwrap();
+        create_simple_rrd(&mut client, &path).await;
 
-        let lines = client.pending("test-pending").await.unwrap();
-        assert_eq!(lines.len(), 1);
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
+
+        let samples = client.pending(&path).await.unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].values, vec![Some(4.2)]);
+        assert!(samples[0].timestamp > UpdateTimestamp::Seconds(1700000000));
 
         // Flush
-        client.flush("test-pending").await.unwrap();
+        client.flush(&path).await.unwrap();
         // Wait 0.1s
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
-        let lines = client.pending("test-pending").await.unwrap();
-        assert!(lines.is_empty());
+        let samples = client.pending(&path).await.unwrap();
+        assert!(samples.is_empty());
     }
 
     #[tokio::test]
@@ -620,10 +921,15 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-forget".to_string()).await;
+        let path = RrdPath::new("test-forget").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
 
-        client.update_one("test-forget", None, 4.2).await.unwrap();
-        client.forget("test-forget").await.unwrap();
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
+        client.forget(&path).await.unwrap();
     }
 
     #[tokio::test]
@@ -645,7 +951,17 @@ mod tests {
             .unwrap();
 
         let stats = client.stats().await.unwrap();
-        assert!(!stats.is_empty());
+
+        let path = RrdPath::new("test-stats").unwrap();
+        create_simple_rrd(&mut client, &path).await;
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
+
+        let new_stats = client.stats().await.unwrap();
+        let delta = new_stats.delta_since(&stats);
+        assert!(delta.updates_received >= 1);
     }
 
     #[tokio::test]
@@ -654,15 +970,19 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-first".to_string()).await;
+        let path = RrdPath::new("test-first").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
 
         // This can fail for subsequent runs
-        let _ = client.update_one("test-first", Some(1612345678), 4.2).await;
+        let _ = client
+            .update_one(&path, UpdateTimestamp::Seconds(1612345678), 4.2)
+            .await;
 
-        let timestamp = client.first("test-first", None).await.unwrap();
+        let timestamp = client.first(&path, None).await.unwrap();
         assert_eq!(timestamp, 1609459101); // I'm guessing some alignment is happening
 
-        let timestamp = client.last("test-first").await.unwrap();
+        let timestamp = client.last(&path).await.unwrap();
         assert_eq!(timestamp, 1612345678);
     }
 
@@ -672,9 +992,41 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-info".to_string()).await;
-        let lines = client.info("test-info").await.unwrap();
-        assert!(!lines.is_empty());
+        let path = RrdPath::new("test-info").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
+        let info = client.info(&path).await.unwrap();
+        assert_eq!(info.step, 1);
+        assert_eq!(info.data_sources.len(), 1);
+        assert_eq!(info.data_sources[0].name, "ds1");
+        assert_eq!(info.data_sources[0].serie_type, CreateDataSourceType::Gauge);
+        assert_eq!(info.data_sources[0].heartbeat, 10);
+        assert_eq!(info.round_robin_archives.len(), 1);
+        assert_eq!(
+            info.round_robin_archives[0].consolidation_function,
+            ConsolidationFunction::Average
+        );
+        assert_eq!(info.round_robin_archives[0].rows, 100);
+        assert_eq!(info.round_robin_archives[0].pdp_per_row, 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_from_info() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-create-from-info").unwrap();
+        let path_copy = RrdPath::new("test-create-from-info-copy").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
+        let info = client.info(&path).await.unwrap();
+        let arguments = CreateArguments::from_info(path_copy.clone(), &info).unwrap();
+        client.create(arguments.clone()).await.unwrap();
+
+        let info_copy = client.info(&path_copy).await.unwrap();
+        let arguments_copy = CreateArguments::from_info(path_copy, &info_copy).unwrap();
+        assert!(arguments.same_schema(&arguments_copy));
     }
 
     #[tokio::test]
@@ -683,8 +1035,62 @@ mod tests {
             .await
             .unwrap();
 
-        let lines = client.list(true, None).await.unwrap();
-        assert!(!lines.is_empty());
+        let entries = client.list(true, None).await.unwrap();
+        assert!(!entries.is_empty());
+
+        let path = RrdPath::new("nested/test-list").unwrap();
+        create_simple_rrd(&mut client, &path).await;
+
+        let entries = client.list(false, Some("nested")).await.unwrap();
+        assert!(entries
+            .iter()
+            .any(|entry| matches!(entry, Ok(ListEntry::File(p)) if *p == path)));
+
+        let entries = client.list(false, None).await.unwrap();
+        assert!(entries
+            .iter()
+            .any(|entry| matches!(entry, Ok(ListEntry::Directory(d)) if d == "nested")));
+
+        let entries = client.list_glob("nested/test-l*").await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(&entries[0], Ok(ListEntry::File(p)) if *p == path));
+
+        // Entries that are not read are discarded
+        let mut entries = client.list_entries(true, None).await.unwrap();
+        assert!(entries.next().await.is_some());
+        client.stats().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_mixed_entries() {
+        let (mut client, mut server) = fake_client(
+            b"5 Listing\ncpu.rrd\nserver-1/\ncpu.rrd.journal\nwith space.rrd\nmemory.rrd\n0 PONG\n",
+        )
+        .await;
+
+        let entries = client.list(false, Some("hosts")).await.unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(
+            matches!(&entries[0], Ok(ListEntry::File(path)) if path.as_str() == "hosts/cpu.rrd")
+        );
+        assert!(matches!(&entries[1], Ok(ListEntry::Directory(path)) if path == "hosts/server-1"));
+        assert!(
+            matches!(&entries[2], Ok(ListEntry::Other(path)) if path == "hosts/cpu.rrd.journal")
+        );
+        assert!(matches!(
+            &entries[3],
+            Err(RRDCachedClientError::InvalidRrdPath(_))
+        ));
+        assert!(
+            matches!(&entries[4], Ok(ListEntry::File(path)) if path.as_str() == "hosts/memory.rrd")
+        );
+
+        // The whole response was read
+        client.ping().await.unwrap();
+        drop(client);
+        let mut commands = String::new();
+        server.read_to_string(&mut commands).await.unwrap();
+        assert_eq!(commands, "LIST /hosts\nPING\n");
     }
 
     #[serial]
@@ -694,14 +1100,19 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-suspend".to_string()).await;
+        let path = RrdPath::new("test-suspend").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
 
         // insert some data and flush
-        client.update_one("test-suspend", None, 4.2).await.unwrap();
-        client.flush("test-suspend").await.unwrap();
+        client
+            .update_one(&path, UpdateTimestamp::Now, 4.2)
+            .await
+            .unwrap();
+        client.flush(&path).await.unwrap();
 
-        client.suspend("test-suspend").await.unwrap();
-        client.resume("test-suspend").await.unwrap();
+        client.suspend(&path).await.unwrap();
+        client.resume(&path).await.unwrap();
     }
 
     #[serial]
@@ -734,25 +1145,170 @@ mod tests {
             .await
             .unwrap();
 
-        create_simple_rrd(&mut client, "test-batch-1".to_string()).await;
-        create_simple_rrd(&mut client, "test-batch-2".to_string()).await;
+        let path_1 = RrdPath::new("test-batch-1").unwrap();
+        let path_2 = RrdPath::new("test-batch-2").unwrap();
+
+        create_simple_rrd(&mut client, &path_1).await;
+        create_simple_rrd(&mut client, &path_2).await;
 
+        let now = UpdateTimestamp::Seconds(now_timestamp().unwrap());
         let commands = vec![
-            BatchUpdate::new("test-batch-1", None, vec![1.0]).unwrap(),
-            BatchUpdate::new("test-batch-2", None, vec![2.0]).unwrap(),
+            BatchUpdate::new(path_1.clone(), now, vec![1.0]).unwrap(),
+            BatchUpdate::new(path_2.clone(), now, vec![2.0]).unwrap(),
         ];
         client.batch(commands).await.unwrap();
 
         // Let's do the errors, it will fail
         // because the time is the same
         let commands = vec![
-            BatchUpdate::new("test-batch-1", None, vec![3.0]).unwrap(),
-            BatchUpdate::new("test-batch-2", None, vec![4.0]).unwrap(),
+            BatchUpdate::new(path_1.clone(), now, vec![3.0]).unwrap(),
+            BatchUpdate::new(path_2.clone(), now, vec![4.0]).unwrap(),
         ];
         let result = client.batch(commands).await;
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_update_unknown() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-update-unknown").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
+
+        let last = client.last(&path).await.unwrap();
+        client
+            .update_one(
+                &path,
+                UpdateTimestamp::Seconds(last + 1),
+                UpdateValue::Unknown,
+            )
+            .await
+            .unwrap();
+        client
+            .update_one(&path, UpdateTimestamp::Seconds(last + 2), f64::NAN)
+            .await
+            .unwrap();
+        client
+            .update(&path, UpdateTimestamp::Seconds(last + 3), vec![None::<f64>])
+            .await
+            .unwrap();
+        assert_eq!(client.last(&path).await.unwrap(), last + 3);
+    }
+
+    #[tokio::test]
+    async fn test_update_timestamps() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-update-timestamps").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
+
+        let last = client.last(&path).await.unwrap();
+        let base = std::time::Duration::from_secs(last as u64 + 1);
+        client
+            .update_one(
+                &path,
+                UpdateTimestamp::Precise(base + std::time::Duration::from_millis(250)),
+                1.0,
+            )
+            .await
+            .unwrap();
+        client
+            .update_one(
+                &path,
+                UpdateTimestamp::Precise(base + std::time::Duration::from_millis(500)),
+                2.0,
+            )
+            .await
+            .unwrap();
+        assert_eq!(client.last(&path).await.unwrap(), last + 1);
+
+        client
+            .update_one(&path, UpdateTimestamp::Now, 3.0)
+            .await
+            .unwrap();
+        assert!(client.last(&path).await.unwrap() > last + 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_many() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-update-many").unwrap();
+
+        create_simple_rrd(&mut client, &path).await;
+
+        // Small lines to force splitting
+        client.set_max_line_length(64);
+
+        let last = client.last(&path).await.unwrap();
+        let samples: Vec<(usize, Vec<f64>)> =
+            (1..=50).map(|i| (last + i, vec![i as f64])).collect();
+        let rejected = client.update_many(&path, samples.clone()).await.unwrap();
+        assert!(rejected.is_empty());
+
+        // Everything is rejected the second time
+        let rejected = client.update_many(&path, samples).await.unwrap();
+        assert_eq!(rejected.len(), 50);
+        assert_eq!(
+            rejected[0].timestamp,
+            Some(UpdateTimestamp::Seconds(last + 1))
+        );
+
+        // Half of them are rejected
+        let samples = (26..=75).map(|i| (last + i, vec![i as f64])).collect();
+        let rejected = client.update_many(&path, samples).await.unwrap();
+        assert_eq!(rejected.len(), 25);
+        assert!(rejected
+            .iter()
+            .all(|r| r.timestamp <= Some(UpdateTimestamp::Seconds(last + 50))));
+
+        // Also in batch mode
+        let samples = (76..=100).map(|i| (last + i, vec![i as f64])).collect();
+        let commands = vec![BatchUpdate::new_many(path.clone(), samples).unwrap()];
+        client.batch(commands).await.unwrap();
+        assert_eq!(client.last(&path).await.unwrap(), last + 100);
+    }
+
+    #[tokio::test]
+    async fn test_update_many_partial() {
+        let (mut client, _server) = fake_client(
+            b"1 errors\nunexpected rejection\n-1 No such file: /var/lib/rrdcached/test.rrd\n",
+        )
+        .await;
+        client.set_max_line_length(64);
+
+        let path = RrdPath::new("test").unwrap();
+        let samples: Vec<(usize, Vec<f64>)> =
+            (1..=10).map(|i| (1700000000 + i, vec![i as f64])).collect();
+        let error = client.update_many(&path, samples).await.unwrap_err();
+        let RRDCachedClientError::PartialUpdate(rejected, timestamp, error) = error else {
+            panic!("unexpected error {:?}", error);
+        };
+
+        // The unknown rejection is kept as it is
+        assert_eq!(
+            rejected,
+            vec![RejectedSample {
+                timestamp: None,
+                reason: "unexpected rejection".to_string(),
+            }]
+        );
+        // The second line failed
+        assert!(timestamp > UpdateTimestamp::Seconds(1700000001));
+        assert!(matches!(
+            *error,
+            RRDCachedClientError::UnexpectedResponse(-1, _)
+        ));
+    }
+
     #[serial]
     #[tokio::test]
     async fn test_fetch() {
@@ -760,9 +1316,11 @@ mod tests {
             .await
             .unwrap();
 
+        let path = RrdPath::new("test-fetch").unwrap();
+
         client
             .create(CreateArguments {
-                path: "test-fetch".to_string(),
+                path: path.clone(),
                 data_sources: vec![
                     CreateDataSource {
                         name: "ds1".to_string(),
@@ -770,6 +1328,7 @@ mod tests {
                         maximum: None,
                         heartbeat: 10,
                         serie_type: CreateDataSourceType::Gauge,
+                        expression: None,
                     },
                     CreateDataSource {
                         name: "ds2".to_string(),
@@ -777,36 +1336,36 @@ mod tests {
                         maximum: Some(100.0),
                         heartbeat: 10,
                         serie_type: CreateDataSourceType::Gauge,
+                        expression: None,
                     },
                 ],
                 round_robin_archives: vec![
-                    CreateRoundRobinArchive {
+                    CreateArchive::Consolidated(CreateRoundRobinArchive {
                         consolidation_function: ConsolidationFunction::Average,
                         xfiles_factor: 0.5,
                         steps: 1,
                         rows: 10,
-                    },
-                    CreateRoundRobinArchive {
+                        step: None,
+                    }),
+                    CreateArchive::Consolidated(CreateRoundRobinArchive {
                         consolidation_function: ConsolidationFunction::Average,
                         xfiles_factor: 0.5,
                         steps: 10,
                         rows: 10,
-                    },
+                        step: None,
+                    }),
                 ],
                 start_timestamp: 1609459200,
                 step_seconds: 1,
+                no_overwrite: false,
+                sources: vec![],
+                template: None,
             })
             .await
             .unwrap();
 
         let result = client
-            .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                None,
-                None,
-                None,
-            )
+            .fetch(FetchRequest::new(&path, ConsolidationFunction::Average))
             .await
             .unwrap();
 
@@ -818,64 +1377,507 @@ mod tests {
         assert_eq!(result.ds_names, vec!["ds1".to_string(), "ds2".to_string()]);
         assert!(!result.data.is_empty());
 
-        // Test the errors in parameters
+        // Columns without start and end
         let result = client
-            .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                None,
-                Some(1609459200),
-                None,
-            )
-            .await;
-        assert!(result.is_err());
+            .fetch(FetchRequest::new(&path, ConsolidationFunction::Average).column("ds2"))
+            .await
+            .unwrap();
+        assert_eq!(result.ds_count, 1);
 
+        let now_timestamp = now_timestamp().unwrap() as u64;
         let result = client
             .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                Some(1609459200),
-                None,
-                Some(vec!["ds1".to_string(), "ds2".to_string()]),
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .start(now_timestamp - 10)
+                    .end(now_timestamp)
+                    .column("not-found"),
             )
             .await;
         assert!(result.is_err());
 
-        let now_timestamp = now_timestamp().unwrap();
         let result = client
             .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                Some(now_timestamp as i64 - 10),
-                Some(now_timestamp as i64),
-                Some(vec!["not-found".to_string()]),
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .start_at(std::time::SystemTime::now() - Duration::from_secs(10))
+                    .end_at(std::time::SystemTime::now())
+                    .column("ds2"),
             )
-            .await;
+            .await
+            .unwrap();
+        assert_eq!(result.ds_count, 1);
 
-        assert!(result.is_err());
+        // Relative and AT-style times
         let result = client
             .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                Some(now_timestamp as i64 - 10),
-                Some(now_timestamp as i64),
-                Some(vec!["ds2".to_string()]),
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .last(Duration::from_secs(10))
+                    .end("now")
+                    .column("ds2"),
             )
             .await
             .unwrap();
         assert_eq!(result.ds_count, 1);
+        assert_eq!(result.end - result.start, 10);
+    }
 
-        // Relative timestamp
-        let result = client
+    #[tokio::test]
+    async fn test_fetch_bin_fake_server() {
+        // A double column in little endian, then a float column in big endian
+        let mut response = b"9 Success\nFlushVersion: 1\nStart: 1708800030\nEnd: 1708800060\nStep: 10\nDSCount: 2\n".to_vec();
+        response.extend_from_slice(b"DSName-ds1: BinaryData 3 8 LITTLEENDIAN\n");
+        for value in [1.5f64, f64::NAN, 1e300] {
+            response.extend_from_slice(&value.to_le_bytes());
+        }
+        response.extend_from_slice(b"\nDSName-ds2: BinaryData 3 4 BIGENDIAN\n");
+        // The payload may contain newline bytes
+        for value in [0.25f32, f32::from_bits(0x0a0a0a0a), -8.0] {
+            response.extend_from_slice(&value.to_be_bytes());
+        }
+        response.extend_from_slice(b"\n0 PONG\n");
+        let (mut client, mut server) = fake_client(&response).await;
+
+        let path = RrdPath::new("test").unwrap();
+        let request = FetchRequest::new(&path, ConsolidationFunction::Average)
+            .start(1708800030)
+            .end(1708800060);
+        let response = client.fetch_bin(request).await.unwrap();
+        assert_eq!(response.start, 1708800030);
+        assert_eq!(response.end, 1708800060);
+        assert_eq!(response.step, 10);
+        assert_eq!(response.ds_names, vec!["ds1", "ds2"]);
+        assert_eq!(
+            response.column("ds1").unwrap(),
+            vec![
+                (1708800040, Some(1.5)),
+                (1708800050, None),
+                (1708800060, Some(1e300))
+            ]
+        );
+        assert_eq!(
+            response.column("ds2").unwrap(),
+            vec![
+                (1708800040, Some(0.25)),
+                (1708800050, Some(f32::from_bits(0x0a0a0a0a) as f64)),
+                (1708800060, Some(-8.0))
+            ]
+        );
+
+        // The whole response was read
+        client.ping().await.unwrap();
+        drop(client);
+        let mut commands = String::new();
+        server.read_to_string(&mut commands).await.unwrap();
+        assert_eq!(
+            commands,
+            "FETCHBIN test.rrd AVERAGE 1708800030 1708800060\nPING\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_bin_fake_server_count() {
+        // More values than the rows between the start and the end
+        let response = format!(
+            "6 Success\nFlushVersion: 1\nStart: 1708800030\nEnd: 1708800060\nStep: 10\nDSCount: 1\nDSName-ds1: BinaryData {} 8 LITTLEENDIAN\n",
+            usize::MAX / 8
+        );
+        let (mut client, _server) = fake_client(response.as_bytes()).await;
+
+        let path = RrdPath::new("test").unwrap();
+        let request = FetchRequest::new(&path, ConsolidationFunction::Average)
+            .start(1708800030)
+            .end(1708800060);
+        assert!(matches!(
+            client.fetch_bin(request).await,
+            Err(RRDCachedClientError::InvalidFetch(message)) if message.contains("at most 3 values")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many_large_responses() {
+        // The commands and the responses don't fit in the buffers
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(server);
+            let mut lines = BufReader::new(reader).lines();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let response = if line.starts_with("FLUSH") {
+                    "0 Successfully flushed\n".to_string()
+                } else {
+                    let rows: String = (1..=100)
+                        .map(|i| format!("{}: {}\n", 1708800000 + i * 10, i))
+                        .collect();
+                    format!(
+                        "106 Success\nFlushVersion: 1\nStart: 1708800000\nEnd: 1708801000\nStep: 10\nDSCount: 1\nDSName: value\n{}",
+                        rows
+                    )
+                };
+                writer.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let mut client = RRDCachedClient {
+            stream: BufReader::new(client),
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            unread_lines: 0,
+        };
+
+        let paths: Vec<RrdPath> = (0..50)
+            .map(|i| RrdPath::new(&format!("test-{}", i)).unwrap())
+            .collect();
+        let requests = paths
+            .iter()
+            .map(|path| {
+                FetchRequest::new(path, ConsolidationFunction::Average)
+                    .start(1708800000)
+                    .end(1708801000)
+                    .consistency(FetchConsistency::FlushIfPending)
+            })
+            .collect();
+        let response = tokio::time::timeout(Duration::from_secs(5), client.fetch_many(requests))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.ds_names.len(), 50);
+        assert_eq!(response.data.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many_fake_server() {
+        let fetch_response = |ds_name: &str| {
+            format!(
+                "8 Success\nFlushVersion: 1\nStart: 1708800030\nEnd: 1708800060\nStep: 10\nDSCount: 1\nDSName: {}\n1708800040: 1\n1708800050: 2\n",
+                ds_name
+            )
+        };
+        let responses = format!(
+            "{}0 Successfully flushed\n{}",
+            fetch_response("user"),
+            fetch_response("used")
+        );
+        let (mut client, mut server) = fake_client(responses.as_bytes()).await;
+
+        let cpu = RrdPath::new("cpu").unwrap();
+        let memory = RrdPath::new("memory").unwrap();
+        let response = client
+            .fetch_many(vec![
+                FetchRequest::new(&cpu, ConsolidationFunction::Average)
+                    .start(1708800030)
+                    .end(1708800060),
+                FetchRequest::new(&memory, ConsolidationFunction::Average)
+                    .start(1708800030)
+                    .end(1708800060)
+                    .consistency(FetchConsistency::FlushIfPending),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(response.ds_names, vec!["cpu:user", "memory:used"]);
+
+        // The flush is sent in the pipeline, without waiting for PENDING
+        drop(client);
+        let mut commands = String::new();
+        server.read_to_string(&mut commands).await.unwrap();
+        assert_eq!(
+            commands,
+            "FETCH cpu.rrd AVERAGE 1708800030 1708800060\nFLUSH memory.rrd\nFETCH memory.rrd AVERAGE 1708800030 1708800060\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_bin() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-fetch-bin").unwrap();
+        let now = now_timestamp().unwrap() as u64;
+        client
+            .create(
+                CreateArguments::builder(path.name())
+                    .step_seconds(1)
+                    .start_timestamp(now - 100)
+                    .gauge("ds1", None, None)
+                    .gauge("ds2", None, None)
+                    .rra(CreateRoundRobinArchive {
+                        consolidation_function: ConsolidationFunction::Average,
+                        xfiles_factor: 0.5,
+                        steps: 1,
+                        rows: 100,
+                        step: None,
+                    })
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let samples = (1..=50)
+            .map(|i| (now as usize - 100 + i, vec![i as f64, (i * 2) as f64]))
+            .collect();
+        client
+            .batch(vec![BatchUpdate::new_many(path.clone(), samples).unwrap()])
+            .await
+            .unwrap();
+
+        // The binary and text protocols return the same values
+        let request = FetchRequest::new(&path, ConsolidationFunction::Average)
+            .start(now - 90)
+            .end(now - 10);
+        let text = client.fetch(request.clone()).await.unwrap();
+        let binary = client.fetch_bin(request.clone()).await.unwrap();
+        assert_eq!(binary.start, text.start);
+        assert_eq!(binary.end, text.end);
+        assert_eq!(binary.step, text.step);
+        assert_eq!(binary.ds_names, text.ds_names);
+        assert_eq!(binary.column("ds1"), text.column("ds1"));
+        assert_eq!(binary.column("ds2"), text.column("ds2"));
+
+        let binary = client.fetch_bin(request.column("ds2")).await.unwrap();
+        assert_eq!(binary.ds_names, vec!["ds2".to_string()]);
+
+        // The connection is still usable
+        client.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_downsample() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-downsample").unwrap();
+        let now = now_timestamp().unwrap() as u64;
+        let start = now - now % 10 - 200;
+        client
+            .create(
+                CreateArguments::builder(path.name())
+                    .step_seconds(1)
+                    .start_timestamp(start)
+                    .gauge("ds1", None, None)
+                    .rra(CreateRoundRobinArchive {
+                        consolidation_function: ConsolidationFunction::Average,
+                        xfiles_factor: 0.5,
+                        steps: 1,
+                        rows: 200,
+                        step: None,
+                    })
+                    .rra(CreateRoundRobinArchive {
+                        consolidation_function: ConsolidationFunction::Min,
+                        xfiles_factor: 0.5,
+                        steps: 10,
+                        rows: 20,
+                        step: None,
+                    })
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let samples = (1..=150)
+            .map(|i| (start as usize + i, vec![((i * 7) % 13) as f64]))
+            .collect();
+        client
+            .batch(vec![BatchUpdate::new_many(path.clone(), samples).unwrap()])
+            .await
+            .unwrap();
+
+        // Consolidating the finest archive gives the values of rrdtool
+        let fine = client
             .fetch(
-                "test-fetch",
-                ConsolidationFunction::Average,
-                Some(-10),
-                Some(0),
-                Some(vec!["ds2".to_string()]),
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .start(start)
+                    .end(start + 150),
             )
             .await
             .unwrap();
-        assert_eq!(result.ds_count, 1);
+        assert_eq!(fine.step, 1);
+        let downsampled = fine
+            .downsample(10, ConsolidationFunction::Min, 0.5)
+            .unwrap();
+        let coarse = client
+            .fetch(
+                FetchRequest::new(&path, ConsolidationFunction::Min)
+                    .start(start)
+                    .end(start + 150),
+            )
+            .await
+            .unwrap();
+        assert_eq!(coarse.step, 10);
+
+        let expected = coarse.column("ds1").unwrap();
+        let mut compared = 0;
+        for (timestamp, value) in downsampled.column("ds1").unwrap() {
+            let Some(value) = value else { continue };
+            if let Some((_, Some(expected))) = expected.iter().find(|(t, _)| *t == timestamp) {
+                assert_eq!(value, *expected, "at {}", timestamp);
+                compared += 1;
+            }
+        }
+        assert!(compared > 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let now = now_timestamp().unwrap() as u64;
+        let start = now - now % 10 - 100;
+        let mut requests = Vec::new();
+        for (name, step) in [("test-fetch-many-cpu", 1), ("test-fetch-many-load", 10)] {
+            let path = RrdPath::new(name).unwrap();
+            client
+                .create(
+                    CreateArguments::builder(name)
+                        .step_seconds(step)
+                        .start_timestamp(start)
+                        .gauge("ds1", None, None)
+                        .rra(CreateRoundRobinArchive {
+                            consolidation_function: ConsolidationFunction::Average,
+                            xfiles_factor: 0.5,
+                            steps: 1,
+                            rows: 100,
+                            step: None,
+                        })
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let samples = (1..=(80 / step))
+                .map(|i| (start as usize + (i * step) as usize, vec![i as f64]))
+                .collect();
+            client
+                .batch(vec![BatchUpdate::new_many(path.clone(), samples).unwrap()])
+                .await
+                .unwrap();
+            requests.push(
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .start(start)
+                    .end(start + 80),
+            );
+        }
+
+        let joined = client.fetch_many(requests.clone()).await.unwrap();
+        assert_eq!(joined.step, 10);
+        assert_eq!(
+            joined.ds_names,
+            vec!["test-fetch-many-cpu:ds1", "test-fetch-many-load:ds1"]
+        );
+        // The values of the coarsest RRD are kept as they are
+        let load = client.fetch(requests[1].clone()).await.unwrap();
+        for (timestamp, value) in load.column("ds1").unwrap() {
+            let row = joined.timestamps().position(|t| t == timestamp).unwrap();
+            assert_eq!(joined.data[row].1[1].is_nan(), value.is_none());
+        }
+
+        // An error doesn't leave responses unread
+        let missing = FetchRequest::new(
+            &RrdPath::new("test-fetch-many-missing").unwrap(),
+            ConsolidationFunction::Average,
+        );
+        requests.insert(0, missing);
+        assert!(client.fetch_many(requests).await.is_err());
+        client.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_after_update() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-fetch-after-update").unwrap();
+        let now = now_timestamp().unwrap() as u64;
+        client
+            .create(
+                CreateArguments::builder(path.name())
+                    .step_seconds(1)
+                    .start_timestamp(now - 20)
+                    .gauge("ds1", None, None)
+                    .rra(CreateRoundRobinArchive {
+                        consolidation_function: ConsolidationFunction::Last,
+                        xfiles_factor: 0.5,
+                        steps: 1,
+                        rows: 100,
+                        step: None,
+                    })
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        for (i, consistency) in [FetchConsistency::Flush, FetchConsistency::FlushIfPending]
+            .into_iter()
+            .enumerate()
+        {
+            let timestamp = now as usize - 10 + i * 5;
+            for offset in 0..5 {
+                client
+                    .update_one(&path, UpdateTimestamp::Seconds(timestamp + offset), 4.2)
+                    .await
+                    .unwrap();
+            }
+
+            // The value just written is visible
+            let response = client
+                .fetch(
+                    FetchRequest::new(&path, ConsolidationFunction::Last)
+                        .start(now - 20)
+                        .end(now)
+                        .consistency(consistency),
+                )
+                .await
+                .unwrap();
+            let value = response
+                .column("ds1")
+                .unwrap()
+                .into_iter()
+                .find(|(t, _)| *t == timestamp + 3)
+                .and_then(|(_, value)| value);
+            assert_eq!(value, Some(4.2));
+            assert!(client.pending(&path).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline() {
+        let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+            .await
+            .unwrap();
+
+        let path = RrdPath::new("test-pipeline").unwrap();
+        create_simple_rrd(&mut client, &path).await;
+        let now = now_timestamp().unwrap();
+
+        let missing = RrdPath::new("test-pipeline-missing").unwrap();
+        let pipeline = Pipeline::new()
+            .ping()
+            .update_one(&path, UpdateTimestamp::Seconds(now - 2), 4.2)
+            .update_one(&path, UpdateTimestamp::Seconds(now - 1), 4.3)
+            .last(&missing)
+            .flush(&path)
+            .last(&path)
+            .fetch(
+                FetchRequest::new(&path, ConsolidationFunction::Average)
+                    .start(now as u64 - 10)
+                    .end(now as u64),
+            );
+        assert_eq!(pipeline.len(), 7);
+        let responses = client.execute(pipeline).await.unwrap();
+        assert_eq!(responses.len(), 7);
+        assert!(matches!(responses[0], Ok(PipelineResponse::Pong)));
+        assert!(matches!(responses[1], Ok(PipelineResponse::Updated)));
+        assert!(matches!(responses[2], Ok(PipelineResponse::Updated)));
+        // An error doesn't prevent the next commands
+        assert!(responses[3].is_err());
+        assert!(matches!(responses[4], Ok(PipelineResponse::Flushed)));
+        assert!(matches!(responses[5], Ok(PipelineResponse::Last(t)) if t == now - 1));
+        match &responses[6] {
+            Ok(PipelineResponse::Fetch(response)) => assert_eq!(response.ds_count, 1),
+            other => panic!("unexpected response {:?}", other),
+        }
+
+        // The connection is still usable
+        client.ping().await.unwrap();
     }
 }
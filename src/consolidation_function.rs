@@ -1,9 +1,29 @@
+use crate::errors::RRDCachedClientError;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ConsolidationFunction {
     Average,
     Min,
     Max,
     Last,
+
+    /// Holt-Winters prediction, additive
+    HwPredict,
+
+    /// Holt-Winters prediction, multiplicative
+    MhwPredict,
+
+    /// Holt-Winters seasonal coefficients
+    Seasonal,
+
+    /// Holt-Winters seasonal deviations
+    DevSeasonal,
+
+    /// Holt-Winters predicted deviations
+    DevPredict,
+
+    /// Aberrant behavior detected by Holt-Winters
+    Failures,
 }
 
 impl ConsolidationFunction {
@@ -13,6 +33,47 @@ impl ConsolidationFunction {
             ConsolidationFunction::Min => "MIN",
             ConsolidationFunction::Max => "MAX",
             ConsolidationFunction::Last => "LAST",
+            ConsolidationFunction::HwPredict => "HWPREDICT",
+            ConsolidationFunction::MhwPredict => "MHWPREDICT",
+            ConsolidationFunction::Seasonal => "SEASONAL",
+            ConsolidationFunction::DevSeasonal => "DEVSEASONAL",
+            ConsolidationFunction::DevPredict => "DEVPREDICT",
+            ConsolidationFunction::Failures => "FAILURES",
+        }
+    }
+
+    /// Whether it's a Holt-Winters function of aberrant behavior detection,
+    /// which can't be used in a [crate::create::CreateRoundRobinArchive].
+    pub fn is_holt_winters(self) -> bool {
+        !matches!(
+            self,
+            ConsolidationFunction::Average
+                | ConsolidationFunction::Min
+                | ConsolidationFunction::Max
+                | ConsolidationFunction::Last
+        )
+    }
+}
+
+impl std::str::FromStr for ConsolidationFunction {
+    type Err = RRDCachedClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AVERAGE" => Ok(ConsolidationFunction::Average),
+            "MIN" => Ok(ConsolidationFunction::Min),
+            "MAX" => Ok(ConsolidationFunction::Max),
+            "LAST" => Ok(ConsolidationFunction::Last),
+            "HWPREDICT" => Ok(ConsolidationFunction::HwPredict),
+            "MHWPREDICT" => Ok(ConsolidationFunction::MhwPredict),
+            "SEASONAL" => Ok(ConsolidationFunction::Seasonal),
+            "DEVSEASONAL" => Ok(ConsolidationFunction::DevSeasonal),
+            "DEVPREDICT" => Ok(ConsolidationFunction::DevPredict),
+            "FAILURES" => Ok(ConsolidationFunction::Failures),
+            _ => Err(RRDCachedClientError::Parsing(format!(
+                "unknown consolidation function: {}",
+                s
+            ))),
         }
     }
 }
@@ -26,5 +87,44 @@ mod tests {
         assert_eq!(ConsolidationFunction::Min.to_str(), "MIN");
         assert_eq!(ConsolidationFunction::Max.to_str(), "MAX");
         assert_eq!(ConsolidationFunction::Last.to_str(), "LAST");
+        assert_eq!(ConsolidationFunction::HwPredict.to_str(), "HWPREDICT");
+        assert_eq!(ConsolidationFunction::Failures.to_str(), "FAILURES");
+    }
+
+    #[test]
+    fn test_consolidation_function_from_str() {
+        assert_eq!(
+            "AVERAGE".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::Average
+        );
+        assert_eq!(
+            "MIN".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::Min
+        );
+        assert_eq!(
+            "MAX".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::Max
+        );
+        assert_eq!(
+            "LAST".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::Last
+        );
+        assert_eq!(
+            "MHWPREDICT".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::MhwPredict
+        );
+        assert_eq!(
+            "DEVSEASONAL".parse::<ConsolidationFunction>().unwrap(),
+            ConsolidationFunction::DevSeasonal
+        );
+        assert!("average".parse::<ConsolidationFunction>().is_err());
+    }
+
+    #[test]
+    fn test_consolidation_function_is_holt_winters() {
+        assert!(!ConsolidationFunction::Average.is_holt_winters());
+        assert!(!ConsolidationFunction::Last.is_holt_winters());
+        assert!(ConsolidationFunction::HwPredict.is_holt_winters());
+        assert!(ConsolidationFunction::Failures.is_holt_winters());
     }
 }
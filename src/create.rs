@@ -1,9 +1,29 @@
 use crate::{
     consolidation_function::ConsolidationFunction,
+    create_builder::CreateArgumentsBuilder,
     errors::RRDCachedClientError,
-    sanitisation::{check_data_source_name, check_rrd_path},
+    holt_winters::{
+        CreateDevPredictArchive, CreateFailuresArchive, CreateHwPredictArchive,
+        CreateSeasonalArchive, DEFAULT_SMOOTHING_WINDOW,
+    },
+    info::{RrdInfo, RrdInfoRoundRobinArchive},
+    rpn::RpnExpression,
+    rrd_duration::RrdDuration,
+    rrd_path::RrdPath,
+    sanitisation::check_data_source_name,
 };
 
+/// Sizes of the structures of the rrdtool file format on 64-bit platforms,
+/// in bytes, to estimate the size of a RRD file.
+const STAT_HEAD_SIZE: u64 = 128;
+const DS_DEF_SIZE: u64 = 120;
+const RRA_DEF_SIZE: u64 = 120;
+const LIVE_HEAD_SIZE: u64 = 16;
+const PDP_PREP_SIZE: u64 = 112;
+const CDP_PREP_SIZE: u64 = 80;
+const RRA_PTR_SIZE: u64 = 8;
+const VALUE_SIZE: u64 = 8;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CreateDataSourceType {
     Gauge,
@@ -12,6 +32,9 @@ pub enum CreateDataSourceType {
     Derive,
     DDerive,
     Absolute,
+
+    /// Computed from the other data sources with an RPN expression
+    Compute,
 }
 
 impl CreateDataSourceType {
@@ -23,12 +46,33 @@ impl CreateDataSourceType {
             CreateDataSourceType::Derive => "DERIVE",
             CreateDataSourceType::DDerive => "DDERIVE",
             CreateDataSourceType::Absolute => "ABSOLUTE",
+            CreateDataSourceType::Compute => "COMPUTE",
+        }
+    }
+}
+
+impl std::str::FromStr for CreateDataSourceType {
+    type Err = RRDCachedClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GAUGE" => Ok(CreateDataSourceType::Gauge),
+            "COUNTER" => Ok(CreateDataSourceType::Counter),
+            "DCOUNTER" => Ok(CreateDataSourceType::DCounter),
+            "DERIVE" => Ok(CreateDataSourceType::Derive),
+            "DDERIVE" => Ok(CreateDataSourceType::DDerive),
+            "ABSOLUTE" => Ok(CreateDataSourceType::Absolute),
+            "COMPUTE" => Ok(CreateDataSourceType::Compute),
+            _ => Err(RRDCachedClientError::Parsing(format!(
+                "unknown data source type: {}",
+                s
+            ))),
         }
     }
 }
 
 /// Arguments for a data source (DS).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CreateDataSource {
     /// Name of the data source.
     /// Must be between 1 and 64 characters and only contain alphanumeric characters and underscores
@@ -43,19 +87,56 @@ pub struct CreateDataSource {
 
     /// Heartbeat, if no data is received for this amount of time,
     /// the value is unknown.
+    ///
+    /// Ignored by COMPUTE data sources.
     pub heartbeat: i64,
 
     /// Type of the data source
     pub serie_type: CreateDataSourceType,
+
+    /// Expression of a COMPUTE data source, `None` for the other types.
+    ///
+    /// It may only reference the data sources defined before this one.
+    pub expression: Option<RpnExpression>,
 }
 
 impl CreateDataSource {
+    /// COMPUTE data source, calculated from the other data sources.
+    pub fn compute(name: &str, expression: RpnExpression) -> CreateDataSource {
+        CreateDataSource {
+            name: name.to_string(),
+            minimum: None,
+            maximum: None,
+            heartbeat: 0,
+            serie_type: CreateDataSourceType::Compute,
+            expression: Some(expression),
+        }
+    }
+
     /// Check that the content is valid.
+    ///
+    /// The data sources referenced by the expression are checked by
+    /// [CreateArguments::validate], the rest by [RpnExpression::validate_compute].
     pub fn validate(&self) -> Result<(), RRDCachedClientError> {
-        if self.heartbeat <= 0 {
-            return Err(RRDCachedClientError::InvalidCreateDataSerie(
-                "heartbeat must be greater than 0".to_string(),
-            ));
+        match (self.serie_type, &self.expression) {
+            (CreateDataSourceType::Compute, None) => {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                    "COMPUTE data sources require an expression".to_string(),
+                ));
+            }
+            (CreateDataSourceType::Compute, Some(expression)) => expression.validate_compute()?,
+            (_, Some(_)) => {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                    "only COMPUTE data sources have an expression".to_string(),
+                ));
+            }
+            (_, None) => {
+                if self.heartbeat <= 0 {
+                    return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                        "heartbeat must be greater than 0".to_string(),
+                    ));
+                }
+            }
         }
         if let Some(minimum) = self.minimum {
             if let Some(maximum) = self.maximum {
@@ -74,6 +155,14 @@ impl CreateDataSource {
 
     /// Convert to a string argument parameter.
     pub fn to_str(&self) -> String {
+        if let Some(expression) = &self.expression {
+            return format!(
+                "DS:{}:{}:{}",
+                self.name,
+                self.serie_type.to_str(),
+                expression
+            );
+        }
         format!(
             "DS:{}:{}:{}:{}:{}",
             self.name,
@@ -89,10 +178,24 @@ impl CreateDataSource {
             }
         )
     }
+
+    /// Same data source, without the fields ignored by its type,
+    /// to compare it with a data source read from an existing RRD.
+    fn without_ignored_fields(&self) -> CreateDataSource {
+        match self.serie_type {
+            CreateDataSourceType::Compute => CreateDataSource {
+                minimum: None,
+                maximum: None,
+                heartbeat: 0,
+                ..self.clone()
+            },
+            _ => self.clone(),
+        }
+    }
 }
 
 /// Arguments for a round robin archive (RRA).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CreateRoundRobinArchive {
     /// Archive types are AVERAGE, MIN, MAX, LAST.
     pub consolidation_function: ConsolidationFunction,
@@ -107,11 +210,53 @@ pub struct CreateRoundRobinArchive {
 
     /// Number of rows in the archive
     pub rows: i64,
+
+    /// Step of the RRD that [Self::steps] was computed for, set by
+    /// [Self::with_durations] and checked by [CreateArguments::validate].
+    ///
+    /// `None` when the steps are given directly.
+    pub step: Option<RrdDuration>,
 }
 
 impl CreateRoundRobinArchive {
+    /// Round robin archive described by durations, like `RRA:AVERAGE:0.5:1m:30d`.
+    ///
+    /// The resolution is the duration of a row and must be a multiple of the
+    /// step of the RRD. The retention is the duration covered by the archive
+    /// and must be a multiple of the resolution. The step must be the one of
+    /// the RRD, which is checked by [CreateArguments::validate].
+    pub fn with_durations(
+        consolidation_function: ConsolidationFunction,
+        xfiles_factor: f64,
+        step: RrdDuration,
+        resolution: RrdDuration,
+        retention: RrdDuration,
+    ) -> Result<CreateRoundRobinArchive, RRDCachedClientError> {
+        let steps = resolution.steps_of(step)?;
+        let rows = retention.steps_of(resolution)?;
+        let archive = CreateRoundRobinArchive {
+            consolidation_function,
+            xfiles_factor,
+            steps: i64::try_from(steps).map_err(|_| {
+                RRDCachedClientError::InvalidCreateDataSerie("too many steps".to_string())
+            })?,
+            rows: i64::try_from(rows).map_err(|_| {
+                RRDCachedClientError::InvalidCreateDataSerie("too many rows".to_string())
+            })?,
+            step: Some(step),
+        };
+        archive.validate()?;
+        Ok(archive)
+    }
+
     /// Check that the content is valid.
     pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        if self.consolidation_function.is_holt_winters() {
+            return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                "{} archives must be created with their CreateArchive variant",
+                self.consolidation_function.to_str()
+            )));
+        }
         if self.xfiles_factor < 0.0 || self.xfiles_factor > 1.0 {
             return Err(RRDCachedClientError::InvalidCreateDataSerie(
                 "xfiles_factor must be between 0 and 1".to_string(),
@@ -142,14 +287,281 @@ impl CreateRoundRobinArchive {
     }
 }
 
+/// Arguments for a round robin archive (RRA) of any kind.
+///
+/// The Holt-Winters archives of aberrant behavior detection reference
+/// each other by their index in [CreateArguments::round_robin_archives],
+/// starting at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CreateArchive {
+    /// Consolidated archive: AVERAGE, MIN, MAX or LAST
+    Consolidated(CreateRoundRobinArchive),
+    HwPredict(CreateHwPredictArchive),
+    MhwPredict(CreateHwPredictArchive),
+    Seasonal(CreateSeasonalArchive),
+    DevSeasonal(CreateSeasonalArchive),
+    DevPredict(CreateDevPredictArchive),
+    Failures(CreateFailuresArchive),
+}
+
+impl CreateArchive {
+    pub fn consolidation_function(&self) -> ConsolidationFunction {
+        match self {
+            CreateArchive::Consolidated(archive) => archive.consolidation_function,
+            CreateArchive::HwPredict(_) => ConsolidationFunction::HwPredict,
+            CreateArchive::MhwPredict(_) => ConsolidationFunction::MhwPredict,
+            CreateArchive::Seasonal(_) => ConsolidationFunction::Seasonal,
+            CreateArchive::DevSeasonal(_) => ConsolidationFunction::DevSeasonal,
+            CreateArchive::DevPredict(_) => ConsolidationFunction::DevPredict,
+            CreateArchive::Failures(_) => ConsolidationFunction::Failures,
+        }
+    }
+
+    /// Check that the content is valid.
+    ///
+    /// The references to other archives are checked by [CreateArguments::validate].
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        match self {
+            CreateArchive::Consolidated(archive) => archive.validate(),
+            CreateArchive::HwPredict(archive) | CreateArchive::MhwPredict(archive) => {
+                archive.validate()
+            }
+            CreateArchive::Seasonal(archive) | CreateArchive::DevSeasonal(archive) => {
+                archive.validate()
+            }
+            CreateArchive::DevPredict(archive) => archive.validate(),
+            CreateArchive::Failures(archive) => archive.validate(),
+        }
+    }
+
+    /// Convert to a string argument parameter.
+    pub fn to_str(&self) -> String {
+        let arguments = match self {
+            CreateArchive::Consolidated(archive) => return archive.to_str(),
+            CreateArchive::HwPredict(archive) | CreateArchive::MhwPredict(archive) => {
+                archive.to_str()
+            }
+            CreateArchive::Seasonal(archive) | CreateArchive::DevSeasonal(archive) => {
+                archive.to_str()
+            }
+            CreateArchive::DevPredict(archive) => archive.to_str(),
+            CreateArchive::Failures(archive) => archive.to_str(),
+        };
+        format!(
+            "RRA:{}:{}",
+            self.consolidation_function().to_str(),
+            arguments
+        )
+    }
+
+    /// Number of archives in the RRD file, including the ones
+    /// rrdtool creates for a HWPREDICT or MHWPREDICT archive
+    /// without SEASONAL archive.
+    fn archive_count(&self) -> u64 {
+        match self {
+            CreateArchive::HwPredict(CreateHwPredictArchive {
+                seasonal_archive: None,
+                ..
+            })
+            | CreateArchive::MhwPredict(CreateHwPredictArchive {
+                seasonal_archive: None,
+                ..
+            }) => 5,
+            _ => 1,
+        }
+    }
+
+    /// Number of rows in the RRD file, including the ones of the
+    /// archives rrdtool creates for a HWPREDICT or MHWPREDICT archive
+    /// without SEASONAL archive.
+    fn total_rows(&self) -> u64 {
+        match self {
+            CreateArchive::Consolidated(archive) => archive.rows.max(0) as u64,
+            CreateArchive::HwPredict(archive) | CreateArchive::MhwPredict(archive) => {
+                match archive.seasonal_archive {
+                    Some(_) => archive.rows,
+                    // SEASONAL and DEVSEASONAL of a season, DEVPREDICT and FAILURES
+                    None => 3 * archive.rows + 2 * archive.seasonal_period,
+                }
+            }
+            CreateArchive::Seasonal(archive) | CreateArchive::DevSeasonal(archive) => {
+                archive.seasonal_period
+            }
+            CreateArchive::DevPredict(archive) => archive.rows,
+            CreateArchive::Failures(archive) => archive.rows,
+        }
+    }
+
+    /// Same archive, with the defaults of rrdtool made explicit,
+    /// to compare it with an archive read from an existing RRD.
+    fn with_explicit_defaults(&self) -> CreateArchive {
+        match self {
+            CreateArchive::Seasonal(archive) => CreateArchive::Seasonal(CreateSeasonalArchive {
+                smoothing_window: archive.smoothing_window.or(Some(DEFAULT_SMOOTHING_WINDOW)),
+                ..archive.clone()
+            }),
+            CreateArchive::DevSeasonal(archive) => {
+                CreateArchive::DevSeasonal(CreateSeasonalArchive {
+                    smoothing_window: archive.smoothing_window.or(Some(DEFAULT_SMOOTHING_WINDOW)),
+                    ..archive.clone()
+                })
+            }
+            CreateArchive::Consolidated(archive) => {
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
+                    step: None,
+                    ..archive.clone()
+                })
+            }
+            archive => archive.clone(),
+        }
+    }
+
+    /// Reconstruct an archive from the INFO of the RRD.
+    ///
+    /// rrdtool doesn't expose the references between the Holt-Winters archives,
+    /// so they are found by type and must not be ambiguous.
+    fn from_info(info: &RrdInfo, index: usize) -> Result<CreateArchive, RRDCachedClientError> {
+        let rra = &info.round_robin_archives[index];
+        let missing = |field: &str| {
+            RRDCachedClientError::Parsing(format!("Missing {} for rra[{}]", field, index))
+        };
+        let archive = match rra.consolidation_function {
+            ConsolidationFunction::HwPredict | ConsolidationFunction::MhwPredict => {
+                let seasonal_archive = find_unique_archive(
+                    &info.round_robin_archives,
+                    &[ConsolidationFunction::Seasonal],
+                )?;
+                let archive = CreateHwPredictArchive {
+                    rows: rra.rows,
+                    alpha: rra.alpha.ok_or_else(|| missing("alpha"))?,
+                    beta: rra.beta.ok_or_else(|| missing("beta"))?,
+                    seasonal_period: info.round_robin_archives[seasonal_archive].rows,
+                    seasonal_archive: Some(seasonal_archive),
+                };
+                if rra.consolidation_function == ConsolidationFunction::HwPredict {
+                    CreateArchive::HwPredict(archive)
+                } else {
+                    CreateArchive::MhwPredict(archive)
+                }
+            }
+            ConsolidationFunction::Seasonal | ConsolidationFunction::DevSeasonal => {
+                let archive = CreateSeasonalArchive {
+                    seasonal_period: rra.rows,
+                    gamma: rra.gamma.ok_or_else(|| missing("gamma"))?,
+                    predict_archive: find_unique_archive(
+                        &info.round_robin_archives,
+                        &[
+                            ConsolidationFunction::HwPredict,
+                            ConsolidationFunction::MhwPredict,
+                        ],
+                    )?,
+                    smoothing_window: rra.smoothing_window,
+                };
+                if rra.consolidation_function == ConsolidationFunction::Seasonal {
+                    CreateArchive::Seasonal(archive)
+                } else {
+                    CreateArchive::DevSeasonal(archive)
+                }
+            }
+            ConsolidationFunction::DevPredict => {
+                CreateArchive::DevPredict(CreateDevPredictArchive {
+                    rows: rra.rows,
+                    dev_seasonal_archive: find_unique_archive(
+                        &info.round_robin_archives,
+                        &[ConsolidationFunction::DevSeasonal],
+                    )?,
+                })
+            }
+            ConsolidationFunction::Failures => CreateArchive::Failures(CreateFailuresArchive {
+                rows: rra.rows,
+                threshold: rra
+                    .failure_threshold
+                    .ok_or_else(|| missing("failure_threshold"))?,
+                window_length: rra.window_length.ok_or_else(|| missing("window_length"))?,
+                dev_seasonal_archive: find_unique_archive(
+                    &info.round_robin_archives,
+                    &[ConsolidationFunction::DevSeasonal],
+                )?,
+            }),
+            consolidation_function => CreateArchive::Consolidated(CreateRoundRobinArchive {
+                consolidation_function,
+                xfiles_factor: rra.xfiles_factor,
+                steps: rra.pdp_per_row as i64,
+                rows: rra.rows as i64,
+                step: None,
+            }),
+        };
+        Ok(archive)
+    }
+}
+
+impl From<CreateRoundRobinArchive> for CreateArchive {
+    fn from(archive: CreateRoundRobinArchive) -> Self {
+        CreateArchive::Consolidated(archive)
+    }
+}
+
+/// Index of the only archive with one of the consolidation functions.
+fn find_unique_archive(
+    archives: &[RrdInfoRoundRobinArchive],
+    consolidation_functions: &[ConsolidationFunction],
+) -> Result<usize, RRDCachedClientError> {
+    let mut indexes = archives
+        .iter()
+        .enumerate()
+        .filter(|(_, rra)| consolidation_functions.contains(&rra.consolidation_function))
+        .map(|(index, _)| index);
+    match (indexes.next(), indexes.next()) {
+        (Some(index), None) => Ok(index),
+        (None, _) => Err(RRDCachedClientError::Parsing(format!(
+            "Missing {} archive",
+            consolidation_functions[0].to_str()
+        ))),
+        (Some(_), Some(_)) => Err(RRDCachedClientError::Parsing(format!(
+            "Ambiguous {} archives",
+            consolidation_functions[0].to_str()
+        ))),
+    }
+}
+
+/// A difference between two RRD schemas.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaDifference {
+    /// The steps are different
+    Step { expected: u64, actual: u64 },
+
+    /// A data source is missing
+    MissingDataSource(String),
+
+    /// A data source is not expected
+    UnexpectedDataSource(String),
+
+    /// A data source has different parameters
+    DataSource {
+        expected: CreateDataSource,
+        actual: CreateDataSource,
+    },
+
+    /// The data sources are in a different order,
+    /// which matters for updates.
+    DataSourceOrder {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+
+    /// A round robin archive is missing
+    MissingRoundRobinArchive(CreateArchive),
+
+    /// A round robin archive is not expected
+    UnexpectedRoundRobinArchive(CreateArchive),
+}
+
 /// Arguments to create a new RRD file
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CreateArguments {
-    /// Path to the RRD file
-    /// The path must be between 1 and 64 characters and only contain alphanumeric characters and underscores
-    ///
-    /// Does **not** end with .rrd
-    pub path: String,
+    /// Path to the RRD file, relative to the base directory of RRDCached.
+    /// It may contain sub-directories, like `hosts/server-1/cpu`, but they must exist.
+    pub path: RrdPath,
 
     /// List of data sources, the order is important
     /// Must be at least one.
@@ -157,24 +569,75 @@ pub struct CreateArguments {
 
     /// List of round robin archives.
     /// Must be at least one.
-    pub round_robin_archives: Vec<CreateRoundRobinArchive>,
+    pub round_robin_archives: Vec<CreateArchive>,
 
     /// Start time of the first data point
     pub start_timestamp: u64,
 
     /// Number of seconds between two data points
+    ///
+    /// See [Self::step] and [Self::set_step] to use a [RrdDuration] like `10s`.
     pub step_seconds: u64,
+
+    /// Fail if the RRD file already exists (`-O`).
+    ///
+    /// Otherwise RRDCached keeps the existing file and reports success.
+    pub no_overwrite: bool,
+
+    /// Existing RRDs to prefill the data from (`-r`), relative to
+    /// the base directory of RRDCached.
+    ///
+    /// The data of the data sources and round robin archives
+    /// with the same names and consolidation functions are copied.
+    pub sources: Vec<RrdPath>,
+
+    /// Existing RRD to use as a template for the data sources, the round
+    /// robin archives and the step (`-t`), relative to the base directory
+    /// of RRDCached.
+    ///
+    /// Data sources and round robin archives may then be empty,
+    /// and the given ones are added to the ones of the template.
+    pub template: Option<RrdPath>,
 }
 
 impl CreateArguments {
+    /// Start building the arguments to create a RRD at the path.
+    pub fn builder(path: &str) -> CreateArgumentsBuilder {
+        CreateArgumentsBuilder::new(path)
+    }
+
+    /// Step between two data points, as a duration.
+    pub fn step(&self) -> Result<RrdDuration, RRDCachedClientError> {
+        RrdDuration::from_secs(self.step_seconds)
+    }
+
+    /// Set the step between two data points from a duration, like `10s`.
+    pub fn set_step(&mut self, step: RrdDuration) {
+        self.step_seconds = step.as_secs();
+    }
+
     /// Check that the content is valid.
+    ///
+    /// On top of what rrdtool requires, it rejects the likely mistakes of a
+    /// new layout: heartbeats shorter than the step, several archives with
+    /// the same consolidation function and steps, or coarser archives not
+    /// keeping a longer history. [Self::from_info] and the create
+    /// commands skip these checks, as existing RRDs may have such a layout.
     pub fn validate(&self) -> Result<(), RRDCachedClientError> {
-        if self.data_sources.is_empty() {
+        self.validate_structure()?;
+        self.validate_heartbeats()?;
+        self.validate_archive_retentions()?;
+        Ok(())
+    }
+
+    /// Check what rrdtool requires, accepting the layouts of existing RRDs.
+    pub(crate) fn validate_structure(&self) -> Result<(), RRDCachedClientError> {
+        if self.data_sources.is_empty() && self.template.is_none() {
             return Err(RRDCachedClientError::InvalidCreateDataSerie(
                 "at least one data serie is required".to_string(),
             ));
         }
-        if self.round_robin_archives.is_empty() {
+        if self.round_robin_archives.is_empty() && self.template.is_none() {
             return Err(RRDCachedClientError::InvalidCreateDataSerie(
                 "at least one round robin archive is required".to_string(),
             ));
@@ -185,16 +648,236 @@ impl CreateArguments {
         for rr_archive in &self.round_robin_archives {
             rr_archive.validate()?;
         }
-        check_rrd_path(&self.path)?;
+        self.validate_data_source_names()?;
+        self.validate_archive_steps()?;
+        self.validate_expression_references()?;
+        self.validate_archive_references()?;
+        if self.sources.contains(&self.path) || self.template.as_ref() == Some(&self.path) {
+            return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                "the RRD can't be its own source or template".to_string(),
+            ));
+        }
+        for (i, source) in self.sources.iter().enumerate() {
+            if self.sources[..i].contains(source) {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                    "duplicate source {}",
+                    source
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the data sources have different names.
+    fn validate_data_source_names(&self) -> Result<(), RRDCachedClientError> {
+        for (index, data_source) in self.data_sources.iter().enumerate() {
+            if self.data_sources[..index]
+                .iter()
+                .any(|ds| ds.name == data_source.name)
+            {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                    "duplicate data source {}",
+                    data_source.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the archives described by durations were
+    /// computed for the step of the RRD.
+    fn validate_archive_steps(&self) -> Result<(), RRDCachedClientError> {
+        for (index, archive) in self.round_robin_archives.iter().enumerate() {
+            if let CreateArchive::Consolidated(CreateRoundRobinArchive {
+                step: Some(step), ..
+            }) = archive
+            {
+                if step.as_secs() != self.step_seconds {
+                    return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                        "round robin archive {} was computed for a step of {}, not {}s",
+                        index, step, self.step_seconds
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the heartbeats are at least the step,
+    /// otherwise every value would be unknown.
+    fn validate_heartbeats(&self) -> Result<(), RRDCachedClientError> {
+        for data_source in &self.data_sources {
+            if data_source.serie_type != CreateDataSourceType::Compute
+                && (data_source.heartbeat as u64) < self.step_seconds
+            {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                    "heartbeat of data source {} must be at least the step",
+                    data_source.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the COMPUTE data sources only reference
+    /// data sources defined before them.
+    fn validate_expression_references(&self) -> Result<(), RRDCachedClientError> {
+        for (index, data_source) in self.data_sources.iter().enumerate() {
+            let Some(expression) = &data_source.expression else {
+                continue;
+            };
+            for name in expression.data_sources() {
+                if !self.data_sources[..index].iter().any(|ds| ds.name == name) {
+                    return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                        "data source {} references {}, which isn't defined before it",
+                        data_source.name, name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the Holt-Winters archives reference
+    /// archives of the right type and seasonal period.
+    fn validate_archive_references(&self) -> Result<(), RRDCachedClientError> {
+        let archives = &self.round_robin_archives;
+        let invalid = |index: usize, expected: &str| {
+            Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                "archive {} must reference {}",
+                index, expected
+            )))
+        };
+        for (index, archive) in archives.iter().enumerate() {
+            match archive {
+                CreateArchive::Consolidated(_) => {}
+                CreateArchive::HwPredict(predict) | CreateArchive::MhwPredict(predict) => {
+                    match predict.seasonal_archive.map(|i| archives.get(i)) {
+                        None => {}
+                        Some(Some(CreateArchive::Seasonal(seasonal)))
+                            if seasonal.seasonal_period == predict.seasonal_period => {}
+                        Some(_) => return invalid(index, "a SEASONAL archive of the same period"),
+                    }
+                }
+                CreateArchive::Seasonal(seasonal) | CreateArchive::DevSeasonal(seasonal) => {
+                    match archives.get(seasonal.predict_archive) {
+                        Some(CreateArchive::HwPredict(predict))
+                        | Some(CreateArchive::MhwPredict(predict))
+                            if predict.seasonal_period == seasonal.seasonal_period => {}
+                        _ => {
+                            return invalid(
+                                index,
+                                "a HWPREDICT or MHWPREDICT archive of the same period",
+                            )
+                        }
+                    }
+                }
+                CreateArchive::DevPredict(CreateDevPredictArchive {
+                    dev_seasonal_archive,
+                    ..
+                })
+                | CreateArchive::Failures(CreateFailuresArchive {
+                    dev_seasonal_archive,
+                    ..
+                }) => {
+                    if !matches!(
+                        archives.get(*dev_seasonal_archive),
+                        Some(CreateArchive::DevSeasonal(_))
+                    ) {
+                        return invalid(index, "a DEVSEASONAL archive");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the consolidated archives of a consolidation function have
+    /// different resolutions, and that the coarser ones keep at least as much history.
+    fn validate_archive_retentions(&self) -> Result<(), RRDCachedClientError> {
+        let archives: Vec<&CreateRoundRobinArchive> = self
+            .round_robin_archives
+            .iter()
+            .filter_map(|archive| match archive {
+                CreateArchive::Consolidated(archive) => Some(archive),
+                _ => None,
+            })
+            .collect();
+        for (index, archive) in archives.iter().enumerate() {
+            for other in &archives[..index] {
+                if other.consolidation_function != archive.consolidation_function {
+                    continue;
+                }
+                if other.steps == archive.steps {
+                    return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                        "duplicate {} archive of {} steps",
+                        archive.consolidation_function.to_str(),
+                        archive.steps
+                    )));
+                }
+                let (finer, coarser) = if other.steps < archive.steps {
+                    (other, archive)
+                } else {
+                    (archive, other)
+                };
+                if coarser.steps.saturating_mul(coarser.rows)
+                    < finer.steps.saturating_mul(finer.rows)
+                {
+                    return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                        "{} archive of {} steps keeps a shorter history than the one of {} steps",
+                        archive.consolidation_function.to_str(),
+                        coarser.steps,
+                        finer.steps
+                    )));
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Estimated size of the RRD file, in bytes.
+    ///
+    /// It follows the file format of rrdtool on 64-bit platforms: a header
+    /// with the definitions and the consolidation state of the data sources
+    /// and archives, then a value of 8 bytes per row and data source.
+    /// The data sources and archives of a template aren't counted.
+    pub fn estimated_size(&self) -> u64 {
+        let data_sources = self.data_sources.len() as u64;
+        let archives: u64 = self
+            .round_robin_archives
+            .iter()
+            .map(CreateArchive::archive_count)
+            .sum();
+        let rows: u64 = self
+            .round_robin_archives
+            .iter()
+            .map(CreateArchive::total_rows)
+            .sum();
+        STAT_HEAD_SIZE
+            + data_sources * (DS_DEF_SIZE + PDP_PREP_SIZE)
+            + archives * (RRA_DEF_SIZE + RRA_PTR_SIZE)
+            + LIVE_HEAD_SIZE
+            + archives * data_sources * CDP_PREP_SIZE
+            + rows * data_sources * VALUE_SIZE
+    }
+
     /// Convert to a string argument parameter.
     pub fn to_str(&self) -> String {
         let mut result = format!(
-            "{}.rrd -s {} -b {}",
+            "{} -s {} -b {}",
             self.path, self.step_seconds, self.start_timestamp
         );
+        if self.no_overwrite {
+            result.push_str(" -O");
+        }
+        for source in &self.sources {
+            result.push_str(" -r ");
+            result.push_str(source.as_str());
+        }
+        if let Some(template) = &self.template {
+            result.push_str(" -t ");
+            result.push_str(template.as_str());
+        }
         for data_serie in &self.data_sources {
             result.push(' ');
             result.push_str(&data_serie.to_str());
@@ -205,6 +888,140 @@ impl CreateArguments {
         }
         result
     }
+
+    /// Reconstruct the arguments that created an existing RRD, from its INFO.
+    ///
+    /// The start timestamp is the last update of the RRD.
+    pub fn from_info(
+        path: RrdPath,
+        info: &RrdInfo,
+    ) -> Result<CreateArguments, RRDCachedClientError> {
+        let data_sources = info
+            .data_sources
+            .iter()
+            .map(|ds| {
+                Ok(CreateDataSource {
+                    name: ds.name.clone(),
+                    minimum: ds.minimum,
+                    maximum: ds.maximum,
+                    heartbeat: ds.heartbeat as i64,
+                    serie_type: ds.serie_type,
+                    expression: ds.cdef.as_deref().map(str::parse).transpose()?,
+                })
+            })
+            .collect::<Result<Vec<CreateDataSource>, RRDCachedClientError>>()?;
+        let round_robin_archives = (0..info.round_robin_archives.len())
+            .map(|index| CreateArchive::from_info(info, index))
+            .collect::<Result<Vec<CreateArchive>, RRDCachedClientError>>()?;
+        let arguments = CreateArguments {
+            path,
+            data_sources,
+            round_robin_archives,
+            start_timestamp: info.last_update,
+            step_seconds: info.step,
+            no_overwrite: false,
+            sources: vec![],
+            template: None,
+        };
+        arguments.validate_structure()?;
+        Ok(arguments)
+    }
+
+    /// Check whether the schema is the same as another one.
+    ///
+    /// The path and the start timestamp are ignored.
+    pub fn same_schema(&self, other: &CreateArguments) -> bool {
+        self.schema_differences(other).is_empty()
+    }
+
+    /// List the differences between the schema, considered as expected,
+    /// and another one, considered as actual.
+    ///
+    /// The path and the start timestamp are ignored. The order of the
+    /// round robin archives is ignored, but not the order of the data sources.
+    pub fn schema_differences(&self, actual: &CreateArguments) -> Vec<SchemaDifference> {
+        let mut differences = Vec::new();
+
+        if self.step_seconds != actual.step_seconds {
+            differences.push(SchemaDifference::Step {
+                expected: self.step_seconds,
+                actual: actual.step_seconds,
+            });
+        }
+
+        for expected_ds in &self.data_sources {
+            match actual
+                .data_sources
+                .iter()
+                .find(|ds| ds.name == expected_ds.name)
+            {
+                Some(actual_ds)
+                    if actual_ds.without_ignored_fields()
+                        != expected_ds.without_ignored_fields() =>
+                {
+                    differences.push(SchemaDifference::DataSource {
+                        expected: expected_ds.clone(),
+                        actual: actual_ds.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => differences.push(SchemaDifference::MissingDataSource(
+                    expected_ds.name.clone(),
+                )),
+            }
+        }
+        for actual_ds in &actual.data_sources {
+            if !self.data_sources.iter().any(|ds| ds.name == actual_ds.name) {
+                differences.push(SchemaDifference::UnexpectedDataSource(
+                    actual_ds.name.clone(),
+                ));
+            }
+        }
+        let expected_names: Vec<String> =
+            self.data_sources.iter().map(|ds| ds.name.clone()).collect();
+        let actual_names: Vec<String> = actual
+            .data_sources
+            .iter()
+            .map(|ds| ds.name.clone())
+            .collect();
+        let mut sorted_expected_names = expected_names.clone();
+        sorted_expected_names.sort();
+        let mut sorted_actual_names = actual_names.clone();
+        sorted_actual_names.sort();
+        if expected_names != actual_names && sorted_expected_names == sorted_actual_names {
+            differences.push(SchemaDifference::DataSourceOrder {
+                expected: expected_names,
+                actual: actual_names,
+            });
+        }
+
+        let expected_rras: Vec<CreateArchive> = self
+            .round_robin_archives
+            .iter()
+            .map(CreateArchive::with_explicit_defaults)
+            .collect();
+        let actual_rras: Vec<CreateArchive> = actual
+            .round_robin_archives
+            .iter()
+            .map(CreateArchive::with_explicit_defaults)
+            .collect();
+        for (expected_rra, explicit_rra) in self.round_robin_archives.iter().zip(&expected_rras) {
+            if !actual_rras.contains(explicit_rra) {
+                differences.push(SchemaDifference::MissingRoundRobinArchive(
+                    expected_rra.clone(),
+                ));
+            }
+        }
+        for (actual_rra, explicit_rra) in actual.round_robin_archives.iter().zip(&actual_rras) {
+            if !expected_rras.contains(explicit_rra) {
+                differences.push(SchemaDifference::UnexpectedRoundRobinArchive(
+                    actual_rra.clone(),
+                ));
+            }
+        }
+
+        differences
+    }
 }
 
 #[cfg(test)]
@@ -220,6 +1037,26 @@ mod tests {
         assert_eq!(CreateDataSourceType::Derive.to_str(), "DERIVE");
         assert_eq!(CreateDataSourceType::DDerive.to_str(), "DDERIVE");
         assert_eq!(CreateDataSourceType::Absolute.to_str(), "ABSOLUTE");
+        assert_eq!(CreateDataSourceType::Compute.to_str(), "COMPUTE");
+    }
+
+    #[test]
+    fn test_create_data_source_type_from_str() {
+        for serie_type in [
+            CreateDataSourceType::Gauge,
+            CreateDataSourceType::Counter,
+            CreateDataSourceType::DCounter,
+            CreateDataSourceType::Derive,
+            CreateDataSourceType::DDerive,
+            CreateDataSourceType::Absolute,
+            CreateDataSourceType::Compute,
+        ] {
+            assert_eq!(
+                serie_type.to_str().parse::<CreateDataSourceType>().unwrap(),
+                serie_type
+            );
+        }
+        assert!("gauge".parse::<CreateDataSourceType>().is_err());
     }
 
     // Test for CreateDataSource validate method
@@ -231,19 +1068,20 @@ mod tests {
             maximum: Some(100.0),
             heartbeat: 300,
             serie_type: CreateDataSourceType::Gauge,
+            expression: None,
         };
         assert!(valid_ds.validate().is_ok());
 
         let invalid_ds_name = CreateDataSource {
             name: "Invalid Name!".to_string(), // Invalid due to space and exclamation
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(invalid_ds_name.validate().is_err());
 
         let invalid_ds_heartbeat = CreateDataSource {
             heartbeat: -1, // Invalid heartbeat
             name: "valid_name_2".to_string(),
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(invalid_ds_heartbeat.validate().is_err());
 
@@ -251,7 +1089,7 @@ mod tests {
             minimum: Some(100.0),
             maximum: Some(50.0), // Invalid minimum and maximum
             name: "valid_name_3".to_string(),
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(invalid_ds_min_max.validate().is_err());
 
@@ -260,7 +1098,7 @@ mod tests {
             minimum: Some(100.0),
             maximum: Some(0.0),
             name: "valid_name_5".to_string(),
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(invalid_ds_max.validate().is_err());
 
@@ -268,7 +1106,7 @@ mod tests {
         let valid_ds_max = CreateDataSource {
             maximum: Some(100.0),
             name: "valid_name_6".to_string(),
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(valid_ds_max.validate().is_ok());
 
@@ -276,7 +1114,7 @@ mod tests {
         let valid_ds_min = CreateDataSource {
             minimum: Some(-100.0),
             name: "valid_name_7".to_string(),
-            ..valid_ds
+            ..valid_ds.clone()
         };
         assert!(valid_ds_min.validate().is_ok());
     }
@@ -290,6 +1128,7 @@ mod tests {
             maximum: Some(100.0),
             heartbeat: 600,
             serie_type: CreateDataSourceType::Gauge,
+            expression: None,
         };
         assert_eq!(ds.to_str(), "DS:test_ds:GAUGE:600:10:100");
 
@@ -299,6 +1138,7 @@ mod tests {
             maximum: None,
             heartbeat: 600,
             serie_type: CreateDataSourceType::Gauge,
+            expression: None,
         };
         assert_eq!(ds.to_str(), "DS:test_ds:GAUGE:600:U:U");
     }
@@ -311,6 +1151,7 @@ mod tests {
             xfiles_factor: 0.5,
             steps: 1,
             rows: 100,
+            step: None,
         };
         assert!(valid_rra.validate().is_ok());
 
@@ -341,71 +1182,748 @@ mod tests {
             xfiles_factor: 0.5,
             steps: 1,
             rows: 100,
+            step: None,
         };
         assert_eq!(rra.to_str(), "RRA:MAX:0.5:1:100");
     }
 
+    #[test]
+    fn test_create_round_robin_archive_with_durations() {
+        let step = "10s".parse().unwrap();
+        let rra = CreateRoundRobinArchive::with_durations(
+            ConsolidationFunction::Average,
+            0.5,
+            step,
+            "1m".parse().unwrap(),
+            "30d".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(rra.to_str(), "RRA:AVERAGE:0.5:6:43200");
+
+        // The resolution must be a multiple of the step
+        let result = CreateRoundRobinArchive::with_durations(
+            ConsolidationFunction::Average,
+            0.5,
+            step,
+            "15s".parse().unwrap(),
+            "1d".parse().unwrap(),
+        );
+        assert!(result.is_err());
+
+        // The retention must be a multiple of the resolution
+        let result = CreateRoundRobinArchive::with_durations(
+            ConsolidationFunction::Average,
+            0.5,
+            step,
+            "7m".parse().unwrap(),
+            "1d".parse().unwrap(),
+        );
+        assert!(result.is_err());
+    }
+
     // Test for CreateArguments validate method
     #[test]
     fn test_create_arguments_validate() {
         let valid_args = CreateArguments {
-            path: "valid_path".to_string(),
+            path: RrdPath::new("valid_path").unwrap(),
             data_sources: vec![CreateDataSource {
                 name: "ds1".to_string(),
                 minimum: Some(0.0),
                 maximum: Some(100.0),
                 heartbeat: 300,
                 serie_type: CreateDataSourceType::Gauge,
+                expression: None,
             }],
-            round_robin_archives: vec![CreateRoundRobinArchive {
+            round_robin_archives: vec![CreateArchive::Consolidated(CreateRoundRobinArchive {
                 consolidation_function: ConsolidationFunction::Average,
                 xfiles_factor: 0.5,
                 steps: 1,
                 rows: 100,
-            }],
+                step: None,
+            })],
             start_timestamp: 1609459200,
             step_seconds: 300,
+            no_overwrite: false,
+            sources: vec![],
+            template: None,
         };
         assert!(valid_args.validate().is_ok());
 
         let invalid_args_no_ds = CreateArguments {
             data_sources: vec![],
-            path: "valid_path".to_string(),
-            ..valid_args
+            path: RrdPath::new("valid_path").unwrap(),
+            ..valid_args.clone()
         };
         assert!(invalid_args_no_ds.validate().is_err());
 
         let invalid_args_no_rra = CreateArguments {
             round_robin_archives: vec![],
-            path: "valid_path".to_string(),
-            ..valid_args
+            path: RrdPath::new("valid_path").unwrap(),
+            ..valid_args.clone()
         };
         assert!(invalid_args_no_rra.validate().is_err());
+
+        let template_args = CreateArguments {
+            data_sources: vec![],
+            round_robin_archives: vec![],
+            template: Some(RrdPath::new("template").unwrap()),
+            ..valid_args.clone()
+        };
+        assert!(template_args.validate().is_ok());
+
+        let own_source_args = CreateArguments {
+            sources: vec![RrdPath::new("valid_path").unwrap()],
+            ..valid_args.clone()
+        };
+        assert!(own_source_args.validate().is_err());
+
+        let own_template_args = CreateArguments {
+            template: Some(RrdPath::new("valid_path").unwrap()),
+            ..valid_args.clone()
+        };
+        assert!(own_template_args.validate().is_err());
+
+        let duplicate_sources_args = CreateArguments {
+            sources: vec![
+                RrdPath::new("source").unwrap(),
+                RrdPath::new("source.rrd").unwrap(),
+            ],
+            ..valid_args
+        };
+        assert!(duplicate_sources_args.validate().is_err());
+    }
+
+    #[test]
+    fn test_create_arguments_validate_consistency() {
+        assert!(schema_for_tests().validate().is_ok());
+
+        let mut args = schema_for_tests();
+        args.data_sources[1].name = "ds1".to_string();
+        assert!(args.validate().is_err());
+
+        // Heartbeat smaller than the step
+        let mut args = schema_for_tests();
+        args.data_sources[0].heartbeat = 299;
+        assert!(args.validate().is_err());
+        args.data_sources[0].heartbeat = 300;
+        assert!(args.validate().is_ok());
+
+        // Same consolidation function and steps
+        let mut args = schema_for_tests();
+        args.round_robin_archives
+            .push(CreateArchive::Consolidated(CreateRoundRobinArchive {
+                consolidation_function: ConsolidationFunction::Average,
+                xfiles_factor: 0.1,
+                steps: 1,
+                rows: 600,
+                step: None,
+            }));
+        assert!(args.validate().is_err());
+
+        // Coarser archive keeping a shorter history
+        let mut args = schema_for_tests();
+        args.round_robin_archives
+            .push(CreateArchive::Consolidated(CreateRoundRobinArchive {
+                consolidation_function: ConsolidationFunction::Average,
+                xfiles_factor: 0.5,
+                steps: 12,
+                rows: 23,
+                step: None,
+            }));
+        assert!(args.validate().is_err());
+
+        // Same history with a coarser resolution
+        if let CreateArchive::Consolidated(rra) = &mut args.round_robin_archives[2] {
+            rra.rows = 24;
+        }
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_create_arguments_estimated_size() {
+        let args = schema_for_tests();
+        // Header of 1184 bytes, and 288 + 797 rows of 2 values
+        assert_eq!(args.estimated_size(), 1184 + (288 + 797) * 2 * 8);
+
+        // Holt-Winters archives created by rrdtool
+        let mut args = schema_for_tests();
+        args.round_robin_archives
+            .push(CreateArchive::HwPredict(CreateHwPredictArchive {
+                rows: 1440,
+                alpha: 0.1,
+                beta: 0.0035,
+                seasonal_period: 288,
+                seasonal_archive: None,
+            }));
+        assert_eq!(
+            args.estimated_size(),
+            128 + 2 * (120 + 112)
+                + 7 * (120 + 8)
+                + 16
+                + 7 * 2 * 80
+                + (288 + 797 + 3 * 1440 + 2 * 288) * 2 * 8
+        );
     }
 
     // Test for CreateArguments to_str method
     #[test]
     fn test_create_arguments_to_str() {
         let args = CreateArguments {
-            path: "test_path".to_string(),
+            path: RrdPath::new("test_path").unwrap(),
             data_sources: vec![CreateDataSource {
                 name: "ds1".to_string(),
                 minimum: Some(0.0),
                 maximum: Some(100.0),
                 heartbeat: 300,
                 serie_type: CreateDataSourceType::Gauge,
+                expression: None,
             }],
-            round_robin_archives: vec![CreateRoundRobinArchive {
+            round_robin_archives: vec![CreateArchive::Consolidated(CreateRoundRobinArchive {
                 consolidation_function: ConsolidationFunction::Average,
                 xfiles_factor: 0.5,
                 steps: 1,
                 rows: 100,
-            }],
+                step: None,
+            })],
             start_timestamp: 1609459200,
             step_seconds: 300,
+            no_overwrite: false,
+            sources: vec![],
+            template: None,
         };
         let expected_str =
             "test_path.rrd -s 300 -b 1609459200 DS:ds1:GAUGE:300:0:100 RRA:AVERAGE:0.5:1:100";
         assert_eq!(args.to_str(), expected_str);
+
+        let args = CreateArguments {
+            no_overwrite: true,
+            sources: vec![
+                RrdPath::new("old/a").unwrap(),
+                RrdPath::new("old/b").unwrap(),
+            ],
+            template: Some(RrdPath::new("template").unwrap()),
+            ..args
+        };
+        let expected_str = "test_path.rrd -s 300 -b 1609459200 -O -r old/a.rrd -r old/b.rrd -t template.rrd DS:ds1:GAUGE:300:0:100 RRA:AVERAGE:0.5:1:100";
+        assert_eq!(args.to_str(), expected_str);
+    }
+
+    fn schema_for_tests() -> CreateArguments {
+        CreateArguments {
+            path: RrdPath::new("test_path").unwrap(),
+            data_sources: vec![
+                CreateDataSource {
+                    name: "ds1".to_string(),
+                    minimum: Some(0.0),
+                    maximum: Some(100.0),
+                    heartbeat: 600,
+                    serie_type: CreateDataSourceType::Gauge,
+                    expression: None,
+                },
+                CreateDataSource {
+                    name: "ds2".to_string(),
+                    minimum: None,
+                    maximum: None,
+                    heartbeat: 600,
+                    serie_type: CreateDataSourceType::Counter,
+                    expression: None,
+                },
+            ],
+            round_robin_archives: vec![
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
+                    consolidation_function: ConsolidationFunction::Average,
+                    xfiles_factor: 0.5,
+                    steps: 1,
+                    rows: 288,
+                    step: None,
+                }),
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
+                    consolidation_function: ConsolidationFunction::Max,
+                    xfiles_factor: 0.5,
+                    steps: 12,
+                    rows: 797,
+                    step: None,
+                }),
+            ],
+            start_timestamp: 1609459200,
+            step_seconds: 300,
+            no_overwrite: false,
+            sources: vec![],
+            template: None,
+        }
+    }
+
+    #[test]
+    fn test_create_arguments_step() {
+        let mut args = schema_for_tests();
+        assert_eq!(args.step().unwrap().to_string(), "5m");
+        args.set_step("1m".parse().unwrap());
+        assert_eq!(args.step_seconds, 60);
+
+        // Archives described by durations must use the step of the RRD
+        let archive = |step: &str| {
+            CreateArchive::from(
+                CreateRoundRobinArchive::with_durations(
+                    ConsolidationFunction::Min,
+                    0.5,
+                    step.parse().unwrap(),
+                    "1h".parse().unwrap(),
+                    "1y".parse().unwrap(),
+                )
+                .unwrap(),
+            )
+        };
+        let mut args = schema_for_tests();
+        args.round_robin_archives.push(archive("1m"));
+        assert!(args.validate().is_err());
+        args.set_step("1m".parse().unwrap());
+        assert!(args.validate().is_ok());
+
+        let mut args = schema_for_tests();
+        args.round_robin_archives.push(archive("5m"));
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_create_arguments_from_info() {
+        let lines = "filename 2 test.rrd
+rrd_version 2 0003
+step 1 300
+last_update 1 1700000000
+header_size 1 1208
+ds[ds1].index 1 0
+ds[ds1].type 2 GAUGE
+ds[ds1].minimal_heartbeat 1 600
+ds[ds1].min 0 0.0000000000e+00
+ds[ds1].max 0 1.0000000000e+02
+ds[ds1].last_ds 2 U
+ds[ds1].value 0 NaN
+ds[ds1].unknown_sec 1 0
+ds[ds2].index 1 1
+ds[ds2].type 2 COUNTER
+ds[ds2].minimal_heartbeat 1 600
+ds[ds2].min 0 NaN
+ds[ds2].max 0 NaN
+ds[ds2].last_ds 2 U
+ds[ds2].value 0 NaN
+ds[ds2].unknown_sec 1 0
+rra[0].cf 2 AVERAGE
+rra[0].rows 1 288
+rra[0].cur_row 1 12
+rra[0].pdp_per_row 1 1
+rra[0].xff 0 5.0000000000e-01
+rra[1].cf 2 MAX
+rra[1].rows 1 797
+rra[1].cur_row 1 3
+rra[1].pdp_per_row 1 12
+rra[1].xff 0 5.0000000000e-01
+"
+        .lines()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        let info = RrdInfo::from_lines(lines).unwrap();
+
+        let args = CreateArguments::from_info(RrdPath::new("copy").unwrap(), &info).unwrap();
+        assert_eq!(args.path.name(), "copy");
+        assert_eq!(args.start_timestamp, 1700000000);
+        assert!(args.same_schema(&schema_for_tests()));
+        assert_eq!(
+            args.to_str(),
+            "copy.rrd -s 300 -b 1700000000 DS:ds1:GAUGE:600:0:100 DS:ds2:COUNTER:600:U:U RRA:AVERAGE:0.5:1:288 RRA:MAX:0.5:12:797"
+        );
+    }
+
+    #[test]
+    fn test_create_arguments_from_existing_layout_info() {
+        // rrdtool accepts a heartbeat shorter than the step,
+        // and archives of the same consolidation function
+        // keeping the same history
+        let lines = "filename 2 test.rrd
+rrd_version 2 0003
+step 1 300
+last_update 1 1700000000
+header_size 1 1208
+ds[ds1].index 1 0
+ds[ds1].type 2 GAUGE
+ds[ds1].minimal_heartbeat 1 120
+ds[ds1].min 0 NaN
+ds[ds1].max 0 NaN
+ds[ds1].last_ds 2 U
+ds[ds1].value 0 NaN
+ds[ds1].unknown_sec 1 0
+rra[0].cf 2 AVERAGE
+rra[0].rows 1 288
+rra[0].cur_row 1 12
+rra[0].pdp_per_row 1 1
+rra[0].xff 0 5.0000000000e-01
+rra[1].cf 2 AVERAGE
+rra[1].rows 1 24
+rra[1].cur_row 1 3
+rra[1].pdp_per_row 1 12
+rra[1].xff 0 5.0000000000e-01
+"
+        .lines()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        let info = RrdInfo::from_lines(lines).unwrap();
+
+        let args = CreateArguments::from_info(RrdPath::new("copy").unwrap(), &info).unwrap();
+        assert_eq!(
+            args.to_str(),
+            "copy.rrd -s 300 -b 1700000000 DS:ds1:GAUGE:120:U:U RRA:AVERAGE:0.5:1:288 RRA:AVERAGE:0.5:12:24"
+        );
+        // But not for a new RRD
+        assert!(matches!(
+            args.validate(),
+            Err(RRDCachedClientError::InvalidCreateDataSerie(message)) if message.contains("heartbeat")
+        ));
+
+        let mut args = args;
+        args.data_sources[0].heartbeat = 600;
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_create_arguments_schema_differences() {
+        let expected = schema_for_tests();
+
+        let actual = CreateArguments {
+            path: RrdPath::new("other").unwrap(),
+            start_timestamp: 0,
+            ..schema_for_tests()
+        };
+        assert!(expected.same_schema(&actual));
+
+        // RRA order doesn't matter
+        let mut actual = schema_for_tests();
+        actual.round_robin_archives.reverse();
+        assert!(expected.same_schema(&actual));
+
+        // DS order matters
+        let mut actual = schema_for_tests();
+        actual.data_sources.reverse();
+        assert_eq!(
+            expected.schema_differences(&actual),
+            vec![SchemaDifference::DataSourceOrder {
+                expected: vec!["ds1".to_string(), "ds2".to_string()],
+                actual: vec!["ds2".to_string(), "ds1".to_string()],
+            }]
+        );
+
+        let mut actual = schema_for_tests();
+        actual.step_seconds = 60;
+        actual.data_sources[0].heartbeat = 120;
+        actual.data_sources[1].name = "ds3".to_string();
+        if let CreateArchive::Consolidated(rra) = &mut actual.round_robin_archives[1] {
+            rra.rows = 100;
+        }
+        assert_eq!(
+            expected.schema_differences(&actual),
+            vec![
+                SchemaDifference::Step {
+                    expected: 300,
+                    actual: 60
+                },
+                SchemaDifference::DataSource {
+                    expected: expected.data_sources[0].clone(),
+                    actual: actual.data_sources[0].clone(),
+                },
+                SchemaDifference::MissingDataSource("ds2".to_string()),
+                SchemaDifference::UnexpectedDataSource("ds3".to_string()),
+                SchemaDifference::MissingRoundRobinArchive(
+                    expected.round_robin_archives[1].clone()
+                ),
+                SchemaDifference::UnexpectedRoundRobinArchive(
+                    actual.round_robin_archives[1].clone()
+                ),
+            ]
+        );
+    }
+
+    fn holt_winters_schema_for_tests() -> CreateArguments {
+        CreateArguments {
+            round_robin_archives: vec![
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
+                    consolidation_function: ConsolidationFunction::Average,
+                    xfiles_factor: 0.5,
+                    steps: 1,
+                    rows: 2016,
+                    step: None,
+                }),
+                CreateArchive::HwPredict(CreateHwPredictArchive {
+                    rows: 1440,
+                    alpha: 0.1,
+                    beta: 0.0035,
+                    seasonal_period: 288,
+                    seasonal_archive: Some(2),
+                }),
+                CreateArchive::Seasonal(CreateSeasonalArchive {
+                    seasonal_period: 288,
+                    gamma: 0.1,
+                    predict_archive: 1,
+                    smoothing_window: None,
+                }),
+                CreateArchive::DevPredict(CreateDevPredictArchive {
+                    rows: 1440,
+                    dev_seasonal_archive: 4,
+                }),
+                CreateArchive::DevSeasonal(CreateSeasonalArchive {
+                    seasonal_period: 288,
+                    gamma: 0.1,
+                    predict_archive: 1,
+                    smoothing_window: None,
+                }),
+                CreateArchive::Failures(CreateFailuresArchive {
+                    rows: 288,
+                    threshold: 7,
+                    window_length: 9,
+                    dev_seasonal_archive: 4,
+                }),
+            ],
+            ..schema_for_tests()
+        }
+    }
+
+    #[test]
+    fn test_create_arguments_holt_winters() {
+        let args = holt_winters_schema_for_tests();
+        assert!(args.validate().is_ok());
+        assert_eq!(
+            args.to_str(),
+            "test_path.rrd -s 300 -b 1609459200 DS:ds1:GAUGE:600:0:100 DS:ds2:COUNTER:600:U:U RRA:AVERAGE:0.5:1:2016 RRA:HWPREDICT:1440:0.1:0.0035:288:3 RRA:SEASONAL:288:0.1:2 RRA:DEVPREDICT:1440:5 RRA:DEVSEASONAL:288:0.1:2 RRA:FAILURES:288:7:9:5"
+        );
+
+        // Holt-Winters functions can't be used in consolidated archives
+        let rra = CreateRoundRobinArchive {
+            consolidation_function: ConsolidationFunction::HwPredict,
+            xfiles_factor: 0.5,
+            steps: 1,
+            rows: 100,
+            step: None,
+        };
+        assert!(rra.validate().is_err());
+    }
+
+    #[test]
+    fn test_create_arguments_holt_winters_references() {
+        // Reference to a missing archive
+        let mut args = holt_winters_schema_for_tests();
+        if let CreateArchive::HwPredict(predict) = &mut args.round_robin_archives[1] {
+            predict.seasonal_archive = Some(10);
+        }
+        assert!(args.validate().is_err());
+
+        // Reference to an archive of the wrong type
+        let mut args = holt_winters_schema_for_tests();
+        if let CreateArchive::Failures(failures) = &mut args.round_robin_archives[5] {
+            failures.dev_seasonal_archive = 2;
+        }
+        assert!(args.validate().is_err());
+
+        let mut args = holt_winters_schema_for_tests();
+        if let CreateArchive::Seasonal(seasonal) = &mut args.round_robin_archives[2] {
+            seasonal.predict_archive = 0;
+        }
+        assert!(args.validate().is_err());
+
+        // Different seasonal periods
+        let mut args = holt_winters_schema_for_tests();
+        if let CreateArchive::DevSeasonal(seasonal) = &mut args.round_robin_archives[4] {
+            seasonal.seasonal_period = 144;
+        }
+        assert!(args.validate().is_err());
+
+        // rrdtool creates the other archives
+        let args = CreateArguments {
+            round_robin_archives: vec![CreateArchive::MhwPredict(CreateHwPredictArchive {
+                rows: 1440,
+                alpha: 0.1,
+                beta: 0.0035,
+                seasonal_period: 288,
+                seasonal_archive: None,
+            })],
+            ..schema_for_tests()
+        };
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_create_arguments_from_holt_winters_info() {
+        let lines = "step 1 300
+last_update 1 1700000000
+ds[ds1].type 2 GAUGE
+ds[ds1].minimal_heartbeat 1 600
+ds[ds1].min 0 0.0000000000e+00
+ds[ds1].max 0 1.0000000000e+02
+ds[ds2].type 2 COUNTER
+ds[ds2].minimal_heartbeat 1 600
+ds[ds2].min 0 NaN
+ds[ds2].max 0 NaN
+rra[0].cf 2 AVERAGE
+rra[0].rows 1 2016
+rra[0].pdp_per_row 1 1
+rra[0].xff 0 5.0000000000e-01
+rra[1].cf 2 HWPREDICT
+rra[1].rows 1 1440
+rra[1].pdp_per_row 1 1
+rra[1].alpha 0 1.0000000000e-01
+rra[1].beta 0 3.5000000000e-03
+rra[2].cf 2 SEASONAL
+rra[2].rows 1 288
+rra[2].pdp_per_row 1 1
+rra[2].gamma 0 1.0000000000e-01
+rra[2].smoothing_window 0 5.0000000000e-02
+rra[3].cf 2 DEVPREDICT
+rra[3].rows 1 1440
+rra[3].pdp_per_row 1 1
+rra[4].cf 2 DEVSEASONAL
+rra[4].rows 1 288
+rra[4].pdp_per_row 1 1
+rra[4].gamma 0 1.0000000000e-01
+rra[4].smoothing_window 0 5.0000000000e-02
+rra[5].cf 2 FAILURES
+rra[5].rows 1 288
+rra[5].pdp_per_row 1 1
+rra[5].delta_pos 0 2.0000000000e+00
+rra[5].delta_neg 0 2.0000000000e+00
+rra[5].failure_threshold 1 7
+rra[5].window_length 1 9
+"
+        .lines()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        let info = RrdInfo::from_lines(lines).unwrap();
+
+        let args = CreateArguments::from_info(RrdPath::new("copy").unwrap(), &info).unwrap();
+        assert_eq!(
+            args.schema_differences(&holt_winters_schema_for_tests()),
+            vec![]
+        );
+        assert_eq!(
+            args.round_robin_archives[2],
+            CreateArchive::Seasonal(CreateSeasonalArchive {
+                seasonal_period: 288,
+                gamma: 0.1,
+                predict_archive: 1,
+                smoothing_window: Some(0.05),
+            })
+        );
+
+        // The references can't be found when there are several candidates
+        let mut info = info;
+        let seasonal = info.round_robin_archives[2].clone();
+        info.round_robin_archives.push(seasonal);
+        assert!(CreateArguments::from_info(RrdPath::new("copy").unwrap(), &info).is_err());
+    }
+
+    #[test]
+    fn test_compute_data_source() {
+        let expression = RpnExpression::data_source("ds1") * 8.0;
+        let ds = CreateDataSource::compute("bits", expression.clone());
+        assert!(ds.validate().is_ok());
+        assert_eq!(ds.to_str(), "DS:bits:COMPUTE:ds1,8,*");
+
+        let invalid = CreateDataSource {
+            expression: None,
+            ..ds.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateDataSource {
+            expression: Some(expression),
+            ..schema_for_tests().data_sources[0].clone()
+        };
+        assert!(invalid.validate().is_err());
+
+        // rrdtool refuses fractional constants in COMPUTE data sources
+        let invalid = CreateDataSource::compute("bits", RpnExpression::data_source("ds1") * 0.5);
+        assert!(matches!(
+            invalid.validate(),
+            Err(RRDCachedClientError::InvalidRpnExpression(_))
+        ));
+
+        let mut args = schema_for_tests();
+        args.data_sources.push(ds);
+        assert!(args.validate().is_ok());
+        assert!(args.to_str().contains(" DS:bits:COMPUTE:ds1,8,* "));
+
+        // Unknown data source
+        let mut invalid = args.clone();
+        invalid.data_sources[2].expression = Some(RpnExpression::data_source("ds4") * 8.0);
+        assert!(invalid.validate().is_err());
+
+        // Self reference
+        let mut invalid = args.clone();
+        invalid.data_sources[2].expression = Some(RpnExpression::data_source("bits") * 8.0);
+        assert!(invalid.validate().is_err());
+
+        // Data source defined after
+        let mut invalid = args;
+        invalid.data_sources.swap(0, 2);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_create_arguments_from_compute_info() {
+        let lines = "filename 2 test.rrd
+rrd_version 2 0003
+step 1 300
+last_update 1 1700000000
+header_size 1 1208
+ds[ds1].index 1 0
+ds[ds1].type 2 GAUGE
+ds[ds1].minimal_heartbeat 1 600
+ds[ds1].min 0 0.0000000000e+00
+ds[ds1].max 0 1.0000000000e+02
+ds[ds1].last_ds 2 U
+ds[ds1].value 0 NaN
+ds[ds1].unknown_sec 1 0
+ds[ds2].index 1 1
+ds[ds2].type 2 COUNTER
+ds[ds2].minimal_heartbeat 1 600
+ds[ds2].min 0 NaN
+ds[ds2].max 0 NaN
+ds[ds2].last_ds 2 U
+ds[ds2].value 0 NaN
+ds[ds2].unknown_sec 1 0
+ds[total].index 1 2
+ds[total].type 2 COMPUTE
+ds[total].cdef 2 ds1,ds2,ADDNAN,8.000000,*
+ds[total].last_ds 2 U
+ds[total].value 0 NaN
+ds[total].unknown_sec 1 0
+rra[0].cf 2 AVERAGE
+rra[0].rows 1 288
+rra[0].cur_row 1 12
+rra[0].pdp_per_row 1 1
+rra[0].xff 0 5.0000000000e-01
+rra[1].cf 2 MAX
+rra[1].rows 1 797
+rra[1].cur_row 1 3
+rra[1].pdp_per_row 1 12
+rra[1].xff 0 5.0000000000e-01
+"
+        .lines()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        let info = RrdInfo::from_lines(lines).unwrap();
+        assert_eq!(
+            info.data_sources[2].cdef.as_deref(),
+            Some("ds1,ds2,ADDNAN,8.000000,*")
+        );
+
+        let args = CreateArguments::from_info(RrdPath::new("copy").unwrap(), &info).unwrap();
+        let mut expected = schema_for_tests();
+        expected.data_sources.push(CreateDataSource::compute(
+            "total",
+            RpnExpression::data_source("ds1").add_nan(RpnExpression::data_source("ds2")) * 8.0,
+        ));
+        assert_eq!(expected.schema_differences(&args), vec![]);
+
+        // The heartbeat of COMPUTE data sources is ignored
+        expected.data_sources[2].heartbeat = 600;
+        assert!(expected.same_schema(&args));
+
+        expected.data_sources[2].expression = Some(RpnExpression::data_source("ds1") * 8.0);
+        assert!(!expected.same_schema(&args));
     }
 }
@@ -0,0 +1,516 @@
+use crate::{
+    consolidation_function::ConsolidationFunction,
+    create::{
+        CreateArchive, CreateArguments, CreateDataSource, CreateDataSourceType,
+        CreateRoundRobinArchive,
+    },
+    errors::RRDCachedClientError,
+    now::now_timestamp,
+    rpn::RpnExpression,
+    rrd_duration::RrdDuration,
+    rrd_path::RrdPath,
+};
+
+/// Step of the RRD when not set, as in rrdtool.
+pub const DEFAULT_STEP_SECONDS: u64 = 300;
+
+/// Ratio of unknown data points accepted in the archives added
+/// by durations or presets, when not set.
+pub const DEFAULT_XFILES_FACTOR: f64 = 0.5;
+
+/// Consolidation functions of the archives added by presets.
+const PRESET_CONSOLIDATION_FUNCTIONS: [ConsolidationFunction; 3] = [
+    ConsolidationFunction::Average,
+    ConsolidationFunction::Min,
+    ConsolidationFunction::Max,
+];
+
+/// Common sets of round robin archives, from a fine resolution
+/// kept for a short time to a coarse one kept for a long time.
+///
+/// Each resolution gets an AVERAGE, a MIN and a MAX archive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetentionPreset {
+    /// 1 day at 10s, 1 month at 1m, 1 year at 1h and 10 years at 1d.
+    ///
+    /// Suggests a step of 10s.
+    HighResolution,
+
+    /// 2 days at 5m, 2 weeks at 30m, 2 months at 2h and 2 years at 1d,
+    /// as in MRTG.
+    ///
+    /// Suggests a step of 5m.
+    Standard,
+}
+
+impl RetentionPreset {
+    /// Step matching the finest resolution of the preset.
+    pub fn step(self) -> RrdDuration {
+        self.resolutions()[0].0
+    }
+
+    /// Resolutions and retentions of the archives, from the finest to the coarsest.
+    pub fn resolutions(self) -> Vec<(RrdDuration, RrdDuration)> {
+        let tiers: &[(&str, &str)] = match self {
+            RetentionPreset::HighResolution => {
+                &[("10s", "1d"), ("1m", "1M"), ("1h", "1y"), ("1d", "10y")]
+            }
+            RetentionPreset::Standard => &[("5m", "2d"), ("30m", "2w"), ("2h", "2M"), ("1d", "2y")],
+        };
+        tiers
+            .iter()
+            .map(|(resolution, retention)| {
+                (
+                    resolution.parse().expect("valid preset resolution"),
+                    retention.parse().expect("valid preset retention"),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Data source added by the builder, whose heartbeat may
+/// depend on the step known at build time.
+#[derive(Debug)]
+struct BuilderDataSource {
+    data_source: CreateDataSource,
+    default_heartbeat: bool,
+}
+
+/// Archive added by the builder, whose steps and rows may
+/// depend on the step known at build time.
+#[derive(Debug)]
+enum BuilderArchive {
+    Archive(CreateArchive),
+    Durations {
+        consolidation_function: ConsolidationFunction,
+        resolution: RrdDuration,
+        retention: RrdDuration,
+    },
+    Preset(RetentionPreset),
+}
+
+/// Builder of [CreateArguments], created by [CreateArguments::builder].
+///
+/// The step, heartbeats and archives described by durations are resolved
+/// by [Self::build], so the methods can be called in any order.
+#[derive(Debug)]
+pub struct CreateArgumentsBuilder {
+    path: Result<RrdPath, RRDCachedClientError>,
+    step_seconds: Option<u64>,
+    start_timestamp: Option<u64>,
+    heartbeat: Option<i64>,
+    xfiles_factor: f64,
+    data_sources: Vec<BuilderDataSource>,
+    archives: Vec<BuilderArchive>,
+    no_overwrite: bool,
+    sources: Vec<RrdPath>,
+    template: Option<RrdPath>,
+}
+
+impl CreateArgumentsBuilder {
+    pub(crate) fn new(path: &str) -> CreateArgumentsBuilder {
+        CreateArgumentsBuilder {
+            path: RrdPath::new(path),
+            step_seconds: None,
+            start_timestamp: None,
+            heartbeat: None,
+            xfiles_factor: DEFAULT_XFILES_FACTOR,
+            data_sources: Vec::new(),
+            archives: Vec::new(),
+            no_overwrite: false,
+            sources: Vec::new(),
+            template: None,
+        }
+    }
+
+    /// Step between two data points, like `10s`.
+    ///
+    /// Defaults to the step of the first preset, or [DEFAULT_STEP_SECONDS].
+    pub fn step(mut self, step: RrdDuration) -> Self {
+        self.step_seconds = Some(step.as_secs());
+        self
+    }
+
+    /// Number of seconds between two data points, see [Self::step].
+    pub fn step_seconds(mut self, seconds: u64) -> Self {
+        self.step_seconds = Some(seconds);
+        self
+    }
+
+    /// Start time of the first data point. Defaults to 10 seconds ago.
+    pub fn start_timestamp(mut self, timestamp: u64) -> Self {
+        self.start_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Heartbeat of the data sources added with [Self::gauge], [Self::counter],
+    /// [Self::derive] and [Self::absolute]. Defaults to twice the step.
+    pub fn heartbeat(mut self, seconds: i64) -> Self {
+        self.heartbeat = Some(seconds);
+        self
+    }
+
+    /// Ratio of unknown data points accepted in the archives added with
+    /// [Self::rra_with_durations] and [Self::preset]. Defaults to [DEFAULT_XFILES_FACTOR].
+    pub fn xfiles_factor(mut self, xfiles_factor: f64) -> Self {
+        self.xfiles_factor = xfiles_factor;
+        self
+    }
+
+    /// Add a data source with all its parameters.
+    pub fn data_source(mut self, data_source: CreateDataSource) -> Self {
+        self.data_sources.push(BuilderDataSource {
+            data_source,
+            default_heartbeat: false,
+        });
+        self
+    }
+
+    fn typed_data_source(
+        mut self,
+        serie_type: CreateDataSourceType,
+        name: &str,
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    ) -> Self {
+        self.data_sources.push(BuilderDataSource {
+            data_source: CreateDataSource {
+                name: name.to_string(),
+                minimum,
+                maximum,
+                heartbeat: 0,
+                serie_type,
+                expression: None,
+            },
+            default_heartbeat: true,
+        });
+        self
+    }
+
+    /// Add a GAUGE data source, for values stored as they are, like temperatures.
+    pub fn gauge(self, name: &str, minimum: Option<f64>, maximum: Option<f64>) -> Self {
+        self.typed_data_source(CreateDataSourceType::Gauge, name, minimum, maximum)
+    }
+
+    /// Add a COUNTER data source, for ever increasing counters
+    /// stored as rates, like the bytes received by a network interface.
+    pub fn counter(self, name: &str, minimum: Option<f64>, maximum: Option<f64>) -> Self {
+        self.typed_data_source(CreateDataSourceType::Counter, name, minimum, maximum)
+    }
+
+    /// Add a DERIVE data source, for counters that may decrease, stored as rates.
+    pub fn derive(self, name: &str, minimum: Option<f64>, maximum: Option<f64>) -> Self {
+        self.typed_data_source(CreateDataSourceType::Derive, name, minimum, maximum)
+    }
+
+    /// Add an ABSOLUTE data source, for counters reset on each read, stored as rates.
+    pub fn absolute(self, name: &str, minimum: Option<f64>, maximum: Option<f64>) -> Self {
+        self.typed_data_source(CreateDataSourceType::Absolute, name, minimum, maximum)
+    }
+
+    /// Add a COMPUTE data source, calculated from the data sources added before it.
+    pub fn compute(self, name: &str, expression: RpnExpression) -> Self {
+        self.data_source(CreateDataSource::compute(name, expression))
+    }
+
+    /// Add a round robin archive with all its parameters.
+    pub fn rra<A: Into<CreateArchive>>(mut self, archive: A) -> Self {
+        self.archives.push(BuilderArchive::Archive(archive.into()));
+        self
+    }
+
+    /// Add a round robin archive described by durations,
+    /// see [CreateRoundRobinArchive::with_durations].
+    pub fn rra_with_durations(
+        mut self,
+        consolidation_function: ConsolidationFunction,
+        resolution: RrdDuration,
+        retention: RrdDuration,
+    ) -> Self {
+        self.archives.push(BuilderArchive::Durations {
+            consolidation_function,
+            resolution,
+            retention,
+        });
+        self
+    }
+
+    /// Add the round robin archives of a preset.
+    ///
+    /// Its resolutions must be multiples of the step.
+    pub fn preset(mut self, preset: RetentionPreset) -> Self {
+        self.archives.push(BuilderArchive::Preset(preset));
+        self
+    }
+
+    /// Fail if the RRD file already exists.
+    pub fn no_overwrite(mut self) -> Self {
+        self.no_overwrite = true;
+        self
+    }
+
+    /// Add an existing RRD to prefill the data from.
+    pub fn source(mut self, source: RrdPath) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Use an existing RRD as a template.
+    pub fn template(mut self, template: RrdPath) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// Build the arguments and check that they are valid.
+    pub fn build(self) -> Result<CreateArguments, RRDCachedClientError> {
+        let path = self.path?;
+        let step_seconds = self
+            .step_seconds
+            .or_else(|| {
+                self.archives.iter().find_map(|archive| match archive {
+                    BuilderArchive::Preset(preset) => Some(preset.step().as_secs()),
+                    _ => None,
+                })
+            })
+            .unwrap_or(DEFAULT_STEP_SECONDS);
+        let step = RrdDuration::from_secs(step_seconds)?;
+        let heartbeat = self.heartbeat.unwrap_or(2 * step_seconds as i64);
+        let start_timestamp = match self.start_timestamp {
+            Some(timestamp) => timestamp,
+            None => now_timestamp()? as u64 - 10,
+        };
+
+        let data_sources = self
+            .data_sources
+            .into_iter()
+            .map(|ds| {
+                if ds.default_heartbeat {
+                    CreateDataSource {
+                        heartbeat,
+                        ..ds.data_source
+                    }
+                } else {
+                    ds.data_source
+                }
+            })
+            .collect();
+
+        let mut round_robin_archives = Vec::new();
+        for archive in self.archives {
+            match archive {
+                BuilderArchive::Archive(archive) => round_robin_archives.push(archive),
+                BuilderArchive::Durations {
+                    consolidation_function,
+                    resolution,
+                    retention,
+                } => round_robin_archives.push(
+                    CreateRoundRobinArchive::with_durations(
+                        consolidation_function,
+                        self.xfiles_factor,
+                        step,
+                        resolution,
+                        retention,
+                    )?
+                    .into(),
+                ),
+                BuilderArchive::Preset(preset) => {
+                    for (resolution, retention) in preset.resolutions() {
+                        for consolidation_function in PRESET_CONSOLIDATION_FUNCTIONS {
+                            round_robin_archives.push(
+                                CreateRoundRobinArchive::with_durations(
+                                    consolidation_function,
+                                    self.xfiles_factor,
+                                    step,
+                                    resolution,
+                                    retention,
+                                )?
+                                .into(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let arguments = CreateArguments {
+            path,
+            data_sources,
+            round_robin_archives,
+            start_timestamp,
+            step_seconds,
+            no_overwrite: self.no_overwrite,
+            sources: self.sources,
+            template: self.template,
+        };
+        arguments.validate()?;
+        Ok(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let arguments = CreateArguments::builder("sensors/temperature")
+            .step("10s".parse().unwrap())
+            .start_timestamp(1609459200)
+            .gauge("temp", Some(-40.0), Some(60.0))
+            .counter("rx", Some(0.0), None)
+            .rra(CreateRoundRobinArchive {
+                consolidation_function: ConsolidationFunction::Last,
+                xfiles_factor: 0.5,
+                steps: 1,
+                rows: 10,
+                step: None,
+            })
+            .rra_with_durations(
+                ConsolidationFunction::Average,
+                "1m".parse().unwrap(),
+                "1d".parse().unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            arguments.to_str(),
+            "sensors/temperature.rrd -s 10 -b 1609459200 DS:temp:GAUGE:20:-40:60 DS:rx:COUNTER:20:0:U RRA:LAST:0.5:1:10 RRA:AVERAGE:0.5:6:1440"
+        );
+
+        let arguments = CreateArguments::builder("test")
+            .heartbeat(600)
+            .gauge("a", None, None)
+            .data_source(CreateDataSource {
+                name: "b".to_string(),
+                minimum: None,
+                maximum: None,
+                heartbeat: 900,
+                serie_type: CreateDataSourceType::Gauge,
+                expression: None,
+            })
+            .compute(
+                "c",
+                RpnExpression::data_source("a") + RpnExpression::data_source("b"),
+            )
+            .xfiles_factor(0.1)
+            .rra_with_durations(
+                ConsolidationFunction::Max,
+                "1h".parse().unwrap(),
+                "1w".parse().unwrap(),
+            )
+            .no_overwrite()
+            .build()
+            .unwrap();
+        assert_eq!(arguments.step_seconds, DEFAULT_STEP_SECONDS);
+        assert!(arguments.start_timestamp > 1609459200);
+        assert!(arguments.no_overwrite);
+        assert_eq!(arguments.data_sources[0].heartbeat, 600);
+        assert_eq!(arguments.data_sources[1].heartbeat, 900);
+        assert_eq!(arguments.data_sources[2].to_str(), "DS:c:COMPUTE:a,b,+");
+        assert_eq!(
+            arguments.round_robin_archives[0].to_str(),
+            "RRA:MAX:0.1:12:168"
+        );
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        // Invalid path
+        assert!(CreateArguments::builder("../test")
+            .gauge("a", None, None)
+            .preset(RetentionPreset::Standard)
+            .build()
+            .is_err());
+
+        // No data source
+        assert!(CreateArguments::builder("test")
+            .preset(RetentionPreset::Standard)
+            .build()
+            .is_err());
+
+        // No archive
+        assert!(CreateArguments::builder("test")
+            .gauge("a", None, None)
+            .build()
+            .is_err());
+
+        // Resolution not a multiple of the step
+        assert!(CreateArguments::builder("test")
+            .step_seconds(7)
+            .gauge("a", None, None)
+            .preset(RetentionPreset::HighResolution)
+            .build()
+            .is_err());
+
+        // Invalid data source
+        assert!(CreateArguments::builder("test")
+            .gauge("a", Some(10.0), Some(0.0))
+            .preset(RetentionPreset::Standard)
+            .build()
+            .is_err());
+
+        // Zero step
+        assert!(CreateArguments::builder("test")
+            .step_seconds(0)
+            .gauge("a", None, None)
+            .preset(RetentionPreset::Standard)
+            .build()
+            .is_err());
+
+        // Heartbeat shorter than the step
+        assert!(CreateArguments::builder("test")
+            .heartbeat(60)
+            .gauge("a", None, None)
+            .preset(RetentionPreset::Standard)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_presets() {
+        let arguments = CreateArguments::builder("test")
+            .gauge("temp", None, None)
+            .preset(RetentionPreset::HighResolution)
+            .build()
+            .unwrap();
+        assert_eq!(arguments.step_seconds, 10);
+        assert_eq!(arguments.data_sources[0].heartbeat, 20);
+        let archives: Vec<String> = arguments
+            .round_robin_archives
+            .iter()
+            .map(CreateArchive::to_str)
+            .collect();
+        assert_eq!(
+            archives,
+            vec![
+                "RRA:AVERAGE:0.5:1:8640",
+                "RRA:MIN:0.5:1:8640",
+                "RRA:MAX:0.5:1:8640",
+                "RRA:AVERAGE:0.5:6:44640",
+                "RRA:MIN:0.5:6:44640",
+                "RRA:MAX:0.5:6:44640",
+                "RRA:AVERAGE:0.5:360:8784",
+                "RRA:MIN:0.5:360:8784",
+                "RRA:MAX:0.5:360:8784",
+                "RRA:AVERAGE:0.5:8640:3660",
+                "RRA:MIN:0.5:8640:3660",
+                "RRA:MAX:0.5:8640:3660",
+            ]
+        );
+
+        // A preset with a coarser step than suggested
+        let arguments = CreateArguments::builder("test")
+            .step("1m".parse().unwrap())
+            .gauge("temp", None, None)
+            .preset(RetentionPreset::Standard)
+            .build()
+            .unwrap();
+        assert_eq!(arguments.step_seconds, 60);
+        assert_eq!(arguments.round_robin_archives.len(), 12);
+        assert_eq!(
+            arguments.round_robin_archives[0].to_str(),
+            "RRA:AVERAGE:0.5:5:576"
+        );
+    }
+}
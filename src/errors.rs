@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::{batch_update::RejectedSample, update_timestamp::UpdateTimestamp};
+
 #[derive(Error, Debug)]
 pub enum RRDCachedClientError {
     #[error("io error: {0}")]
@@ -10,16 +12,37 @@ pub enum RRDCachedClientError {
     UnexpectedResponse(i64, String),
     #[error("Invalid create data serie: {0}")]
     InvalidCreateDataSerie(String),
+    #[error("Invalid RRD path: {0}")]
+    InvalidRrdPath(String),
+    #[error("Invalid RPN expression: {0}")]
+    InvalidRpnExpression(String),
+    #[error("Invalid duration: {0}")]
+    InvalidDuration(String),
     #[error("Invalid data source name: {0}")]
     InvalidDataSourceName(String),
     #[error("Invalid batch update: {0}")]
     InvalidBatchUpdate(String),
     #[error("Batch Update Error Response: {0}")]
     BatchUpdateErrorResponse(String, Vec<String>),
+    /// The samples before the timestamp were sent, with the samples
+    /// rejected so far. The error happened on the line starting at
+    /// the timestamp, whose samples and the next ones were not applied.
+    #[error("Update failed from {1}: {2}")]
+    PartialUpdate(
+        Vec<RejectedSample>,
+        UpdateTimestamp,
+        Box<RRDCachedClientError>,
+    ),
     #[error("Unable to get system time")]
     SystemTimeError,
     #[error("Invalid fetch: {0}")]
     InvalidFetch(String),
     #[error("Invalid fetch header line: {0}")]
     InvalidFetchHeaderLine(String),
+    #[error("Invalid fetch line {0}, column {1}: {2}")]
+    InvalidFetchLine(usize, usize, String),
+    #[error("Invalid resample: {0}")]
+    InvalidResample(String),
+    #[error("Connection closed")]
+    ConnectionClosed,
 }
@@ -1,20 +1,196 @@
+use std::collections::HashMap;
+
 use crate::{
     errors::RRDCachedClientError,
-    parsers::{parse_fetch_header_line, parse_fetch_line},
+    parsers::{parse_fetch_binary_header_line, parse_fetch_header_line, parse_fetch_line},
 };
 
-#[derive(Debug, PartialEq)]
+/// Byte order of the values of a FETCHBIN response,
+/// the one of the machine running RRDCached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+/// Header of a column of a FETCHBIN response, followed by the values
+/// of the data source, like `DSName-ds1: BinaryData 10 8 LITTLEENDIAN`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryColumnHeader {
+    /// Name of the data source
+    pub name: String,
+
+    /// Number of values
+    pub count: usize,
+
+    /// Size of a value in bytes, 8 for a double and 4 for a float
+    pub value_size: usize,
+
+    pub byte_order: ByteOrder,
+}
+
+impl BinaryColumnHeader {
+    pub fn from_line(line: &str) -> Result<BinaryColumnHeader, RRDCachedClientError> {
+        let (name, count, value_size, byte_order) = parse_fetch_binary_header_line(line)?;
+        if value_size != 4 && value_size != 8 {
+            return Err(RRDCachedClientError::InvalidFetch(format!(
+                "unsupported value size {} for {}",
+                value_size, name
+            )));
+        }
+        Ok(BinaryColumnHeader {
+            name: name.to_string(),
+            count,
+            value_size,
+            byte_order: match byte_order {
+                "BIGENDIAN" => ByteOrder::BigEndian,
+                _ => ByteOrder::LittleEndian,
+            },
+        })
+    }
+
+    /// Check that the number of values doesn't exceed the rows of the response,
+    /// before allocating them.
+    pub fn check_count(&self, row_count: usize) -> Result<(), RRDCachedClientError> {
+        if self.count > row_count {
+            return Err(RRDCachedClientError::InvalidFetch(format!(
+                "expected at most {} values for {}, got {}",
+                row_count, self.name, self.count
+            )));
+        }
+        Ok(())
+    }
+
+    /// Number of bytes of the values following the header.
+    pub fn payload_len(&self) -> Result<usize, RRDCachedClientError> {
+        self.count.checked_mul(self.value_size).ok_or_else(|| {
+            RRDCachedClientError::InvalidFetch(format!("too many values for {}", self.name))
+        })
+    }
+
+    /// Decode the values following the header.
+    pub fn decode(&self, payload: &[u8]) -> Result<Vec<f64>, RRDCachedClientError> {
+        if payload.len() != self.payload_len()? {
+            return Err(RRDCachedClientError::InvalidFetch(format!(
+                "expected {} bytes for {}, got {}",
+                self.payload_len()?,
+                self.name,
+                payload.len()
+            )));
+        }
+        let values = payload
+            .chunks_exact(self.value_size)
+            .map(|chunk| match (self.value_size, self.byte_order) {
+                (4, ByteOrder::LittleEndian) => {
+                    f32::from_le_bytes(chunk.try_into().unwrap()) as f64
+                }
+                (4, ByteOrder::BigEndian) => f32::from_be_bytes(chunk.try_into().unwrap()) as f64,
+                (_, ByteOrder::LittleEndian) => f64::from_le_bytes(chunk.try_into().unwrap()),
+                (_, ByteOrder::BigEndian) => f64::from_be_bytes(chunk.try_into().unwrap()),
+            })
+            .collect();
+        Ok(values)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FetchResponse {
+    /// Version of the response format sent by RRDCached, not a count of
+    /// flushes: see [crate::fetch_request::FetchConsistency] to include
+    /// the pending updates.
     pub flush_version: u32,
     pub start: usize,
     pub end: usize,
     pub step: usize,
     pub ds_count: usize,
     pub ds_names: Vec<String>,
+
+    /// Rows of values, one per data source in the order of [Self::ds_names],
+    /// with their timestamp. Unknown values are NaN.
     pub data: Vec<(usize, Vec<f64>)>,
 }
 
 impl FetchResponse {
+    /// Index of a data source in the rows, by name.
+    pub fn ds_index(&self, name: &str) -> Option<usize> {
+        self.ds_names.iter().position(|ds_name| ds_name == name)
+    }
+
+    /// Number of rows between the start and the end, one per step.
+    pub fn row_count(&self) -> usize {
+        self.end
+            .saturating_sub(self.start)
+            .checked_div(self.step)
+            .unwrap_or(0)
+    }
+
+    /// Timestamps of the rows.
+    pub fn timestamps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.data.iter().map(|(timestamp, _)| *timestamp)
+    }
+
+    /// Time series of a data source, by name. Unknown values are `None`.
+    pub fn column(&self, name: &str) -> Option<Vec<(usize, Option<f64>)>> {
+        let index = self.ds_index(name)?;
+        Some(
+            self.data
+                .iter()
+                .map(|(timestamp, values)| (*timestamp, values.get(index).copied().and_then(known)))
+                .collect(),
+        )
+    }
+
+    /// Rows with their values by data source name. Unknown values are `None`.
+    pub fn rows(&self) -> impl Iterator<Item = (usize, HashMap<&str, Option<f64>>)> + '_ {
+        self.data.iter().map(|(timestamp, values)| {
+            let values = self
+                .ds_names
+                .iter()
+                .map(String::as_str)
+                .zip(values.iter().copied().map(known))
+                .collect();
+            (*timestamp, values)
+        })
+    }
+
+    /// Build a response from the header lines and the decoded
+    /// columns of a FETCHBIN response.
+    pub fn from_binary(
+        header_lines: Vec<String>,
+        columns: Vec<(String, Vec<f64>)>,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        let mut response = FetchResponse::from_lines(header_lines)?;
+        if !response.data.is_empty() || !response.ds_names.is_empty() {
+            return Err(RRDCachedClientError::InvalidFetch(
+                "unexpected text data in a binary response".to_string(),
+            ));
+        }
+        if columns.len() != response.ds_count {
+            return Err(RRDCachedClientError::InvalidFetch(format!(
+                "expected {} columns, got {}",
+                response.ds_count,
+                columns.len()
+            )));
+        }
+        let rows = columns.first().map_or(0, |(_, values)| values.len());
+        if let Some((name, _)) = columns.iter().find(|(_, values)| values.len() != rows) {
+            return Err(RRDCachedClientError::InvalidFetch(format!(
+                "expected {} values for {}",
+                rows, name
+            )));
+        }
+        response.data = (0..rows)
+            .map(|row| {
+                (
+                    response.start + (row + 1) * response.step,
+                    columns.iter().map(|(_, values)| values[row]).collect(),
+                )
+            })
+            .collect();
+        response.ds_names = columns.into_iter().map(|(name, _)| name).collect();
+        Ok(response)
+    }
+
     pub fn from_lines(lines: Vec<String>) -> Result<FetchResponse, RRDCachedClientError> {
         let mut flush_version = None;
         let mut start = None;
@@ -56,34 +232,27 @@ impl FetchResponse {
                 "DSName" => {
                     ds_names = Some(value.split_whitespace().map(|s| s.to_string()).collect());
                 }
-                _ => match parse_fetch_line(line) {
-                    Ok((_, (timestamp, values))) => {
-                        data.push((timestamp, values));
-                        index_data_start = Some(index);
-                        break;
-                    }
-                    Err(_) => {
-                        return Err(RRDCachedClientError::InvalidFetchHeaderLine(
-                            line.to_string(),
-                        ));
-                    }
-                },
+                _ if key.bytes().all(|b| b.is_ascii_digit()) => {
+                    data.push(parse_data_line(line, index + 1)?);
+                    index_data_start = Some(index);
+                    break;
+                }
+                _ => {
+                    return Err(RRDCachedClientError::InvalidFetchHeaderLine(
+                        line.to_string(),
+                    ));
+                }
             }
         }
 
         if let Some(index_data_start) = index_data_start {
-            for line in lines.iter().skip(index_data_start + 1) {
-                match parse_fetch_line(line) {
-                    Ok((_, (timestamp, values))) => {
-                        data.push((timestamp, values));
-                    }
-                    Err(_) => {
-                        return Err(RRDCachedClientError::InvalidFetch(line.to_string()));
-                    }
-                }
+            for (index, line) in lines.iter().enumerate().skip(index_data_start + 1) {
+                data.push(parse_data_line(line, index + 1)?);
             }
         }
 
+        check_rows(&data, start, step, ds_count)?;
+
         Ok(FetchResponse {
             flush_version: flush_version.unwrap_or(0),
             start: start.unwrap_or(0),
@@ -96,6 +265,69 @@ impl FetchResponse {
     }
 }
 
+/// Parse a data line, reporting the line number and the column of an error.
+///
+/// Lines are numbered from 1, after the status line of the response.
+fn parse_data_line(
+    line: &str,
+    line_number: usize,
+) -> Result<(usize, Vec<f64>), RRDCachedClientError> {
+    parse_fetch_line(line).map(|(_, row)| row).map_err(|error| {
+        let remaining = match error {
+            nom::Err::Error(error) | nom::Err::Failure(error) => error.input.len(),
+            nom::Err::Incomplete(_) => 0,
+        };
+        RRDCachedClientError::InvalidFetchLine(
+            line_number,
+            line.len() - remaining + 1,
+            line.trim_end().to_string(),
+        )
+    })
+}
+
+/// Check that the rows have one value per data source, and that their
+/// timestamps follow each other by step, starting one step after the start.
+///
+/// The checks depending on a missing header are skipped.
+fn check_rows(
+    data: &[(usize, Vec<f64>)],
+    start: Option<usize>,
+    step: Option<usize>,
+    ds_count: Option<usize>,
+) -> Result<(), RRDCachedClientError> {
+    for (index, (timestamp, values)) in data.iter().enumerate() {
+        if let Some(ds_count) = ds_count {
+            if values.len() != ds_count {
+                return Err(RRDCachedClientError::InvalidFetch(format!(
+                    "expected {} values at {}, got {}",
+                    ds_count,
+                    timestamp,
+                    values.len()
+                )));
+            }
+        }
+        if let (Some(start), Some(step)) = (start, step) {
+            let expected = start + (index + 1) * step;
+            if *timestamp != expected {
+                return Err(RRDCachedClientError::InvalidFetch(format!(
+                    "expected timestamp {}, got {}",
+                    expected, timestamp
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// NaN is how rrdtool represents unknown values.
+fn known(value: f64) -> Option<f64> {
+    if value.is_nan() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,6 +359,234 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    fn response_for_tests() -> FetchResponse {
+        let input = vec![
+            "FlushVersion: 1\n".to_string(),
+            "Start: 1708800030\n".to_string(),
+            "End: 1708800060\n".to_string(),
+            "Step: 10\n".to_string(),
+            "DSCount: 2\n".to_string(),
+            "DSName: ds1 ds2\n".to_string(),
+            "1708800040: 1 2\n".to_string(),
+            "1708800050: -nan 3\n".to_string(),
+            "1708800060: 4 -nan\n".to_string(),
+        ];
+        FetchResponse::from_lines(input).unwrap()
+    }
+
+    #[test]
+    fn test_column() {
+        let response = response_for_tests();
+        assert_eq!(response.ds_index("ds2"), Some(1));
+        assert_eq!(
+            response.column("ds1").unwrap(),
+            vec![
+                (1708800040, Some(1.0)),
+                (1708800050, None),
+                (1708800060, Some(4.0))
+            ]
+        );
+        assert_eq!(
+            response.column("ds2").unwrap(),
+            vec![
+                (1708800040, Some(2.0)),
+                (1708800050, Some(3.0)),
+                (1708800060, None)
+            ]
+        );
+        assert!(response.column("ds3").is_none());
+        assert_eq!(
+            response.timestamps().collect::<Vec<usize>>(),
+            vec![1708800040, 1708800050, 1708800060]
+        );
+    }
+
+    #[test]
+    fn test_rows() {
+        let response = response_for_tests();
+        let rows: Vec<(usize, HashMap<&str, Option<f64>>)> = response.rows().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].0, 1708800040);
+        assert_eq!(rows[0].1["ds1"], Some(1.0));
+        assert_eq!(rows[0].1["ds2"], Some(2.0));
+        assert_eq!(rows[1].1["ds1"], None);
+        assert_eq!(rows[2].1["ds2"], None);
+    }
+
+    #[test]
+    fn test_invalid_rows() {
+        // Wrong number of values
+        let input = vec![
+            "Start: 1708800030\n".to_string(),
+            "Step: 10\n".to_string(),
+            "DSCount: 2\n".to_string(),
+            "1708800040: 1 2 3\n".to_string(),
+        ];
+        assert!(FetchResponse::from_lines(input).is_err());
+
+        // Missing row
+        let input = vec![
+            "Start: 1708800030\n".to_string(),
+            "Step: 10\n".to_string(),
+            "DSCount: 1\n".to_string(),
+            "1708800040: 1\n".to_string(),
+            "1708800060: 1\n".to_string(),
+        ];
+        assert!(FetchResponse::from_lines(input).is_err());
+
+        // Not aligned on the start
+        let input = vec![
+            "Start: 1708800030\n".to_string(),
+            "Step: 10\n".to_string(),
+            "DSCount: 1\n".to_string(),
+            "1708800045: 1\n".to_string(),
+        ];
+        assert!(FetchResponse::from_lines(input).is_err());
+    }
+
+    #[test]
+    fn test_invalid_line_position() {
+        let input = vec![
+            "FlushVersion: 1\n".to_string(),
+            "DSCount: 2\n".to_string(),
+            "1708800040: 1.0000000000e+00 2\n".to_string(),
+            "1708800050: 1.0000000000e+00 2.5x\n".to_string(),
+        ];
+        match FetchResponse::from_lines(input) {
+            Err(RRDCachedClientError::InvalidFetchLine(line, column, content)) => {
+                assert_eq!(line, 4);
+                assert_eq!(column, 30);
+                assert_eq!(content, "1708800050: 1.0000000000e+00 2.5x");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // First data line
+        let input = vec![
+            "FlushVersion: 1\n".to_string(),
+            "1708800040: abc\n".to_string(),
+        ];
+        match FetchResponse::from_lines(input) {
+            Err(RRDCachedClientError::InvalidFetchLine(line, column, _)) => {
+                assert_eq!((line, column), (2, 13));
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_garbage_lines() {
+        // Deterministic pseudo-random lines must fail cleanly, without panicking
+        let alphabet = b"0123456789:.,-+eEnaifINF \t";
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        for _ in 0..2000 {
+            let mut line = "1708800040: ".to_string();
+            for _ in 0..(state % 24) {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                line.push(alphabet[(state % alphabet.len() as u64) as usize] as char);
+            }
+            line.push('\n');
+            if let Err(RRDCachedClientError::InvalidFetchLine(number, column, _)) =
+                FetchResponse::from_lines(vec![line.clone()])
+            {
+                assert_eq!(number, 1);
+                assert!(column >= 1 && column <= line.len() + 1, "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_column_header() {
+        let header =
+            BinaryColumnHeader::from_line("DSName-ds1: BinaryData 3 8 LITTLEENDIAN\n").unwrap();
+        assert_eq!(header.name, "ds1");
+        assert_eq!(header.payload_len().unwrap(), 24);
+        let payload: Vec<u8> = [1.5, f64::NAN, -2.0]
+            .iter()
+            .flat_map(|value: &f64| value.to_le_bytes())
+            .collect();
+        let values = header.decode(&payload).unwrap();
+        assert_eq!(values[0], 1.5);
+        assert!(values[1].is_nan());
+        assert_eq!(values[2], -2.0);
+        assert!(header.decode(&payload[..16]).is_err());
+
+        let header =
+            BinaryColumnHeader::from_line("DSName-ds1: BinaryData 2 8 BIGENDIAN\n").unwrap();
+        assert_eq!(header.byte_order, ByteOrder::BigEndian);
+        let payload: Vec<u8> = [1.5f64, 1e300]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        assert_eq!(header.decode(&payload).unwrap(), vec![1.5, 1e300]);
+
+        let header =
+            BinaryColumnHeader::from_line("DSName-ds1: BinaryData 2 4 LITTLEENDIAN\n").unwrap();
+        let payload: Vec<u8> = [0.25f32, 8.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        assert_eq!(header.decode(&payload).unwrap(), vec![0.25, 8.0]);
+
+        assert!(
+            BinaryColumnHeader::from_line("DSName-ds1: BinaryData 2 2 LITTLEENDIAN\n").is_err()
+        );
+        assert!(BinaryColumnHeader::from_line(&format!(
+            "DSName-ds1: BinaryData {} 8 LITTLEENDIAN\n",
+            usize::MAX
+        ))
+        .unwrap()
+        .payload_len()
+        .is_err());
+
+        assert!(header.check_count(2).is_ok());
+        assert!(header.check_count(1).is_err());
+    }
+
+    #[test]
+    fn test_from_binary_matches_text() {
+        let header_lines = vec![
+            "FlushVersion: 1\n".to_string(),
+            "Start: 1708800030\n".to_string(),
+            "End: 1708800060\n".to_string(),
+            "Step: 10\n".to_string(),
+            "DSCount: 2\n".to_string(),
+        ];
+        let binary = FetchResponse::from_binary(
+            header_lines.clone(),
+            vec![
+                ("ds1".to_string(), vec![1.0, f64::NAN, 4.0]),
+                ("ds2".to_string(), vec![2.0, 3.0, f64::NAN]),
+            ],
+        )
+        .unwrap();
+        let text = response_for_tests();
+        assert_eq!(binary.start, text.start);
+        assert_eq!(binary.step, text.step);
+        assert_eq!(binary.ds_names, text.ds_names);
+        assert_eq!(binary.column("ds1"), text.column("ds1"));
+        assert_eq!(binary.column("ds2"), text.column("ds2"));
+
+        // Columns of different lengths
+        assert!(FetchResponse::from_binary(
+            header_lines.clone(),
+            vec![
+                ("ds1".to_string(), vec![1.0, 2.0]),
+                ("ds2".to_string(), vec![2.0]),
+            ],
+        )
+        .is_err());
+
+        // Missing column
+        assert!(FetchResponse::from_binary(
+            header_lines,
+            vec![("ds1".to_string(), vec![1.0, 2.0])],
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_parse_error_numbers() {
         let input = vec![
@@ -0,0 +1,381 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    consolidation_function::ConsolidationFunction, errors::RRDCachedClientError, rrd_path::RrdPath,
+    sanitisation::check_data_source_name,
+};
+
+/// Start of a FETCH when only the end or the columns are set, as in rrdtool.
+const DEFAULT_START: &str = "end-1d";
+
+/// End of a FETCH when only the columns are set, as in rrdtool.
+const DEFAULT_END: &str = "now";
+
+/// A time of a FETCH request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchTime {
+    /// Absolute time
+    At(SystemTime),
+
+    /// Seconds since the Unix epoch
+    Timestamp(u64),
+
+    /// Duration before now
+    Ago(Duration),
+
+    /// Duration before the end, for the start only
+    BeforeEnd(Duration),
+
+    /// Duration after the start, for the end only
+    AfterStart(Duration),
+
+    /// rrdtool AT-style time, like `now`, `end-1d` or `midnight-2h`.
+    ///
+    /// The RRDCached protocol separates arguments with spaces,
+    /// so it can't contain any.
+    AtStyle(String),
+}
+
+impl FetchTime {
+    /// Check that the time can be sent.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        match self {
+            FetchTime::At(time) => {
+                time.duration_since(UNIX_EPOCH).map_err(|_| {
+                    RRDCachedClientError::InvalidFetch("time before the Unix epoch".to_string())
+                })?;
+            }
+            FetchTime::AtStyle(time) => {
+                if time.is_empty()
+                    || !time
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-:./_@".contains(c))
+                {
+                    return Err(RRDCachedClientError::InvalidFetch(format!(
+                        "invalid AT-style time {:?}",
+                        time
+                    )));
+                }
+            }
+            FetchTime::Timestamp(_)
+            | FetchTime::Ago(_)
+            | FetchTime::BeforeEnd(_)
+            | FetchTime::AfterStart(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Convert to a string argument parameter.
+    pub fn to_str(&self) -> String {
+        match self {
+            FetchTime::At(time) => time
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0)
+                .to_string(),
+            FetchTime::Timestamp(timestamp) => timestamp.to_string(),
+            FetchTime::Ago(duration) => format!("now-{}", duration.as_secs()),
+            FetchTime::BeforeEnd(duration) => format!("end-{}", duration.as_secs()),
+            FetchTime::AfterStart(duration) => format!("start+{}", duration.as_secs()),
+            FetchTime::AtStyle(time) => time.clone(),
+        }
+    }
+}
+
+impl From<SystemTime> for FetchTime {
+    fn from(time: SystemTime) -> Self {
+        FetchTime::At(time)
+    }
+}
+
+impl From<u64> for FetchTime {
+    fn from(timestamp: u64) -> Self {
+        FetchTime::Timestamp(timestamp)
+    }
+}
+
+impl From<&str> for FetchTime {
+    fn from(time: &str) -> Self {
+        FetchTime::AtStyle(time.to_string())
+    }
+}
+
+/// How a FETCH handles the updates still pending in the cache of RRDCached.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FetchConsistency {
+    /// Send the FETCH alone. Whether it includes the pending updates
+    /// depends on the version of RRDCached.
+    #[default]
+    Cached,
+
+    /// Flush the RRD before the FETCH, so it includes all the updates
+    /// sent before.
+    Flush,
+
+    /// Flush the RRD before the FETCH only if it has pending updates,
+    /// checked with PENDING. Cheaper than [Self::Flush] for RRDs
+    /// that are rarely updated.
+    FlushIfPending,
+}
+
+/// Arguments of a FETCH command, built from a path and a consolidation function.
+///
+/// The start, the end and the columns are independent: the defaults
+/// of rrdtool (`end-1d` and `now`) are sent when needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchRequest {
+    pub path: RrdPath,
+    pub consolidation_function: ConsolidationFunction,
+
+    /// Start of the fetched range, one day before the end by default
+    pub start: Option<FetchTime>,
+
+    /// End of the fetched range, now by default
+    pub end: Option<FetchTime>,
+
+    /// Data sources to fetch, all by default
+    pub columns: Vec<String>,
+
+    /// Handling of the pending updates, [FetchConsistency::Cached] by default
+    pub consistency: FetchConsistency,
+}
+
+impl FetchRequest {
+    pub fn new(path: &RrdPath, consolidation_function: ConsolidationFunction) -> FetchRequest {
+        FetchRequest {
+            path: path.clone(),
+            consolidation_function,
+            start: None,
+            end: None,
+            columns: Vec::new(),
+            consistency: FetchConsistency::default(),
+        }
+    }
+
+    pub fn start<T: Into<FetchTime>>(mut self, start: T) -> Self {
+        self.start = Some(start.into());
+        self
+    }
+
+    pub fn end<T: Into<FetchTime>>(mut self, end: T) -> Self {
+        self.end = Some(end.into());
+        self
+    }
+
+    /// Start at an absolute time, from any type convertible
+    /// to [SystemTime], like the `DateTime` of chrono.
+    pub fn start_at<T: Into<SystemTime>>(self, start: T) -> Self {
+        self.start(FetchTime::At(start.into()))
+    }
+
+    /// End at an absolute time, from any type convertible
+    /// to [SystemTime], like the `DateTime` of chrono.
+    pub fn end_at<T: Into<SystemTime>>(self, end: T) -> Self {
+        self.end(FetchTime::At(end.into()))
+    }
+
+    /// Fetch the last duration before the end.
+    pub fn last(self, duration: Duration) -> Self {
+        self.start(FetchTime::BeforeEnd(duration))
+    }
+
+    /// Add a data source to fetch.
+    pub fn column(mut self, name: &str) -> Self {
+        self.columns.push(name.to_string());
+        self
+    }
+
+    /// Add data sources to fetch.
+    pub fn columns<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, names: I) -> Self {
+        self.columns
+            .extend(names.into_iter().map(|name| name.as_ref().to_string()));
+        self
+    }
+
+    /// Set the handling of the pending updates.
+    pub fn consistency(mut self, consistency: FetchConsistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Flush the RRD before fetching, so the updates sent before are included.
+    pub fn flush_first(self) -> Self {
+        self.consistency(FetchConsistency::Flush)
+    }
+
+    /// Check that the content is valid.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        if let Some(start) = &self.start {
+            start.validate()?;
+            if matches!(start, FetchTime::AfterStart(_)) {
+                return Err(RRDCachedClientError::InvalidFetch(
+                    "the start can't be relative to itself".to_string(),
+                ));
+            }
+        }
+        if let Some(end) = &self.end {
+            end.validate()?;
+            if matches!(end, FetchTime::BeforeEnd(_)) {
+                return Err(RRDCachedClientError::InvalidFetch(
+                    "the end can't be relative to itself".to_string(),
+                ));
+            }
+        }
+        if matches!(self.start, Some(FetchTime::BeforeEnd(_)))
+            && matches!(self.end, Some(FetchTime::AfterStart(_)))
+        {
+            return Err(RRDCachedClientError::InvalidFetch(
+                "the start and the end can't be relative to each other".to_string(),
+            ));
+        }
+        for column in &self.columns {
+            check_data_source_name(column)?;
+        }
+        Ok(())
+    }
+
+    /// Convert to a string argument parameter.
+    pub fn to_str(&self) -> String {
+        let mut result = format!("{} {}", self.path, self.consolidation_function.to_str());
+        // The arguments are positional: the start is required before
+        // the end, and both before the columns.
+        let end = match (&self.end, self.columns.is_empty()) {
+            (Some(end), _) => Some(end.to_str()),
+            (None, false) => Some(DEFAULT_END.to_string()),
+            (None, true) => None,
+        };
+        let start = match (&self.start, &end) {
+            (Some(start), _) => Some(start.to_str()),
+            (None, Some(_)) => Some(DEFAULT_START.to_string()),
+            (None, None) => None,
+        };
+        for argument in [start, end].into_iter().flatten() {
+            result.push(' ');
+            result.push_str(&argument);
+        }
+        for column in &self.columns {
+            result.push(' ');
+            result.push_str(column);
+        }
+        result
+    }
+
+    /// Convert to a FETCH command, checking that the content is valid.
+    pub(crate) fn to_command(&self) -> Result<String, RRDCachedClientError> {
+        self.validate()?;
+        let arguments_str = self.to_str();
+        let mut command = String::with_capacity(6 + arguments_str.len() + 1);
+        command.push_str("FETCH ");
+        command.push_str(&arguments_str);
+        command.push('\n');
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> RrdPath {
+        RrdPath::new("test").unwrap()
+    }
+
+    #[test]
+    fn test_to_str() {
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average);
+        assert_eq!(request.to_str(), "test.rrd AVERAGE");
+
+        let request = request.start(1609459200);
+        assert_eq!(request.to_str(), "test.rrd AVERAGE 1609459200");
+
+        let request = request.end("now");
+        assert_eq!(request.to_str(), "test.rrd AVERAGE 1609459200 now");
+
+        let request = request.column("ds1").columns(["ds2", "ds3"]);
+        assert_eq!(
+            request.to_str(),
+            "test.rrd AVERAGE 1609459200 now ds1 ds2 ds3"
+        );
+    }
+
+    #[test]
+    fn test_consistency() {
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average);
+        assert_eq!(request.consistency, FetchConsistency::Cached);
+
+        // Not sent as an argument
+        let request = request.start(1609459200).flush_first();
+        assert_eq!(request.consistency, FetchConsistency::Flush);
+        assert_eq!(request.to_str(), "test.rrd AVERAGE 1609459200");
+
+        let request = request.consistency(FetchConsistency::FlushIfPending);
+        assert_eq!(request.consistency, FetchConsistency::FlushIfPending);
+    }
+
+    #[test]
+    fn test_independent_arguments() {
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Max).column("ds1");
+        assert_eq!(request.to_str(), "test.rrd MAX end-1d now ds1");
+
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Max).end(1609459200);
+        assert_eq!(request.to_str(), "test.rrd MAX end-1d 1609459200");
+    }
+
+    #[test]
+    fn test_times() {
+        let time = UNIX_EPOCH + Duration::from_secs(1609459200);
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average)
+            .start_at(time)
+            .end(FetchTime::AfterStart(Duration::from_secs(3600)));
+        assert!(request.validate().is_ok());
+        assert_eq!(request.to_str(), "test.rrd AVERAGE 1609459200 start+3600");
+
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average)
+            .last(Duration::from_secs(600))
+            .end(FetchTime::Ago(Duration::from_secs(60)));
+        assert!(request.validate().is_ok());
+        assert_eq!(request.to_str(), "test.rrd AVERAGE end-600 now-60");
+
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average)
+            .start("midnight-2h")
+            .end_at(time);
+        assert!(request.validate().is_ok());
+        assert_eq!(request.to_str(), "test.rrd AVERAGE midnight-2h 1609459200");
+    }
+
+    #[test]
+    fn test_validate() {
+        let request = FetchRequest::new(&path(), ConsolidationFunction::Average);
+        assert!(request.validate().is_ok());
+
+        // Arguments injection
+        assert!(request.clone().start("now\nFLUSHALL").validate().is_err());
+        assert!(request.clone().start("now -1d").validate().is_err());
+        assert!(request.clone().start("").validate().is_err());
+        assert!(request.clone().column("ds1 ds2").validate().is_err());
+
+        assert!(request
+            .clone()
+            .start_at(UNIX_EPOCH - Duration::from_secs(1))
+            .validate()
+            .is_err());
+
+        // Relative times
+        let duration = Duration::from_secs(60);
+        assert!(request
+            .clone()
+            .start(FetchTime::AfterStart(duration))
+            .validate()
+            .is_err());
+        assert!(request
+            .clone()
+            .end(FetchTime::BeforeEnd(duration))
+            .validate()
+            .is_err());
+        assert!(request
+            .last(duration)
+            .end(FetchTime::AfterStart(duration))
+            .validate()
+            .is_err());
+    }
+}
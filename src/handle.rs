@@ -0,0 +1,545 @@
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::sync::{mpsc, oneshot, Notify};
+
+use crate::{
+    create::CreateArguments,
+    errors::RRDCachedClientError,
+    fetch::FetchResponse,
+    fetch_request::FetchRequest,
+    info::RrdInfo,
+    pending::PendingSample,
+    pipeline::{join_fetch_responses, read_responses, Pipeline, PipelineCommand, PipelineResponse},
+    rrd_path::RrdPath,
+    stats::ServerStats,
+    update_timestamp::UpdateTimestamp,
+    update_value::UpdateValue,
+};
+
+/// Number of requests waiting to be written before the callers wait.
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+type Responses = Result<Vec<Result<PipelineResponse, RRDCachedClientError>>, RRDCachedClientError>;
+
+/// Commands sent together by a handle, and where to send their responses.
+struct Request {
+    commands: Vec<(String, PipelineCommand)>,
+    responder: oneshot::Sender<Responses>,
+}
+
+/// Request written to the server, waiting for its responses.
+struct InFlightRequest {
+    commands: Vec<PipelineCommand>,
+    responder: oneshot::Sender<Responses>,
+}
+
+/// A handle to a RRDCached connection, cheap to clone and to share
+/// between tasks.
+///
+/// The connection is owned by background tasks that write the requests
+/// of all the handles back-to-back, as a [Pipeline], and route the
+/// responses back in order. It's closed when all the handles are dropped.
+///
+/// Unlike [crate::RRDCachedClient], the methods take `&self`. The commands
+/// that can't be pipelined are left to [crate::RRDCachedClient]:
+/// `update_many` and `batch` may be split in several lines answered
+/// separately, and `list` reads the entries one by one. A pipeline of
+/// [Pipeline::update] sends several updates in a single write instead.
+#[derive(Debug, Clone)]
+pub struct RRDCachedHandle {
+    requests: mpsc::Sender<Request>,
+}
+
+impl RRDCachedHandle {
+    /// Connect to a RRDCached server over TCP.
+    pub async fn connect_tcp(addr: &str) -> Result<Self, RRDCachedClientError> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::new(stream))
+    }
+
+    /// Connect to a RRDCached server over a Unix socket.
+    pub async fn connect_unix(addr: &str) -> Result<Self, RRDCachedClientError> {
+        let stream = UnixStream::connect(addr).await?;
+        Ok(Self::new(stream))
+    }
+
+    /// Spawn the tasks of the connection on the current tokio runtime.
+    pub fn new<T>(stream: T) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let (requests, requests_receiver) = mpsc::channel(DEFAULT_QUEUE_SIZE);
+        let (in_flight, in_flight_receiver) = mpsc::unbounded_channel();
+        let write_failed = Arc::new(Notify::new());
+        tokio::spawn(write_requests(
+            writer,
+            requests_receiver,
+            in_flight,
+            write_failed.clone(),
+        ));
+        tokio::spawn(read_requests_responses(
+            BufReader::new(reader),
+            in_flight_receiver,
+            write_failed,
+        ));
+        RRDCachedHandle { requests }
+    }
+
+    /// Execute the commands of a pipeline, see [crate::RRDCachedClient::execute].
+    ///
+    /// The commands are written together, without the commands of other handles
+    /// in between.
+    pub async fn execute(
+        &self,
+        pipeline: Pipeline,
+    ) -> Result<Vec<Result<PipelineResponse, RRDCachedClientError>>, RRDCachedClientError> {
+        let commands = pipeline.into_commands()?;
+        let (responder, responses) = oneshot::channel();
+        self.requests
+            .send(Request {
+                commands,
+                responder,
+            })
+            .await
+            .map_err(|_| RRDCachedClientError::ConnectionClosed)?;
+        responses
+            .await
+            .map_err(|_| RRDCachedClientError::ConnectionClosed)?
+    }
+
+    /// Execute a pipeline of a single command.
+    async fn execute_one(
+        &self,
+        pipeline: Pipeline,
+    ) -> Result<PipelineResponse, RRDCachedClientError> {
+        // The reader answers every command unless the connection is lost
+        self.execute(pipeline)
+            .await?
+            .pop()
+            .ok_or(RRDCachedClientError::ConnectionClosed)?
+    }
+
+    /// Ping the server to check if it's alive.
+    pub async fn ping(&self) -> Result<(), RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().ping()).await? {
+            PipelineResponse::Pong => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Create a RRD, see [crate::RRDCachedClient::create].
+    pub async fn create(&self, arguments: CreateArguments) -> Result<(), RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().create(arguments)).await? {
+            PipelineResponse::Created => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Update a RRD with a list of values at a specific timestamp,
+    /// see [crate::RRDCachedClient::update].
+    pub async fn update<V: Into<UpdateValue>>(
+        &self,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: Vec<V>,
+    ) -> Result<(), RRDCachedClientError> {
+        match self
+            .execute_one(Pipeline::new().update(path, timestamp, data))
+            .await?
+        {
+            PipelineResponse::Updated => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Update a RRD with a single value at a specific timestamp.
+    pub async fn update_one<V: Into<UpdateValue>>(
+        &self,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: V,
+    ) -> Result<(), RRDCachedClientError> {
+        self.update(path, timestamp, vec![data]).await
+    }
+
+    /// Flush a RRD
+    pub async fn flush(&self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().flush(path)).await? {
+            PipelineResponse::Flushed => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Flush all RRDs
+    pub async fn flush_all(&self) -> Result<(), RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().flush_all()).await? {
+            PipelineResponse::FlushedAll => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Pending updates
+    pub async fn pending(
+        &self,
+        path: &RrdPath,
+    ) -> Result<Vec<PendingSample>, RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().pending(path)).await? {
+            PipelineResponse::Pending(samples) => Ok(samples),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Forget pending updates
+    pub async fn forget(&self, path: &RrdPath) -> Result<(), RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().forget(path)).await? {
+            PipelineResponse::Forgotten => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Statistics of the server
+    pub async fn stats(&self) -> Result<ServerStats, RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().stats()).await? {
+            PipelineResponse::Stats(stats) => Ok(stats),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Get the first timestamp of a round robin archive
+    pub async fn first(
+        &self,
+        path: &RrdPath,
+        round_robin_archive: Option<usize>,
+    ) -> Result<usize, RRDCachedClientError> {
+        match self
+            .execute_one(Pipeline::new().first(path, round_robin_archive))
+            .await?
+        {
+            PipelineResponse::First(timestamp) => Ok(timestamp),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Retrieve the last update timestamp
+    pub async fn last(&self, path: &RrdPath) -> Result<usize, RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().last(path)).await? {
+            PipelineResponse::Last(timestamp) => Ok(timestamp),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Retreive information about a RRD
+    pub async fn info(&self, path: &RrdPath) -> Result<RrdInfo, RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().info(path)).await? {
+            PipelineResponse::Info(info) => Ok(info),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Fetch the content of a Round Robin Database (RRD), see [Pipeline::fetch].
+    pub async fn fetch(
+        &self,
+        request: FetchRequest,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        match self.execute_one(Pipeline::new().fetch(request)).await? {
+            PipelineResponse::Fetch(response) => Ok(response),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    /// Fetch several RRDs and join them into one table,
+    /// see [crate::RRDCachedClient::fetch_many].
+    pub async fn fetch_many(
+        &self,
+        requests: Vec<FetchRequest>,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        let responses = self.execute(Pipeline::fetch_many(&requests)).await?;
+        join_fetch_responses(&requests, responses)
+    }
+}
+
+fn unexpected_response(response: PipelineResponse) -> RRDCachedClientError {
+    RRDCachedClientError::Parsing(format!("unexpected response {:?}", response))
+}
+
+/// Write the requests of the handles, batching the ones already waiting.
+///
+/// The requests are passed to the reader before being written, so the
+/// responses are read while writing large batches. If a write fails, the
+/// reader is notified to fail the requests in flight.
+async fn write_requests<T: AsyncWrite>(
+    mut writer: WriteHalf<T>,
+    mut requests: mpsc::Receiver<Request>,
+    in_flight: mpsc::UnboundedSender<InFlightRequest>,
+    write_failed: Arc<Notify>,
+) {
+    let mut batch = Vec::new();
+    while requests.recv_many(&mut batch, DEFAULT_QUEUE_SIZE).await > 0 {
+        let mut commands_str = String::new();
+        for request in batch.drain(..) {
+            let mut commands = Vec::with_capacity(request.commands.len());
+            for (command_str, command) in request.commands {
+                commands_str.push_str(&command_str);
+                commands.push(command);
+            }
+            let in_flight_request = InFlightRequest {
+                commands,
+                responder: request.responder,
+            };
+            if in_flight.send(in_flight_request).is_err() {
+                // The responses can't be read anymore
+                return;
+            }
+        }
+        // The responses of the requests in flight will never come
+        if writer.write_all(commands_str.as_bytes()).await.is_err() {
+            write_failed.notify_one();
+            return;
+        }
+    }
+}
+
+/// Read the responses of the written requests, in order.
+///
+/// Once the connection is lost, the remaining requests in flight
+/// fail with [RRDCachedClientError::ConnectionClosed].
+async fn read_requests_responses<T: AsyncRead>(
+    mut reader: BufReader<ReadHalf<T>>,
+    mut in_flight: mpsc::UnboundedReceiver<InFlightRequest>,
+    write_failed: Arc<Notify>,
+) {
+    while let Some(request) = in_flight.recv().await {
+        let responses = tokio::select! {
+            responses = read_responses(&mut reader, &request.commands) => responses,
+            _ = write_failed.notified() => Err(RRDCachedClientError::ConnectionClosed),
+        };
+        let lost = responses.is_err();
+        // The caller may have given up waiting
+        let _ = request.responder.send(responses);
+        if lost {
+            break;
+        }
+    }
+    in_flight.close();
+    while let Ok(request) = in_flight.try_recv() {
+        let _ = request
+            .responder
+            .send(Err(RRDCachedClientError::ConnectionClosed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consolidation_function::ConsolidationFunction;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, DuplexStream, ReadBuf};
+
+    /// A fake server answering each command line with `answer`.
+    async fn serve<F: Fn(&str) -> String>(stream: DuplexStream, answer: F) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            writer.write_all(answer(&line).as_bytes()).await.unwrap();
+        }
+    }
+
+    /// A fake server answering LAST with the number in the name of the RRD.
+    async fn serve_last(stream: DuplexStream) {
+        serve(stream, |line| {
+            match line
+                .strip_prefix("LAST test-")
+                .and_then(|name| name.strip_suffix(".rrd"))
+            {
+                Some(number) => format!("0 {}\n", number),
+                None => "-1 Unknown command\n".to_string(),
+            }
+        })
+        .await
+    }
+
+    /// A stream whose writes fail while its reads wait forever.
+    struct BrokenWrites(DuplexStream);
+
+    impl AsyncRead for BrokenWrites {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for BrokenWrites {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests() {
+        let (client, server) = tokio::io::duplex(64);
+        tokio::spawn(serve_last(server));
+        let handle = RRDCachedHandle::new(client);
+
+        let tasks: Vec<_> = (0..200)
+            .map(|i| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let path = RrdPath::new(&format!("test-{}", i)).unwrap();
+                    assert_eq!(handle.last(&path).await.unwrap(), i);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Errors are routed to their caller only
+        assert!(handle.ping().await.is_err());
+        let path = RrdPath::new("test-42").unwrap();
+        assert_eq!(handle.last(&path).await.unwrap(), 42);
+
+        let responses = handle
+            .execute(Pipeline::new().last(&path).ping().last(&path))
+            .await
+            .unwrap();
+        assert!(matches!(responses[0], Ok(PipelineResponse::Last(42))));
+        assert!(responses[1].is_err());
+        assert!(matches!(responses[2], Ok(PipelineResponse::Last(42))));
+    }
+
+    #[tokio::test]
+    async fn test_connection_closed() {
+        let (client, server) = tokio::io::duplex(64);
+        let handle = RRDCachedHandle::new(client);
+        drop(server);
+
+        let path = RrdPath::new("test-1").unwrap();
+        assert!(matches!(
+            handle.last(&path).await,
+            Err(RRDCachedClientError::ConnectionClosed)
+        ));
+        assert!(matches!(
+            handle.last(&path).await,
+            Err(RRDCachedClientError::ConnectionClosed)
+        ));
+
+        // Invalid arguments are reported before sending anything
+        let result = handle
+            .update::<f64>(&path, UpdateTimestamp::Now, vec![])
+            .await;
+        assert!(matches!(
+            result,
+            Err(RRDCachedClientError::InvalidBatchUpdate(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_write_failed() {
+        // The server never answers, the requests must not wait forever
+        let (client, _server) = tokio::io::duplex(64);
+        let handle = RRDCachedHandle::new(BrokenWrites(client));
+
+        let result = tokio::time::timeout(Duration::from_secs(5), handle.ping()).await;
+        assert!(matches!(
+            result,
+            Ok(Err(RRDCachedClientError::ConnectionClosed))
+        ));
+        assert!(matches!(
+            handle.ping().await,
+            Err(RRDCachedClientError::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_stats_and_fetch_many() {
+        let (client, server) = tokio::io::duplex(1024);
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let server_commands = commands.clone();
+        tokio::spawn(serve(server, move |line| {
+            server_commands.lock().unwrap().push(line.to_string());
+            let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+            match command {
+                "CREATE" => "0 RRD created OK\n".to_string(),
+                "STATS" => "2 Statistics follow\nQueueLength: 3\nUpdatesReceived: 10\n".to_string(),
+                "FLUSH" => "0 Successfully flushed\n".to_string(),
+                "FETCH" => {
+                    let ds_name = if arguments.starts_with("cpu.rrd") {
+                        "user"
+                    } else {
+                        "used"
+                    };
+                    format!(
+                        "8 Success\nFlushVersion: 1\nStart: 1708800030\nEnd: 1708800060\nStep: 10\nDSCount: 1\nDSName: {}\n1708800040: 1\n1708800050: 2\n",
+                        ds_name
+                    )
+                }
+                _ => "-1 Unknown command\n".to_string(),
+            }
+        }));
+        let handle = RRDCachedHandle::new(client);
+
+        let arguments = CreateArguments::builder("cpu")
+            .step_seconds(10)
+            .start_timestamp(1708800000)
+            .gauge("user", None, None)
+            .rra_with_durations(
+                ConsolidationFunction::Average,
+                "10s".parse().unwrap(),
+                "1h".parse().unwrap(),
+            )
+            .build()
+            .unwrap();
+        handle.create(arguments).await.unwrap();
+
+        let stats = handle.stats().await.unwrap();
+        assert_eq!(stats.queue_length, 3);
+        assert_eq!(stats.updates_received, 10);
+
+        let cpu = RrdPath::new("cpu").unwrap();
+        let memory = RrdPath::new("memory").unwrap();
+        let response = handle
+            .fetch_many(vec![
+                FetchRequest::new(&cpu, ConsolidationFunction::Average)
+                    .start(1708800030)
+                    .end(1708800060),
+                FetchRequest::new(&memory, ConsolidationFunction::Average)
+                    .start(1708800030)
+                    .end(1708800060)
+                    .flush_first(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(response.ds_names, vec!["cpu:user", "memory:used"]);
+
+        assert_eq!(
+            commands.lock().unwrap()[2..],
+            [
+                "FETCH cpu.rrd AVERAGE 1708800030 1708800060",
+                "FLUSH memory.rrd",
+                "FETCH memory.rrd AVERAGE 1708800030 1708800060",
+            ]
+        );
+    }
+}
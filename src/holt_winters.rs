@@ -0,0 +1,349 @@
+use crate::errors::RRDCachedClientError;
+
+/// Maximum window length of a FAILURES archive, as in rrdtool.
+pub const MAX_FAILURES_WINDOW_LENGTH: u64 = 28;
+
+/// Smoothing window of SEASONAL and DEVSEASONAL archives when not set, as in rrdtool.
+pub const DEFAULT_SMOOTHING_WINDOW: f64 = 0.05;
+
+/// Arguments for a HWPREDICT or MHWPREDICT archive,
+/// the predictions of the Holt-Winters forecasting algorithm.
+///
+/// Archives are referenced by their index in
+/// [crate::create::CreateArguments::round_robin_archives], starting at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateHwPredictArchive {
+    /// Number of rows in the archive
+    pub rows: u64,
+
+    /// Intercept adaption parameter, between 0 and 1 excluded
+    pub alpha: f64,
+
+    /// Slope adaption parameter, between 0 and 1 excluded
+    pub beta: f64,
+
+    /// Number of primary data points in a season
+    pub seasonal_period: u64,
+
+    /// Index of the SEASONAL archive.
+    ///
+    /// When `None`, rrdtool creates the SEASONAL, DEVSEASONAL,
+    /// DEVPREDICT and FAILURES archives with default parameters.
+    pub seasonal_archive: Option<usize>,
+}
+
+impl CreateHwPredictArchive {
+    /// Check that the content is valid.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        check_rows(self.rows)?;
+        check_adaption_parameter("alpha", self.alpha)?;
+        check_adaption_parameter("beta", self.beta)?;
+        check_seasonal_period(self.seasonal_period)?;
+        if let Some(seasonal_archive) = self.seasonal_archive {
+            check_archive_index("seasonal_archive", seasonal_archive)?;
+        }
+        Ok(())
+    }
+
+    /// Convert to the arguments following the consolidation function.
+    pub fn to_str(&self) -> String {
+        let mut result = format!(
+            "{}:{}:{}:{}",
+            self.rows, self.alpha, self.beta, self.seasonal_period
+        );
+        if let Some(seasonal_archive) = self.seasonal_archive {
+            result.push(':');
+            result.push_str(&rra_num(seasonal_archive));
+        }
+        result
+    }
+}
+
+/// Arguments for a SEASONAL or DEVSEASONAL archive,
+/// the seasonal coefficients or deviations of Holt-Winters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateSeasonalArchive {
+    /// Number of primary data points in a season, which is
+    /// the number of rows in the archive
+    pub seasonal_period: u64,
+
+    /// Seasonal adaption parameter, between 0 and 1 excluded
+    pub gamma: f64,
+
+    /// Index of the HWPREDICT or MHWPREDICT archive
+    pub predict_archive: usize,
+
+    /// Fraction of the season to smooth the coefficients over,
+    /// between 0 and 1. Defaults to [DEFAULT_SMOOTHING_WINDOW].
+    pub smoothing_window: Option<f64>,
+}
+
+impl CreateSeasonalArchive {
+    /// Check that the content is valid.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        check_seasonal_period(self.seasonal_period)?;
+        check_adaption_parameter("gamma", self.gamma)?;
+        check_archive_index("predict_archive", self.predict_archive)?;
+        if let Some(smoothing_window) = self.smoothing_window {
+            if !(0.0..=1.0).contains(&smoothing_window) {
+                return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                    "smoothing_window must be between 0 and 1".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert to the arguments following the consolidation function.
+    pub fn to_str(&self) -> String {
+        let mut result = format!(
+            "{}:{}:{}",
+            self.seasonal_period,
+            self.gamma,
+            rra_num(self.predict_archive)
+        );
+        if let Some(smoothing_window) = self.smoothing_window {
+            result.push_str(":smoothing-window=");
+            result.push_str(&smoothing_window.to_string());
+        }
+        result
+    }
+}
+
+/// Arguments for a DEVPREDICT archive, the predicted deviations of Holt-Winters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateDevPredictArchive {
+    /// Number of rows in the archive
+    pub rows: u64,
+
+    /// Index of the DEVSEASONAL archive
+    pub dev_seasonal_archive: usize,
+}
+
+impl CreateDevPredictArchive {
+    /// Check that the content is valid.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        check_rows(self.rows)?;
+        check_archive_index("dev_seasonal_archive", self.dev_seasonal_archive)
+    }
+
+    /// Convert to the arguments following the consolidation function.
+    pub fn to_str(&self) -> String {
+        format!("{}:{}", self.rows, rra_num(self.dev_seasonal_archive))
+    }
+}
+
+/// Arguments for a FAILURES archive, the aberrant behaviors detected by Holt-Winters.
+///
+/// A failure is reported when at least `threshold` values
+/// of the last `window_length` values are out of the confidence bands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateFailuresArchive {
+    /// Number of rows in the archive
+    pub rows: u64,
+
+    /// Minimum number of violations in the window to report a failure
+    pub threshold: u64,
+
+    /// Number of values in the window, at most [MAX_FAILURES_WINDOW_LENGTH]
+    pub window_length: u64,
+
+    /// Index of the DEVSEASONAL archive
+    pub dev_seasonal_archive: usize,
+}
+
+impl CreateFailuresArchive {
+    /// Check that the content is valid.
+    pub fn validate(&self) -> Result<(), RRDCachedClientError> {
+        check_rows(self.rows)?;
+        if self.window_length == 0 || self.window_length > MAX_FAILURES_WINDOW_LENGTH {
+            return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+                "window_length must be between 1 and {}",
+                MAX_FAILURES_WINDOW_LENGTH
+            )));
+        }
+        if self.threshold == 0 || self.threshold > self.window_length {
+            return Err(RRDCachedClientError::InvalidCreateDataSerie(
+                "threshold must be between 1 and window_length".to_string(),
+            ));
+        }
+        check_archive_index("dev_seasonal_archive", self.dev_seasonal_archive)
+    }
+
+    /// Convert to the arguments following the consolidation function.
+    pub fn to_str(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.rows,
+            self.threshold,
+            self.window_length,
+            rra_num(self.dev_seasonal_archive)
+        )
+    }
+}
+
+/// rrdtool references the archives starting at 1.
+fn rra_num(index: usize) -> String {
+    (index as u128 + 1).to_string()
+}
+
+/// Check that the index can be numbered from 1 by rrdtool.
+fn check_archive_index(name: &str, index: usize) -> Result<(), RRDCachedClientError> {
+    if index.checked_add(1).is_none() {
+        return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+            "{} is not a valid archive index",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn check_rows(rows: u64) -> Result<(), RRDCachedClientError> {
+    if rows == 0 {
+        return Err(RRDCachedClientError::InvalidCreateDataSerie(
+            "rows must be greater than 0".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_seasonal_period(seasonal_period: u64) -> Result<(), RRDCachedClientError> {
+    if seasonal_period == 0 {
+        return Err(RRDCachedClientError::InvalidCreateDataSerie(
+            "seasonal_period must be greater than 0".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_adaption_parameter(name: &str, value: f64) -> Result<(), RRDCachedClientError> {
+    if value <= 0.0 || value >= 1.0 || value.is_nan() {
+        return Err(RRDCachedClientError::InvalidCreateDataSerie(format!(
+            "{} must be between 0 and 1 excluded",
+            name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hw_predict_archive() {
+        let archive = CreateHwPredictArchive {
+            rows: 1440,
+            alpha: 0.1,
+            beta: 0.0035,
+            seasonal_period: 288,
+            seasonal_archive: Some(2),
+        };
+        assert!(archive.validate().is_ok());
+        assert_eq!(archive.to_str(), "1440:0.1:0.0035:288:3");
+
+        let archive = CreateHwPredictArchive {
+            seasonal_archive: None,
+            ..archive
+        };
+        assert_eq!(archive.to_str(), "1440:0.1:0.0035:288");
+
+        let invalid = CreateHwPredictArchive {
+            alpha: 1.0,
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateHwPredictArchive {
+            beta: 0.0,
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateHwPredictArchive {
+            seasonal_period: 0,
+            ..archive
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_seasonal_archive() {
+        let archive = CreateSeasonalArchive {
+            seasonal_period: 288,
+            gamma: 0.1,
+            predict_archive: 1,
+            smoothing_window: None,
+        };
+        assert!(archive.validate().is_ok());
+        assert_eq!(archive.to_str(), "288:0.1:2");
+
+        let archive = CreateSeasonalArchive {
+            smoothing_window: Some(0.1),
+            ..archive
+        };
+        assert_eq!(archive.to_str(), "288:0.1:2:smoothing-window=0.1");
+
+        let invalid = CreateSeasonalArchive {
+            smoothing_window: Some(1.5),
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateSeasonalArchive {
+            gamma: -0.1,
+            ..archive
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_dev_predict_archive() {
+        let archive = CreateDevPredictArchive {
+            rows: 1440,
+            dev_seasonal_archive: 4,
+        };
+        assert!(archive.validate().is_ok());
+        assert_eq!(archive.to_str(), "1440:5");
+
+        let invalid = CreateDevPredictArchive {
+            rows: 0,
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateDevPredictArchive {
+            dev_seasonal_archive: usize::MAX,
+            ..archive
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(RRDCachedClientError::InvalidCreateDataSerie(_))
+        ));
+        assert_eq!(invalid.to_str(), format!("1440:{}", usize::MAX as u128 + 1));
+    }
+
+    #[test]
+    fn test_failures_archive() {
+        let archive = CreateFailuresArchive {
+            rows: 288,
+            threshold: 7,
+            window_length: 9,
+            dev_seasonal_archive: 4,
+        };
+        assert!(archive.validate().is_ok());
+        assert_eq!(archive.to_str(), "288:7:9:5");
+
+        let invalid = CreateFailuresArchive {
+            threshold: 10,
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateFailuresArchive {
+            threshold: 0,
+            ..archive.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = CreateFailuresArchive {
+            window_length: 29,
+            ..archive
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,550 @@
+use std::str::FromStr;
+
+use crate::{
+    consolidation_function::ConsolidationFunction,
+    create::CreateDataSourceType,
+    errors::RRDCachedClientError,
+    parsers::{parse_info_key, parse_info_line, InfoKey},
+};
+
+/// Information about a RRD, as returned by the INFO command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrdInfo {
+    /// File name of the RRD
+    pub filename: String,
+
+    /// Version of the RRD file format
+    pub rrd_version: String,
+
+    /// Number of seconds between two data points
+    pub step: u64,
+
+    /// Timestamp of the last update
+    pub last_update: u64,
+
+    /// Size of the header of the RRD file, in bytes
+    pub header_size: u64,
+
+    /// Data sources, in the order of the RRD
+    pub data_sources: Vec<RrdInfoDataSource>,
+
+    /// Round robin archives, in the order of the RRD
+    pub round_robin_archives: Vec<RrdInfoRoundRobinArchive>,
+}
+
+/// Information about a data source (DS).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrdInfoDataSource {
+    /// Name of the data source
+    pub name: String,
+
+    /// Index of the data source in the RRD
+    pub index: usize,
+
+    /// Type of the data source
+    pub serie_type: CreateDataSourceType,
+
+    /// Heartbeat, in seconds. 0 for COMPUTE data sources.
+    pub heartbeat: u64,
+
+    /// Minimum value, if any
+    pub minimum: Option<f64>,
+
+    /// Maximum value, if any
+    pub maximum: Option<f64>,
+
+    /// Last value received, as sent by the client. `U` if unknown.
+    pub last_ds: String,
+
+    /// Value accumulated in the current primary data point (PDP)
+    pub value: f64,
+
+    /// Number of unknown seconds in the current primary data point (PDP)
+    pub unknown_sec: u64,
+
+    /// RPN expression of COMPUTE data sources, like `a,b,+`
+    pub cdef: Option<String>,
+}
+
+/// Information about a round robin archive (RRA).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrdInfoRoundRobinArchive {
+    /// Consolidation function of the archive
+    pub consolidation_function: ConsolidationFunction,
+
+    /// Number of rows in the archive
+    pub rows: u64,
+
+    /// Current row of the archive
+    pub cur_row: u64,
+
+    /// Number of primary data points (PDP) per row
+    pub pdp_per_row: u64,
+
+    /// Ratio of unknown primary data points accepted in a row
+    ///
+    /// 0 for Holt-Winters archives, which don't have one.
+    pub xfiles_factor: f64,
+
+    /// Intercept adaption parameter of HWPREDICT and MHWPREDICT archives
+    pub alpha: Option<f64>,
+
+    /// Slope adaption parameter of HWPREDICT and MHWPREDICT archives
+    pub beta: Option<f64>,
+
+    /// Seasonal adaption parameter of SEASONAL and DEVSEASONAL archives
+    pub gamma: Option<f64>,
+
+    /// Smoothing window of SEASONAL and DEVSEASONAL archives
+    pub smoothing_window: Option<f64>,
+
+    /// Failure threshold of FAILURES archives
+    pub failure_threshold: Option<u64>,
+
+    /// Window length of FAILURES archives
+    pub window_length: Option<u64>,
+
+    /// State of the consolidated data point (CDP) being built, per data source
+    pub cdp_prep: Vec<RrdInfoCdpPrep>,
+}
+
+/// State of a consolidated data point (CDP) being built.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RrdInfoCdpPrep {
+    /// Value accumulated so far
+    pub value: f64,
+
+    /// Number of unknown primary data points so far
+    pub unknown_datapoints: u64,
+}
+
+#[derive(Default)]
+struct PartialDataSource {
+    name: String,
+    index: Option<usize>,
+    serie_type: Option<CreateDataSourceType>,
+    heartbeat: u64,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    last_ds: String,
+    value: f64,
+    unknown_sec: u64,
+    cdef: Option<String>,
+}
+
+#[derive(Default)]
+struct PartialRoundRobinArchive {
+    consolidation_function: Option<ConsolidationFunction>,
+    rows: u64,
+    cur_row: u64,
+    pdp_per_row: u64,
+    xfiles_factor: f64,
+    alpha: Option<f64>,
+    beta: Option<f64>,
+    gamma: Option<f64>,
+    smoothing_window: Option<f64>,
+    failure_threshold: Option<u64>,
+    window_length: Option<u64>,
+    cdp_prep: Vec<RrdInfoCdpPrep>,
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, RRDCachedClientError> {
+    value
+        .parse()
+        .map_err(|_| RRDCachedClientError::Parsing(format!("Unable to parse {}", key)))
+}
+
+/// Parse a value that is `NaN` when unknown.
+fn parse_optional_value(key: &str, value: &str) -> Result<Option<f64>, RRDCachedClientError> {
+    let value: f64 = parse_value(key, value)?;
+    Ok(if value.is_nan() { None } else { Some(value) })
+}
+
+/// Get the element at the index, adding it if it's the next one.
+///
+/// rrdtool lists the archives and their data sources in order,
+/// so the indexes given by the server never skip an element.
+fn get_or_insert<'a, T: Default>(
+    vec: &'a mut Vec<T>,
+    index: usize,
+    key: &str,
+) -> Result<&'a mut T, RRDCachedClientError> {
+    if index > vec.len() {
+        return Err(RRDCachedClientError::Parsing(format!(
+            "Unexpected index in {}",
+            key
+        )));
+    }
+    if index == vec.len() {
+        vec.push(T::default());
+    }
+    Ok(&mut vec[index])
+}
+
+impl RrdInfo {
+    pub fn from_lines(lines: Vec<String>) -> Result<RrdInfo, RRDCachedClientError> {
+        let mut filename = String::new();
+        let mut rrd_version = String::new();
+        let mut step = None;
+        let mut last_update = None;
+        let mut header_size = 0;
+        let mut data_sources: Vec<PartialDataSource> = Vec::new();
+        let mut round_robin_archives: Vec<PartialRoundRobinArchive> = Vec::new();
+
+        for line in lines.iter() {
+            let (key, value) = parse_info_line(line)?;
+            // Unknown keys are ignored, for forward compatibility
+            match parse_info_key(key)? {
+                InfoKey::Global(field) => match field {
+                    "filename" => filename = value.to_string(),
+                    "rrd_version" => rrd_version = value.to_string(),
+                    "step" => step = Some(parse_value(key, value)?),
+                    "last_update" => last_update = Some(parse_value(key, value)?),
+                    "header_size" => header_size = parse_value(key, value)?,
+                    _ => {}
+                },
+                InfoKey::DataSource(name, field) => {
+                    let data_source = match data_sources.iter().position(|ds| ds.name == name) {
+                        Some(position) => &mut data_sources[position],
+                        None => {
+                            data_sources.push(PartialDataSource {
+                                name: name.to_string(),
+                                ..Default::default()
+                            });
+                            data_sources.last_mut().unwrap()
+                        }
+                    };
+                    match field {
+                        "index" => data_source.index = Some(parse_value(key, value)?),
+                        "type" => data_source.serie_type = Some(value.parse()?),
+                        "minimal_heartbeat" => data_source.heartbeat = parse_value(key, value)?,
+                        "min" => data_source.minimum = parse_optional_value(key, value)?,
+                        "max" => data_source.maximum = parse_optional_value(key, value)?,
+                        "last_ds" => data_source.last_ds = value.to_string(),
+                        "value" => data_source.value = parse_value(key, value)?,
+                        "unknown_sec" => data_source.unknown_sec = parse_value(key, value)?,
+                        "cdef" => data_source.cdef = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+                InfoKey::RoundRobinArchive(index, field) => {
+                    let rra = get_or_insert(&mut round_robin_archives, index, key)?;
+                    match field {
+                        "cf" => rra.consolidation_function = Some(value.parse()?),
+                        "rows" => rra.rows = parse_value(key, value)?,
+                        "cur_row" => rra.cur_row = parse_value(key, value)?,
+                        "pdp_per_row" => rra.pdp_per_row = parse_value(key, value)?,
+                        "xff" => rra.xfiles_factor = parse_value(key, value)?,
+                        "alpha" => rra.alpha = Some(parse_value(key, value)?),
+                        "beta" => rra.beta = Some(parse_value(key, value)?),
+                        "gamma" => rra.gamma = Some(parse_value(key, value)?),
+                        "smoothing_window" => rra.smoothing_window = Some(parse_value(key, value)?),
+                        "failure_threshold" => {
+                            rra.failure_threshold = Some(parse_value(key, value)?)
+                        }
+                        "window_length" => rra.window_length = Some(parse_value(key, value)?),
+                        _ => {}
+                    }
+                }
+                InfoKey::CdpPrep(index, ds_index, field) => {
+                    let rra = get_or_insert(&mut round_robin_archives, index, key)?;
+                    let cdp_prep = get_or_insert(&mut rra.cdp_prep, ds_index, key)?;
+                    match field {
+                        "value" => cdp_prep.value = parse_value(key, value)?,
+                        "unknown_datapoints" => {
+                            cdp_prep.unknown_datapoints = parse_value(key, value)?
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut data_sources = data_sources
+            .into_iter()
+            .enumerate()
+            .map(|(position, ds)| {
+                Ok(RrdInfoDataSource {
+                    index: ds.index.unwrap_or(position),
+                    serie_type: ds.serie_type.ok_or_else(|| {
+                        RRDCachedClientError::Parsing(format!("Missing type for ds[{}]", ds.name))
+                    })?,
+                    name: ds.name,
+                    heartbeat: ds.heartbeat,
+                    minimum: ds.minimum,
+                    maximum: ds.maximum,
+                    last_ds: ds.last_ds,
+                    value: ds.value,
+                    unknown_sec: ds.unknown_sec,
+                    cdef: ds.cdef,
+                })
+            })
+            .collect::<Result<Vec<RrdInfoDataSource>, RRDCachedClientError>>()?;
+        data_sources.sort_by_key(|ds| ds.index);
+
+        let round_robin_archives = round_robin_archives
+            .into_iter()
+            .enumerate()
+            .map(|(index, rra)| {
+                Ok(RrdInfoRoundRobinArchive {
+                    consolidation_function: rra.consolidation_function.ok_or_else(|| {
+                        RRDCachedClientError::Parsing(format!("Missing cf for rra[{}]", index))
+                    })?,
+                    rows: rra.rows,
+                    cur_row: rra.cur_row,
+                    pdp_per_row: rra.pdp_per_row,
+                    xfiles_factor: rra.xfiles_factor,
+                    alpha: rra.alpha,
+                    beta: rra.beta,
+                    gamma: rra.gamma,
+                    smoothing_window: rra.smoothing_window,
+                    failure_threshold: rra.failure_threshold,
+                    window_length: rra.window_length,
+                    cdp_prep: rra.cdp_prep,
+                })
+            })
+            .collect::<Result<Vec<RrdInfoRoundRobinArchive>, RRDCachedClientError>>()?;
+
+        Ok(RrdInfo {
+            filename,
+            rrd_version,
+            step: step.ok_or_else(|| RRDCachedClientError::Parsing("Missing step".to_string()))?,
+            last_update: last_update
+                .ok_or_else(|| RRDCachedClientError::Parsing("Missing last_update".to_string()))?,
+            header_size,
+            data_sources,
+            round_robin_archives,
+        })
+    }
+
+    /// Get a data source by name
+    pub fn data_source(&self, name: &str) -> Option<&RrdInfoDataSource> {
+        self.data_sources.iter().find(|ds| ds.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_lines(input: &str) -> Vec<String> {
+        input.lines().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn test_successful_parse() {
+        let input = to_lines(
+            "filename 2 test.rrd
+rrd_version 2 0003
+step 1 300
+last_update 1 1700000000
+header_size 1 1208
+ds[temp].index 1 0
+ds[temp].type 2 GAUGE
+ds[temp].minimal_heartbeat 1 600
+ds[temp].min 0 NaN
+ds[temp].max 0 1.0000000000e+02
+ds[temp].last_ds 2 21.5
+ds[temp].value 0 4.3000000000e+01
+ds[temp].unknown_sec 1 0
+ds[count].index 1 1
+ds[count].type 2 COUNTER
+ds[count].minimal_heartbeat 1 600
+ds[count].min 0 0.0000000000e+00
+ds[count].max 0 NaN
+ds[count].last_ds 2 U
+ds[count].value 0 NaN
+ds[count].unknown_sec 1 2
+rra[0].cf 2 AVERAGE
+rra[0].rows 1 288
+rra[0].cur_row 1 12
+rra[0].pdp_per_row 1 1
+rra[0].xff 0 5.0000000000e-01
+rra[0].cdp_prep[0].value 0 NaN
+rra[0].cdp_prep[0].unknown_datapoints 1 0
+rra[0].cdp_prep[1].value 0 NaN
+rra[0].cdp_prep[1].unknown_datapoints 1 0
+rra[1].cf 2 MAX
+rra[1].rows 1 797
+rra[1].cur_row 1 3
+rra[1].pdp_per_row 1 12
+rra[1].xff 0 5.0000000000e-01
+rra[1].cdp_prep[0].value 0 2.3000000000e+01
+rra[1].cdp_prep[0].unknown_datapoints 1 1
+rra[1].cdp_prep[1].value 0 NaN
+rra[1].cdp_prep[1].unknown_datapoints 1 4
+",
+        );
+
+        let info = RrdInfo::from_lines(input).unwrap();
+        assert_eq!(info.filename, "test.rrd");
+        assert_eq!(info.rrd_version, "0003");
+        assert_eq!(info.step, 300);
+        assert_eq!(info.last_update, 1700000000);
+        assert_eq!(info.header_size, 1208);
+
+        assert_eq!(info.data_sources.len(), 2);
+        let temp = info.data_source("temp").unwrap();
+        assert_eq!(temp.index, 0);
+        assert_eq!(temp.serie_type, CreateDataSourceType::Gauge);
+        assert_eq!(temp.heartbeat, 600);
+        assert_eq!(temp.minimum, None);
+        assert_eq!(temp.maximum, Some(100.0));
+        assert_eq!(temp.last_ds, "21.5");
+        assert_eq!(temp.value, 43.0);
+        assert_eq!(temp.unknown_sec, 0);
+        let count = &info.data_sources[1];
+        assert_eq!(count.name, "count");
+        assert_eq!(count.serie_type, CreateDataSourceType::Counter);
+        assert_eq!(count.minimum, Some(0.0));
+        assert_eq!(count.maximum, None);
+        assert_eq!(count.last_ds, "U");
+        assert!(count.value.is_nan());
+        assert_eq!(count.unknown_sec, 2);
+
+        assert_eq!(info.round_robin_archives.len(), 2);
+        let rra = &info.round_robin_archives[1];
+        assert_eq!(rra.consolidation_function, ConsolidationFunction::Max);
+        assert_eq!(rra.rows, 797);
+        assert_eq!(rra.cur_row, 3);
+        assert_eq!(rra.pdp_per_row, 12);
+        assert_eq!(rra.xfiles_factor, 0.5);
+        assert_eq!(rra.cdp_prep.len(), 2);
+        assert_eq!(rra.cdp_prep[0].value, 23.0);
+        assert_eq!(rra.cdp_prep[0].unknown_datapoints, 1);
+        assert_eq!(rra.cdp_prep[1].unknown_datapoints, 4);
+    }
+
+    #[test]
+    fn test_rrdtool_format() {
+        let input = to_lines(
+            "filename = \"test.rrd\"
+rrd_version = \"0003\"
+step = 300
+last_update = 1700000000
+header_size = 1208
+ds[temp].index = 0
+ds[temp].type = \"GAUGE\"
+ds[temp].minimal_heartbeat = 600
+ds[temp].min = NaN
+ds[temp].max = NaN
+ds[temp].last_ds = \"U\"
+ds[temp].value = 0.0000000000e+00
+ds[temp].unknown_sec = 0
+rra[0].cf = \"AVERAGE\"
+rra[0].rows = 288
+rra[0].cur_row = 12
+rra[0].pdp_per_row = 1
+rra[0].xff = 5.0000000000e-01
+rra[0].cdp_prep[0].value = NaN
+rra[0].cdp_prep[0].unknown_datapoints = 0
+",
+        );
+
+        let info = RrdInfo::from_lines(input).unwrap();
+        assert_eq!(info.filename, "test.rrd");
+        assert_eq!(info.step, 300);
+        assert_eq!(info.data_sources[0].name, "temp");
+        assert_eq!(info.data_sources[0].serie_type, CreateDataSourceType::Gauge);
+        assert_eq!(
+            info.round_robin_archives[0].consolidation_function,
+            ConsolidationFunction::Average
+        );
+    }
+
+    #[test]
+    fn test_holt_winters_parse() {
+        let input = to_lines(
+            "step 1 300
+last_update 1 1700000000
+ds[temp].type 2 GAUGE
+rra[0].cf 2 HWPREDICT
+rra[0].rows 1 1440
+rra[0].pdp_per_row 1 1
+rra[0].alpha 0 1.0000000000e-01
+rra[0].beta 0 3.5000000000e-03
+rra[0].cdp_prep[0].intercept 0 NaN
+rra[1].cf 2 SEASONAL
+rra[1].rows 1 288
+rra[1].pdp_per_row 1 1
+rra[1].gamma 0 1.0000000000e-01
+rra[1].smoothing_window 0 5.0000000000e-02
+rra[2].cf 2 FAILURES
+rra[2].rows 1 288
+rra[2].pdp_per_row 1 1
+rra[2].delta_pos 0 2.0000000000e+00
+rra[2].delta_neg 0 2.0000000000e+00
+rra[2].failure_threshold 1 7
+rra[2].window_length 1 9
+",
+        );
+        let info = RrdInfo::from_lines(input).unwrap();
+        let hwpredict = &info.round_robin_archives[0];
+        assert_eq!(
+            hwpredict.consolidation_function,
+            ConsolidationFunction::HwPredict
+        );
+        assert_eq!(hwpredict.alpha, Some(0.1));
+        assert_eq!(hwpredict.beta, Some(0.0035));
+        assert_eq!(hwpredict.gamma, None);
+        let seasonal = &info.round_robin_archives[1];
+        assert_eq!(seasonal.gamma, Some(0.1));
+        assert_eq!(seasonal.smoothing_window, Some(0.05));
+        let failures = &info.round_robin_archives[2];
+        assert_eq!(failures.failure_threshold, Some(7));
+        assert_eq!(failures.window_length, Some(9));
+        assert_eq!(failures.xfiles_factor, 0.0);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_ignored() {
+        let input = to_lines(
+            "step 1 300
+last_update 1 1700000000
+something_new 2 hello
+ds[temp].type 2 GAUGE
+ds[temp].something_new 2 hello
+",
+        );
+        let info = RrdInfo::from_lines(input).unwrap();
+        assert_eq!(info.data_sources.len(), 1);
+        assert_eq!(info.data_sources[0].index, 0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let input = to_lines("step 1 abc\nlast_update 1 1700000000\n");
+        assert!(RrdInfo::from_lines(input).is_err());
+
+        // Missing step
+        let input = to_lines("last_update 1 1700000000\n");
+        assert!(RrdInfo::from_lines(input).is_err());
+
+        // Missing type
+        let input = to_lines("step 1 300\nlast_update 1 1700000000\nds[temp].index 1 0\n");
+        assert!(RrdInfo::from_lines(input).is_err());
+
+        // Unknown consolidation function
+        let input = to_lines("step 1 300\nlast_update 1 1700000000\nrra[0].cf 2 MEDIAN\n");
+        assert!(RrdInfo::from_lines(input).is_err());
+
+        let input = to_lines("step 300\n");
+        assert!(RrdInfo::from_lines(input).is_err());
+
+        // Indexes skipping archives
+        for key in [
+            "rra[18446744073709551615].rows",
+            "rra[1000000000].rows",
+            "rra[0].cdp_prep[18446744073709551615].value",
+        ] {
+            let input = to_lines(&format!(
+                "step 1 300\nlast_update 1 1700000000\n{} 1 1\n",
+                key
+            ));
+            assert!(matches!(
+                RrdInfo::from_lines(input),
+                Err(RRDCachedClientError::Parsing(_))
+            ));
+        }
+    }
+}
@@ -2,10 +2,27 @@ pub mod batch_update;
 pub mod client;
 pub mod consolidation_function;
 pub mod create;
+pub mod create_builder;
 pub mod errors;
 pub mod fetch;
+pub mod fetch_request;
+pub mod handle;
+pub mod holt_winters;
+pub mod info;
+pub mod list;
 pub mod now;
 pub mod parsers;
+pub mod pending;
+pub mod pipeline;
+pub mod resample;
+pub mod rpn;
+pub mod rrd_duration;
+pub mod rrd_path;
 pub mod sanitisation;
+pub mod stats;
+pub mod update_timestamp;
+pub mod update_value;
 
 pub use client::RRDCachedClient;
+pub use handle::RRDCachedHandle;
+pub use rrd_path::RrdPath;
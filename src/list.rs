@@ -0,0 +1,227 @@
+use crate::{
+    client::RRDCachedClient, errors::RRDCachedClientError, rrd_path::RrdPath,
+    sanitisation::check_rrd_directory,
+};
+
+/// An entry of the LIST command.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ListEntry {
+    /// A RRD file
+    File(RrdPath),
+
+    /// A directory, relative to the base directory of RRDCached,
+    /// without trailing slash
+    Directory(String),
+
+    /// A file without the .rrd extension, like a journal,
+    /// relative to the base directory of RRDCached
+    Other(String),
+}
+
+impl ListEntry {
+    /// Parse a line of the LIST response of a directory.
+    ///
+    /// RRDCached lists the entries relative to the listed directory,
+    /// and directories end with a slash. The entry is made relative
+    /// to the base directory of RRDCached.
+    ///
+    /// Files without the .rrd extension are [ListEntry::Other],
+    /// as they can't be used as a [RrdPath].
+    pub fn from_line(directory: &str, line: &str) -> Result<ListEntry, RRDCachedClientError> {
+        let line = line.trim_end_matches('\n').trim_start_matches('/');
+        let (name, is_directory) = match line.strip_suffix('/') {
+            Some(name) => (name, true),
+            None => (line, false),
+        };
+        if name.is_empty() {
+            return Err(RRDCachedClientError::Parsing(
+                "empty list entry".to_string(),
+            ));
+        }
+
+        let mut path = String::with_capacity(directory.len() + 1 + name.len());
+        if !directory.is_empty() {
+            path.push_str(directory);
+            path.push('/');
+        }
+        path.push_str(name);
+
+        if is_directory {
+            check_rrd_directory(&path)?;
+            Ok(ListEntry::Directory(path))
+        } else if path.ends_with(".rrd") {
+            Ok(ListEntry::File(RrdPath::new(&path)?))
+        } else {
+            check_rrd_directory(&path)?;
+            Ok(ListEntry::Other(path))
+        }
+    }
+
+    /// Path of the entry, without the .rrd extension for files.
+    pub fn path(&self) -> &str {
+        match self {
+            ListEntry::File(path) => path.name(),
+            ListEntry::Directory(path) | ListEntry::Other(path) => path,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self, ListEntry::File(_))
+    }
+
+    pub fn is_directory(&self) -> bool {
+        matches!(self, ListEntry::Directory(_))
+    }
+
+    pub fn is_other(&self) -> bool {
+        matches!(self, ListEntry::Other(_))
+    }
+
+    /// Whether the entry matches a glob pattern, such as `hosts/*/cpu*`.
+    ///
+    /// The pattern is matched against [ListEntry::path].
+    /// `*` matches any characters but `/`, `**` matches any characters
+    /// including `/`, and `?` matches a single character but `/`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        glob_match(pattern.as_bytes(), self.path().as_bytes())
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let end = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=end).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'?', rest @ ..] => match text {
+            [c, text @ ..] if *c != b'/' => glob_match(rest, text),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text @ ..] if c == p => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
+
+/// Directory to list and whether to list it recursively
+/// to find the entries matching a glob pattern.
+///
+/// The directory is made of the components before the first wildcard.
+pub(crate) fn glob_list_arguments(pattern: &str) -> (&str, bool) {
+    let first_wildcard = pattern.find(['*', '?']).unwrap_or(pattern.len());
+    let directory_end = pattern[..first_wildcard].rfind('/').unwrap_or(0);
+    let rest = pattern[directory_end..].trim_start_matches('/');
+    let recursive = rest.contains('/') || rest.contains("**");
+    (&pattern[..directory_end], recursive)
+}
+
+/// Entries of a LIST response, read one by one from the server.
+///
+/// Useful for huge recursive listings. The entries that are not read
+/// are discarded before the next command.
+pub struct ListEntries<'a, T> {
+    client: &'a mut RRDCachedClient<T>,
+    directory: String,
+}
+
+impl<'a, T> ListEntries<'a, T>
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    pub(crate) fn new(client: &'a mut RRDCachedClient<T>, directory: String) -> Self {
+        ListEntries { client, directory }
+    }
+
+    /// Next entry, or `None` when all the entries have been read.
+    pub async fn next(&mut self) -> Option<Result<ListEntry, RRDCachedClientError>> {
+        let line = self.client.read_unread_line().await?;
+        Some(line.and_then(|line| ListEntry::from_line(&self.directory, &line)))
+    }
+
+    /// Read all the remaining entries.
+    ///
+    /// Fails only if the entries can't be read anymore,
+    /// the invalid entries are reported one by one.
+    pub async fn read_all(
+        self,
+    ) -> Result<Vec<Result<ListEntry, RRDCachedClientError>>, RRDCachedClientError> {
+        let mut entries = Vec::new();
+        while let Some(line) = self.client.read_unread_line().await {
+            entries.push(ListEntry::from_line(&self.directory, &line?));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_line() {
+        let entry = ListEntry::from_line("", "test.rrd\n").unwrap();
+        assert_eq!(entry, ListEntry::File(RrdPath::new("test").unwrap()));
+        assert_eq!(entry.path(), "test");
+        assert!(entry.is_file());
+
+        let entry = ListEntry::from_line("hosts", "server-1/\n").unwrap();
+        assert_eq!(entry, ListEntry::Directory("hosts/server-1".to_string()));
+        assert_eq!(entry.path(), "hosts/server-1");
+        assert!(entry.is_directory());
+
+        let entry = ListEntry::from_line("hosts", "server-1/cpu.rrd\n").unwrap();
+        assert_eq!(
+            entry,
+            ListEntry::File(RrdPath::new("hosts/server-1/cpu").unwrap())
+        );
+
+        // Files that are not RRDs are kept as they are
+        let entry = ListEntry::from_line("hosts", "server-1/cpu.rrd.journal\n").unwrap();
+        assert_eq!(
+            entry,
+            ListEntry::Other("hosts/server-1/cpu.rrd.journal".to_string())
+        );
+        assert_eq!(entry.path(), "hosts/server-1/cpu.rrd.journal");
+        assert!(entry.is_other());
+        assert_eq!(
+            ListEntry::from_line("", "README\n").unwrap(),
+            ListEntry::Other("README".to_string())
+        );
+
+        assert!(ListEntry::from_line("", "\n").is_err());
+        assert!(ListEntry::from_line("", "../test.rrd\n").is_err());
+        assert!(ListEntry::from_line("", "with space.rrd\n").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let entry = ListEntry::File(RrdPath::new("hosts/server-1/cpu-user").unwrap());
+        assert!(entry.matches("hosts/*/cpu*"));
+        assert!(entry.matches("hosts/server-?/cpu-user"));
+        assert!(entry.matches("hosts/**"));
+        assert!(entry.matches("**/cpu-user"));
+        assert!(!entry.matches("hosts/*"));
+        assert!(!entry.matches("*/cpu*"));
+        assert!(!entry.matches("hosts/*/memory*"));
+        assert!(!entry.matches("hosts/server-1/cpu-user.rrd"));
+
+        let entry = ListEntry::Directory("hosts/server-1".to_string());
+        assert!(entry.matches("hosts/*"));
+    }
+
+    #[test]
+    fn test_glob_list_arguments() {
+        assert_eq!(glob_list_arguments("hosts/*/cpu*"), ("hosts", true));
+        assert_eq!(
+            glob_list_arguments("hosts/server-1/cpu*"),
+            ("hosts/server-1", false)
+        );
+        assert_eq!(glob_list_arguments("cpu*"), ("", false));
+        assert_eq!(glob_list_arguments("**/cpu"), ("", true));
+        assert_eq!(glob_list_arguments("hosts/**"), ("hosts", true));
+        assert_eq!(glob_list_arguments("hosts/cpu"), ("hosts", false));
+    }
+}
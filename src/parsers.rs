@@ -1,11 +1,13 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until1},
-    character::complete::{i64 as parse_i64, newline, not_line_ending, space1, u64 as parse_u64},
-    combinator::value,
+    bytes::complete::{is_not, tag, take_until, take_until1},
+    character::complete::{
+        i64 as parse_i64, newline, not_line_ending, one_of, space1, u64 as parse_u64,
+    },
+    combinator::{all_consuming, cut, map, map_opt, opt, rest, value},
     multi::separated_list1,
     number::complete::double,
-    sequence::{terminated, tuple},
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 
@@ -50,6 +52,21 @@ pub fn parse_stats_line(input: &str) -> Result<(&str, i64), RRDCachedClientError
     }
 }
 
+/// Parse a line of the STATS response, whatever the type of the value.
+pub fn parse_stats_raw_line(input: &str) -> Result<(&str, &str), RRDCachedClientError> {
+    let parse_result: IResult<&str, (&str, &str, &str, &str)> = tuple((
+        take_until1(":"),
+        tag(":"),
+        space1,
+        terminated(not_line_ending, newline),
+    ))(input);
+
+    match parse_result {
+        Ok((_, (name, _, _, value))) => Ok((name, value)),
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
 pub fn parse_timestamp(input: &str) -> Result<usize, RRDCachedClientError> {
     let parse_result: IResult<&str, u64> = parse_u64(input);
     match parse_result {
@@ -72,17 +89,176 @@ pub fn parse_fetch_header_line(input: &str) -> Result<(String, String), RRDCache
     }
 }
 
+/// Parse a line of the FETCH response into a timestamp and values.
+///
+/// Such as `1708800040: 1.0000000000e+01 nan`. On error, the remaining
+/// input points at the invalid part of the line.
 pub fn parse_fetch_line(input: &str) -> IResult<&str, (usize, Vec<f64>)> {
     tuple((
         parse_u64,
         tag(":"),
         space1,
-        separated_list1(space1, alt((double, value(f64::NAN, tag("-nan"))))),
+        separated_list1(space1, cut(parse_fetch_value)),
         newline,
     ))(input)
     .map(|(i, (timestamp, _, _, values, _))| (i, (timestamp as usize, values)))
 }
 
+/// Parse a value of a FETCH line, as printed by the libc of RRDCached.
+///
+/// Unknown values may be `nan`, `-nan` or `NaN` and infinite values `inf`,
+/// `-inf` or `Infinity`. Some locales use a decimal comma, like `1,0000000000e+01`.
+fn parse_fetch_value(input: &str) -> IResult<&str, f64> {
+    map_opt(is_not(" \t\r\n"), |token: &str| {
+        if token.contains(',') && !token.contains('.') {
+            token.replacen(',', ".", 1).parse().ok()
+        } else {
+            token.parse().ok()
+        }
+    })(input)
+}
+
+/// Parse the header of a column of the FETCHBIN response into the name
+/// of the data source, the number of values, the size of a value in bytes
+/// and the byte order.
+///
+/// Such as `DSName-ds1: BinaryData 10 8 LITTLEENDIAN`, followed by the values.
+pub fn parse_fetch_binary_header_line(
+    input: &str,
+) -> Result<(&str, usize, usize, &str), RRDCachedClientError> {
+    let parse_result: IResult<&str, (&str, u64, u64, &str)> = all_consuming(tuple((
+        delimited(tag("DSName-"), take_until1(": "), tag(": BinaryData ")),
+        terminated(parse_u64, space1),
+        terminated(parse_u64, space1),
+        terminated(alt((tag("LITTLEENDIAN"), tag("BIGENDIAN"))), newline),
+    )))(input);
+
+    match parse_result {
+        Ok((_, (name, count, size, byte_order))) => {
+            Ok((name, count as usize, size as usize, byte_order))
+        }
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
+/// Parse the lines describing the samples rejected during an UPDATE.
+///
+/// Such as `illegal attempt to update using time 1700000000.000000 when last update time is 1700000005.000000 (minimum one second step)`
+pub fn parse_rejected_update_line(input: &str) -> Result<(f64, &str), RRDCachedClientError> {
+    let parse_result: IResult<&str, (&str, f64)> =
+        tuple((tag("illegal attempt to update using time "), double))(input);
+
+    match parse_result {
+        Ok((_, (_, timestamp))) => Ok((timestamp, input.trim_end_matches('\n'))),
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
+/// Parse a line of the PENDING response into a timestamp and values.
+///
+/// Such as `1700000000:1.5:U`, where `U` is an unknown value.
+pub fn parse_pending_line(input: &str) -> Result<(f64, Vec<Option<f64>>), RRDCachedClientError> {
+    let parse_result: IResult<&str, (f64, Vec<Option<f64>>)> = all_consuming(tuple((
+        terminated(double, tag(":")),
+        terminated(
+            separated_list1(tag(":"), alt((value(None, tag("U")), map(double, Some)))),
+            opt(newline),
+        ),
+    )))(input);
+
+    match parse_result {
+        Ok((_, (timestamp, values))) => Ok((timestamp, values)),
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
+/// Parse a duration in the rrdtool time-spec syntax, such as `5m` or `30d`,
+/// into a number and an optional unit.
+pub fn parse_duration(input: &str) -> Result<(u64, Option<char>), RRDCachedClientError> {
+    let parse_result: IResult<&str, (u64, Option<char>)> =
+        all_consuming(tuple((parse_u64, opt(one_of("smhdwMy")))))(input);
+
+    match parse_result {
+        Ok((_, (value, unit))) => Ok((value, unit)),
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
+/// Parse a line of the INFO response into a key and a raw value.
+///
+/// RRDCached sends `key type value`, such as `ds[temp].type 2 GAUGE`,
+/// where type is the rrd_info value type. The `key = value` format
+/// of `rrdtool info`, such as `ds[temp].type = "GAUGE"`, is also supported.
+pub fn parse_info_line(input: &str) -> Result<(&str, &str), RRDCachedClientError> {
+    let parse_result: IResult<&str, (&str, &str)> = tuple((
+        terminated(is_not(" \n"), space1),
+        terminated(
+            alt((
+                preceded(tuple((one_of("01234"), space1)), not_line_ending),
+                preceded(
+                    tuple((tag("="), space1)),
+                    alt((
+                        delimited(tag("\""), take_until("\""), tag("\"")),
+                        not_line_ending,
+                    )),
+                ),
+            )),
+            newline,
+        ),
+    ))(input);
+
+    match parse_result {
+        Ok((_, (key, value))) => Ok((key, value)),
+        Err(_) => Err(RRDCachedClientError::Parsing("parse error".to_string())),
+    }
+}
+
+/// Key of an INFO line.
+#[derive(Debug, PartialEq)]
+pub enum InfoKey<'a> {
+    /// Such as `step`
+    Global(&'a str),
+    /// Such as `ds[temp].type`
+    DataSource(&'a str, &'a str),
+    /// Such as `rra[0].cf`
+    RoundRobinArchive(usize, &'a str),
+    /// Such as `rra[0].cdp_prep[1].value`
+    CdpPrep(usize, usize, &'a str),
+}
+
+pub fn parse_info_key(input: &str) -> Result<InfoKey<'_>, RRDCachedClientError> {
+    let parse_result: IResult<&str, InfoKey> = all_consuming(alt((
+        map(
+            tuple((tag("ds["), take_until1("]"), tag("]."), rest)),
+            |(_, name, _, field)| InfoKey::DataSource(name, field),
+        ),
+        map(
+            tuple((
+                tag("rra["),
+                parse_u64,
+                tag("].cdp_prep["),
+                parse_u64,
+                tag("]."),
+                rest,
+            )),
+            |(_, rra, _, ds, _, field)| InfoKey::CdpPrep(rra as usize, ds as usize, field),
+        ),
+        map(
+            tuple((tag("rra["), parse_u64, tag("]."), rest)),
+            |(_, rra, _, field)| InfoKey::RoundRobinArchive(rra as usize, field),
+        ),
+        map(is_not("[]. "), InfoKey::Global),
+    )))(input);
+
+    match parse_result {
+        Ok((_, key)) => Ok(key),
+        Err(_) => Err(RRDCachedClientError::Parsing(format!(
+            "invalid info key: {}",
+            input
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +320,25 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_stats_raw_line() {
+        let input = "QueueLength: 0\n";
+        let result = parse_stats_raw_line(input);
+        assert_eq!(result.unwrap(), ("QueueLength", "0"));
+
+        let input = "Version: 1.7.2\n";
+        let result = parse_stats_raw_line(input);
+        assert_eq!(result.unwrap(), ("Version", "1.7.2"));
+
+        let input = "QueueLength: 0";
+        let result = parse_stats_raw_line(input);
+        assert!(result.is_err());
+
+        let input = "QueueLength 0\n";
+        let result = parse_stats_raw_line(input);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_parse_timestamp() {
         let input = "1234";
@@ -221,4 +416,263 @@ mod tests {
         assert_eq!(result.1.len(), 2);
         assert!(result.1.iter().all(|f| f.is_nan()));
     }
+
+    #[test]
+    fn test_parse_fetch_binary_header_line() {
+        assert_eq!(
+            parse_fetch_binary_header_line("DSName-ds1: BinaryData 10 8 LITTLEENDIAN\n").unwrap(),
+            ("ds1", 10, 8, "LITTLEENDIAN")
+        );
+        assert_eq!(
+            parse_fetch_binary_header_line("DSName-in-bytes: BinaryData 0 4 BIGENDIAN\n").unwrap(),
+            ("in-bytes", 0, 4, "BIGENDIAN")
+        );
+        assert!(parse_fetch_binary_header_line("DSName: ds1 ds2\n").is_err());
+        assert!(
+            parse_fetch_binary_header_line("DSName-ds1: BinaryData 10 8 MIDDLEENDIAN\n").is_err()
+        );
+        assert!(
+            parse_fetch_binary_header_line("DSName-ds1: BinaryData 10 8 LITTLEENDIAN").is_err()
+        );
+        assert!(
+            parse_fetch_binary_header_line("DSName-ds1: BinaryData x 8 LITTLEENDIAN\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_fetch_value_forms() {
+        for (token, expected) in [
+            ("1.0000000000e+01", 10.0),
+            ("1.0000000000e-01", 0.1),
+            ("-2.5000000000e+00", -2.5),
+            ("1.0000000000E+01", 10.0),
+            ("4.2", 4.2),
+            ("100000", 100000.0),
+            ("+3", 3.0),
+            ("0", 0.0),
+            (".5", 0.5),
+            ("1,0000000000e+01", 10.0),
+            ("-1,5", -1.5),
+            ("inf", f64::INFINITY),
+            ("INF", f64::INFINITY),
+            ("+inf", f64::INFINITY),
+            ("Infinity", f64::INFINITY),
+            ("-inf", f64::NEG_INFINITY),
+            ("-Infinity", f64::NEG_INFINITY),
+        ] {
+            let line = format!("1708800040: {}\n", token);
+            assert_eq!(
+                parse_fetch_line(&line).unwrap().1 .1,
+                vec![expected],
+                "{}",
+                token
+            );
+        }
+
+        for token in ["nan", "-nan", "NaN", "-NaN", "NAN", "+nan"] {
+            let line = format!("1708800040: {} 1\n", token);
+            let values = parse_fetch_line(&line).unwrap().1 .1;
+            assert!(values[0].is_nan(), "{}", token);
+            assert_eq!(values[1], 1.0);
+        }
+
+        for token in [
+            "abc", "1e", "-", "1,2,3", "1.5,3", "0x10", "U", "1.0.0", "nan(0x1)",
+        ] {
+            let line = format!("1708800040: 1 {} 2\n", token);
+            match parse_fetch_line(&line) {
+                Err(nom::Err::Failure(error)) => {
+                    // The error points at the invalid value
+                    assert_eq!(error.input.len(), token.len() + 3, "{}", token)
+                }
+                result => panic!("{}: {:?}", token, result),
+            }
+        }
+    }
+
+    /// Format a value like `%0.10e` in C, as rrdcached does.
+    fn format_like_c(value: f64) -> String {
+        if value.is_infinite() {
+            return value.to_string();
+        }
+        let formatted = format!("{:.10e}", value);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap();
+        let exponent: i32 = exponent.parse().unwrap();
+        format!(
+            "{}e{}{:02}",
+            mantissa,
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    }
+
+    #[test]
+    fn test_fetch_line_corpus() {
+        // Deterministic pseudo-random values, in every form rrdcached may print
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..500 {
+            let count = (next() % 5 + 1) as usize;
+            let mut expected = Vec::with_capacity(count);
+            let mut tokens = Vec::with_capacity(count);
+            for _ in 0..count {
+                let value = match next() % 8 {
+                    0 => f64::NAN,
+                    1 => f64::INFINITY,
+                    2 => f64::NEG_INFINITY,
+                    3 => (next() % 1000) as f64,
+                    _ => {
+                        let value = f64::from_bits(next());
+                        if value.is_finite() {
+                            value
+                        } else {
+                            0.0
+                        }
+                    }
+                };
+                let token = match (value.is_nan(), next() % 4) {
+                    (true, 0) => "nan".to_string(),
+                    (true, 1) => "-nan".to_string(),
+                    (true, _) => "NaN".to_string(),
+                    (false, 0) => value.to_string(),
+                    (false, 1) => format_like_c(value).replace('.', ","),
+                    (false, _) => format_like_c(value),
+                };
+                expected.push(value);
+                tokens.push(token);
+            }
+            let line = format!("1708800040: {}\n", tokens.join(" "));
+            let (remaining, (timestamp, values)) = parse_fetch_line(&line).unwrap();
+            assert_eq!(remaining, "");
+            assert_eq!(timestamp, 1708800040);
+            assert_eq!(values.len(), expected.len(), "{}", line);
+            for (value, expected) in values.iter().zip(&expected) {
+                if expected.is_nan() {
+                    assert!(value.is_nan(), "{}", line);
+                } else if expected.is_infinite() || *expected == 0.0 {
+                    assert_eq!(value, expected, "{}", line);
+                } else {
+                    assert!(
+                        ((value - expected) / expected).abs() < 1e-9,
+                        "{}: {} != {}",
+                        line,
+                        value,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_rejected_update_line() {
+        let input = "illegal attempt to update using time 1700000000.000000 when last update time is 1700000005.000000 (minimum one second step)\n";
+        let result = parse_rejected_update_line(input);
+        assert_eq!(
+            result.unwrap(),
+            (
+                1700000000.0,
+                "illegal attempt to update using time 1700000000.000000 when last update time is 1700000005.000000 (minimum one second step)"
+            )
+        );
+
+        let input = "No values updated.\n";
+        let result = parse_rejected_update_line(input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_pending_line() {
+        let input = "1700000000:1.5:U\n";
+        let result = parse_pending_line(input);
+        assert_eq!(result.unwrap(), (1700000000.0, vec![Some(1.5), None]));
+
+        let input = "1700000000.25:-3:42";
+        let result = parse_pending_line(input);
+        assert_eq!(
+            result.unwrap(),
+            (1700000000.25, vec![Some(-3.0), Some(42.0)])
+        );
+
+        let input = "1700000000\n";
+        let result = parse_pending_line(input);
+        assert!(result.is_err());
+
+        let input = "1700000000:1.5:abc\n";
+        let result = parse_pending_line(input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("300").unwrap(), (300, None));
+        assert_eq!(parse_duration("5m").unwrap(), (5, Some('m')));
+        assert_eq!(parse_duration("1M").unwrap(), (1, Some('M')));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5 m").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("-5m").is_err());
+        assert!(parse_duration("5mm").is_err());
+    }
+
+    #[test]
+    fn test_parse_info_line() {
+        let input = "ds[temp].type 2 GAUGE\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("ds[temp].type", "GAUGE"));
+
+        let input = "rra[0].xff 0 5.0000000000e-01\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("rra[0].xff", "5.0000000000e-01"));
+
+        let input = "ds[temp].min 0 NaN\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("ds[temp].min", "NaN"));
+
+        let input = "ds[temp].type = \"GAUGE\"\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("ds[temp].type", "GAUGE"));
+
+        let input = "step = 300\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("step", "300"));
+
+        let input = "ds[temp].last_ds = \"\"\n";
+        let result = parse_info_line(input);
+        assert_eq!(result.unwrap(), ("ds[temp].last_ds", ""));
+
+        let input = "step 1 300";
+        let result = parse_info_line(input);
+        assert!(result.is_err());
+
+        let input = "step 300\n";
+        let result = parse_info_line(input);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_info_key() {
+        assert_eq!(parse_info_key("step").unwrap(), InfoKey::Global("step"));
+        assert_eq!(
+            parse_info_key("ds[temp].minimal_heartbeat").unwrap(),
+            InfoKey::DataSource("temp", "minimal_heartbeat")
+        );
+        assert_eq!(
+            parse_info_key("rra[12].cf").unwrap(),
+            InfoKey::RoundRobinArchive(12, "cf")
+        );
+        assert_eq!(
+            parse_info_key("rra[1].cdp_prep[0].unknown_datapoints").unwrap(),
+            InfoKey::CdpPrep(1, 0, "unknown_datapoints")
+        );
+        assert!(parse_info_key("rra[x].cf").is_err());
+        assert!(parse_info_key("ds[].type").is_err());
+        assert!(parse_info_key("").is_err());
+    }
 }
@@ -0,0 +1,66 @@
+use crate::{
+    errors::RRDCachedClientError, parsers::parse_pending_line, update_timestamp::UpdateTimestamp,
+};
+
+/// An update enqueued in the server, not yet written to disk,
+/// as returned by the PENDING command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingSample {
+    /// Timestamp of the update
+    pub timestamp: UpdateTimestamp,
+
+    /// Values of the update, in the order of the data sources, `None` when unknown
+    pub values: Vec<Option<f64>>,
+}
+
+impl PendingSample {
+    pub fn from_line(line: &str) -> Result<PendingSample, RRDCachedClientError> {
+        let (timestamp, values) = parse_pending_line(line)?;
+        Ok(PendingSample {
+            timestamp: UpdateTimestamp::from_secs_f64(timestamp),
+            values,
+        })
+    }
+
+    pub fn from_lines(lines: Vec<String>) -> Result<Vec<PendingSample>, RRDCachedClientError> {
+        lines
+            .iter()
+            .map(|line| PendingSample::from_line(line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_successful_parse() {
+        let input = vec![
+            "1700000000:1.5:U\n".to_string(),
+            "1700000000.5:U:2\n".to_string(),
+        ];
+        let samples = PendingSample::from_lines(input).unwrap();
+        assert_eq!(
+            samples,
+            vec![
+                PendingSample {
+                    timestamp: UpdateTimestamp::Seconds(1700000000),
+                    values: vec![Some(1.5), None],
+                },
+                PendingSample {
+                    timestamp: UpdateTimestamp::Precise(Duration::from_millis(1700000000500)),
+                    values: vec![None, Some(2.0)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let input = vec!["1700000000:1.5\n".to_string(), "garbage\n".to_string()];
+        assert!(PendingSample::from_lines(input).is_err());
+    }
+}
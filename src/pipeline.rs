@@ -0,0 +1,525 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::{
+    batch_update::BatchUpdate,
+    create::CreateArguments,
+    errors::RRDCachedClientError,
+    fetch::FetchResponse,
+    fetch_request::{FetchConsistency, FetchRequest},
+    info::RrdInfo,
+    parsers::{parse_response_line, parse_timestamp},
+    pending::PendingSample,
+    rrd_path::RrdPath,
+    stats::ServerStats,
+    update_timestamp::UpdateTimestamp,
+    update_value::UpdateValue,
+};
+
+/// Kind of a command of a pipeline, telling how to parse its response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PipelineCommand {
+    Ping,
+    Create,
+    Update,
+    Flush,
+    FlushAll,
+    Pending,
+    Forget,
+    Stats,
+    First,
+    Last,
+    Info,
+    Fetch,
+
+    /// FLUSH sent before a FETCH, whose response is not returned
+    FlushBeforeFetch,
+}
+
+impl PipelineCommand {
+    /// Whether the response of the command is returned.
+    pub(crate) fn has_response(self) -> bool {
+        self != PipelineCommand::FlushBeforeFetch
+    }
+
+    /// Parse the response of the command, from the message of its
+    /// response line and the lines following it.
+    pub(crate) fn parse_response(
+        self,
+        message: &str,
+        lines: Vec<String>,
+    ) -> Result<PipelineResponse, RRDCachedClientError> {
+        match self {
+            PipelineCommand::Ping => Ok(PipelineResponse::Pong),
+            PipelineCommand::Create => {
+                if message == "RRD created OK" {
+                    Ok(PipelineResponse::Created)
+                } else {
+                    Err(RRDCachedClientError::UnexpectedResponse(
+                        0,
+                        message.to_string(),
+                    ))
+                }
+            }
+            PipelineCommand::Update => {
+                // A single sample is either rejected with an error code,
+                // or accepted without error lines
+                if lines.is_empty() {
+                    Ok(PipelineResponse::Updated)
+                } else {
+                    Err(RRDCachedClientError::UnexpectedResponse(
+                        lines.len() as i64,
+                        lines.join(""),
+                    ))
+                }
+            }
+            PipelineCommand::Flush | PipelineCommand::FlushBeforeFetch => {
+                Ok(PipelineResponse::Flushed)
+            }
+            PipelineCommand::FlushAll => Ok(PipelineResponse::FlushedAll),
+            PipelineCommand::Pending => {
+                Ok(PipelineResponse::Pending(PendingSample::from_lines(lines)?))
+            }
+            PipelineCommand::Forget => Ok(PipelineResponse::Forgotten),
+            PipelineCommand::Stats => Ok(PipelineResponse::Stats(ServerStats::from_lines(lines)?)),
+            PipelineCommand::First => Ok(PipelineResponse::First(parse_timestamp(message)?)),
+            PipelineCommand::Last => Ok(PipelineResponse::Last(parse_timestamp(message)?)),
+            PipelineCommand::Info => Ok(PipelineResponse::Info(RrdInfo::from_lines(lines)?)),
+            PipelineCommand::Fetch => {
+                Ok(PipelineResponse::Fetch(FetchResponse::from_lines(lines)?))
+            }
+        }
+    }
+}
+
+/// Response to a command of a pipeline, in the order of the commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineResponse {
+    /// Response to [Pipeline::ping]
+    Pong,
+
+    /// Response to [Pipeline::create]
+    Created,
+
+    /// Response to [Pipeline::update] and [Pipeline::update_one]
+    Updated,
+
+    /// Response to [Pipeline::flush]
+    Flushed,
+
+    /// Response to [Pipeline::flush_all]
+    FlushedAll,
+
+    /// Response to [Pipeline::pending]
+    Pending(Vec<PendingSample>),
+
+    /// Response to [Pipeline::forget]
+    Forgotten,
+
+    /// Response to [Pipeline::stats]
+    Stats(ServerStats),
+
+    /// Response to [Pipeline::first]
+    First(usize),
+
+    /// Response to [Pipeline::last]
+    Last(usize),
+
+    /// Response to [Pipeline::info]
+    Info(RrdInfo),
+
+    /// Response to [Pipeline::fetch]
+    Fetch(FetchResponse),
+}
+
+/// Commands written back-to-back on a connection by
+/// [crate::RRDCachedClient::execute], without waiting for their responses.
+///
+/// The invalid arguments of a command are reported by
+/// [crate::RRDCachedClient::execute], before sending anything.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    commands: Vec<(String, PipelineCommand)>,
+    error: Option<RRDCachedClientError>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Number of responses returned by the pipeline.
+    pub fn len(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|(_, command)| command.has_response())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(mut self, command: String, kind: PipelineCommand) -> Self {
+        self.commands.push((command, kind));
+        self
+    }
+
+    fn push_path(self, name: &str, path: &RrdPath, kind: PipelineCommand) -> Self {
+        let path = path.as_str();
+        let mut command = String::with_capacity(name.len() + 1 + path.len() + 1);
+        command.push_str(name);
+        command.push(' ');
+        command.push_str(path);
+        command.push('\n');
+        self.push(command, kind)
+    }
+
+    fn push_result(
+        mut self,
+        command: Result<String, RRDCachedClientError>,
+        kind: PipelineCommand,
+    ) -> Self {
+        match command {
+            Ok(command) => self.push(command, kind),
+            Err(error) => {
+                self.error.get_or_insert(error);
+                self
+            }
+        }
+    }
+
+    /// Ping the server.
+    pub fn ping(self) -> Self {
+        self.push("PING\n".to_string(), PipelineCommand::Ping)
+    }
+
+    /// Create a RRD, see [crate::RRDCachedClient::create].
+    pub fn create(self, arguments: CreateArguments) -> Self {
+        let command = arguments.validate_structure().map(|_| {
+            let arguments_str = arguments.to_str();
+            let mut command = String::with_capacity(7 + arguments_str.len() + 1);
+            command.push_str("CREATE ");
+            command.push_str(&arguments_str);
+            command.push('\n');
+            command
+        });
+        self.push_result(command, PipelineCommand::Create)
+    }
+
+    /// Update a RRD with a list of values at a specific timestamp,
+    /// see [crate::RRDCachedClient::update].
+    pub fn update<V: Into<UpdateValue>>(
+        self,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: Vec<V>,
+    ) -> Self {
+        let command = BatchUpdate::new(path.clone(), timestamp, data)
+            .and_then(|update| update.to_command_string());
+        self.push_result(command, PipelineCommand::Update)
+    }
+
+    /// Update a RRD with a single value at a specific timestamp.
+    pub fn update_one<V: Into<UpdateValue>>(
+        self,
+        path: &RrdPath,
+        timestamp: UpdateTimestamp,
+        data: V,
+    ) -> Self {
+        self.update(path, timestamp, vec![data])
+    }
+
+    /// Flush a RRD.
+    pub fn flush(self, path: &RrdPath) -> Self {
+        self.push_path("FLUSH", path, PipelineCommand::Flush)
+    }
+
+    /// Flush all RRDs.
+    pub fn flush_all(self) -> Self {
+        self.push("FLUSHALL\n".to_string(), PipelineCommand::FlushAll)
+    }
+
+    /// Pending updates of a RRD.
+    pub fn pending(self, path: &RrdPath) -> Self {
+        self.push_path("PENDING", path, PipelineCommand::Pending)
+    }
+
+    /// Forget the pending updates of a RRD.
+    pub fn forget(self, path: &RrdPath) -> Self {
+        self.push_path("FORGET", path, PipelineCommand::Forget)
+    }
+
+    /// Statistics of the server.
+    pub fn stats(self) -> Self {
+        self.push("STATS\n".to_string(), PipelineCommand::Stats)
+    }
+
+    /// First timestamp of a round robin archive, the first one when `None`.
+    pub fn first(self, path: &RrdPath, round_robin_archive: Option<usize>) -> Self {
+        let path = path.as_str();
+        let rranum_str = round_robin_archive.unwrap_or(0).to_string();
+        let mut command = String::with_capacity(6 + path.len() + 1 + rranum_str.len() + 1);
+        command.push_str("FIRST ");
+        command.push_str(path);
+        command.push(' ');
+        command.push_str(&rranum_str);
+        command.push('\n');
+        self.push(command, PipelineCommand::First)
+    }
+
+    /// Last update timestamp of a RRD.
+    pub fn last(self, path: &RrdPath) -> Self {
+        self.push_path("LAST", path, PipelineCommand::Last)
+    }
+
+    /// Information about a RRD.
+    pub fn info(self, path: &RrdPath) -> Self {
+        self.push_path("INFO", path, PipelineCommand::Info)
+    }
+
+    /// Fetch the content of a RRD.
+    ///
+    /// The commands can't depend on the responses, so
+    /// [FetchConsistency::FlushIfPending] always flushes the RRD.
+    pub fn fetch(self, request: FetchRequest) -> Self {
+        let pipeline = match request.consistency {
+            FetchConsistency::Cached => self,
+            FetchConsistency::Flush | FetchConsistency::FlushIfPending => {
+                self.push_path("FLUSH", &request.path, PipelineCommand::FlushBeforeFetch)
+            }
+        };
+        pipeline.push_result(request.to_command(), PipelineCommand::Fetch)
+    }
+
+    /// Fetch several RRDs, to join their responses with [join_fetch_responses].
+    pub(crate) fn fetch_many(requests: &[FetchRequest]) -> Self {
+        requests
+            .iter()
+            .cloned()
+            .fold(Pipeline::new(), Pipeline::fetch)
+    }
+
+    /// Commands of the pipeline, or the first invalid argument.
+    pub(crate) fn into_commands(
+        self,
+    ) -> Result<Vec<(String, PipelineCommand)>, RRDCachedClientError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.commands),
+        }
+    }
+}
+
+/// Join the responses of [Pipeline::fetch_many], see [FetchResponse::join].
+///
+/// Returns the first error if a fetch failed.
+pub(crate) fn join_fetch_responses(
+    requests: &[FetchRequest],
+    responses: Vec<Result<PipelineResponse, RRDCachedClientError>>,
+) -> Result<FetchResponse, RRDCachedClientError> {
+    let mut joined = Vec::with_capacity(requests.len());
+    for (request, response) in requests.iter().zip(responses) {
+        match response? {
+            PipelineResponse::Fetch(response) => joined.push((
+                request.path.name(),
+                request.consolidation_function,
+                response,
+            )),
+            response => {
+                return Err(RRDCachedClientError::Parsing(format!(
+                    "unexpected response {:?}",
+                    response
+                )))
+            }
+        }
+    }
+    FetchResponse::join(&joined)
+}
+
+/// Read a line of a response, failing if the server closed the connection.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<String, RRDCachedClientError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(RRDCachedClientError::ConnectionClosed);
+    }
+    Ok(line)
+}
+
+/// Read the responses of commands written back-to-back, in order.
+///
+/// Fails only if the responses can't be read anymore. The error of
+/// a flush before a fetch is reported as the response of the fetch.
+pub(crate) async fn read_responses<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    commands: &[PipelineCommand],
+) -> Result<Vec<Result<PipelineResponse, RRDCachedClientError>>, RRDCachedClientError> {
+    let mut responses = Vec::with_capacity(commands.len());
+    let mut flush_error = None;
+    for command in commands {
+        let response_line = read_line(reader).await?;
+        let (code, message) = parse_response_line(&response_line)?;
+        let response = if code < 0 {
+            Err(RRDCachedClientError::UnexpectedResponse(
+                code,
+                message.to_string(),
+            ))
+        } else {
+            let mut lines = Vec::with_capacity(code as usize);
+            for _ in 0..code {
+                lines.push(read_line(reader).await?);
+            }
+            command.parse_response(message, lines)
+        };
+        if !command.has_response() {
+            flush_error = response.err();
+            continue;
+        }
+        match flush_error.take() {
+            Some(error) => responses.push(Err(error)),
+            None => responses.push(response),
+        }
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consolidation_function::ConsolidationFunction;
+    use crate::create_builder::RetentionPreset;
+
+    fn commands(pipeline: Pipeline) -> String {
+        pipeline
+            .into_commands()
+            .unwrap()
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect()
+    }
+
+    #[test]
+    fn test_commands() {
+        let path = RrdPath::new("test").unwrap();
+        let pipeline = Pipeline::new()
+            .ping()
+            .update_one(&path, UpdateTimestamp::Seconds(1609459200), 4.2)
+            .flush(&path)
+            .first(&path, Some(1))
+            .last(&path)
+            .pending(&path)
+            .forget(&path)
+            .info(&path)
+            .flush_all()
+            .stats();
+        assert_eq!(pipeline.len(), 10);
+        assert_eq!(
+            commands(pipeline),
+            "PING\nUPDATE test.rrd 1609459200:4.2\nFLUSH test.rrd\nFIRST test.rrd 1\nLAST test.rrd\nPENDING test.rrd\nFORGET test.rrd\nINFO test.rrd\nFLUSHALL\nSTATS\n"
+        );
+    }
+
+    #[test]
+    fn test_fetch() {
+        let path = RrdPath::new("test").unwrap();
+        let request = FetchRequest::new(&path, ConsolidationFunction::Average).start(1609459200);
+        let pipeline = Pipeline::new()
+            .fetch(request.clone())
+            .fetch(request.flush_first());
+        // The flush before the second fetch has no response
+        assert_eq!(pipeline.len(), 2);
+        assert_eq!(
+            commands(pipeline),
+            "FETCH test.rrd AVERAGE 1609459200\nFLUSH test.rrd\nFETCH test.rrd AVERAGE 1609459200\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let path = RrdPath::new("test").unwrap();
+        assert!(Pipeline::new().is_empty());
+
+        let pipeline = Pipeline::new()
+            .ping()
+            .update::<f64>(&path, UpdateTimestamp::Now, vec![])
+            .last(&path);
+        assert!(pipeline.into_commands().is_err());
+
+        let request = FetchRequest::new(&path, ConsolidationFunction::Average).start("now -1d");
+        assert!(Pipeline::new().fetch(request).into_commands().is_err());
+
+        let arguments = CreateArguments::builder("test")
+            .gauge("a", None, None)
+            .preset(RetentionPreset::Standard)
+            .build()
+            .unwrap();
+        let mut invalid_arguments = arguments.clone();
+        invalid_arguments.round_robin_archives.clear();
+        assert!(Pipeline::new().create(arguments).into_commands().is_ok());
+        assert!(Pipeline::new()
+            .create(invalid_arguments)
+            .into_commands()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            PipelineCommand::Last
+                .parse_response("1609459200", vec![])
+                .unwrap(),
+            PipelineResponse::Last(1609459200)
+        );
+        assert_eq!(
+            PipelineCommand::Update
+                .parse_response("errors, enqueued 1 value(s).", vec![])
+                .unwrap(),
+            PipelineResponse::Updated
+        );
+        assert!(PipelineCommand::Last
+            .parse_response("not a timestamp", vec![])
+            .is_err());
+        assert_eq!(
+            PipelineCommand::Create
+                .parse_response("RRD created OK", vec![])
+                .unwrap(),
+            PipelineResponse::Created
+        );
+        assert!(PipelineCommand::Create
+            .parse_response("RRD Error: already exists", vec![])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_responses() {
+        let commands = [
+            PipelineCommand::Ping,
+            PipelineCommand::FlushBeforeFetch,
+            PipelineCommand::Last,
+        ];
+        let mut reader: &[u8] = b"0 PONG\n-1 No such file\n0 1609459200\n";
+        let responses = read_responses(&mut reader, &commands).await.unwrap();
+        assert_eq!(responses.len(), 2);
+        assert!(matches!(responses[0], Ok(PipelineResponse::Pong)));
+        // The error of the flush is reported instead of the response
+        assert!(matches!(
+            responses[1],
+            Err(RRDCachedClientError::UnexpectedResponse(-1, _))
+        ));
+
+        // The server closed the connection before answering everything
+        let mut reader: &[u8] = b"0 PONG\n";
+        let result = read_responses(&mut reader, &commands).await;
+        assert!(matches!(
+            result,
+            Err(RRDCachedClientError::ConnectionClosed)
+        ));
+        let mut reader: &[u8] = b"2 Statistics follow\nQueueLength: 3\n";
+        let result = read_responses(&mut reader, &[PipelineCommand::Stats]).await;
+        assert!(matches!(
+            result,
+            Err(RRDCachedClientError::ConnectionClosed)
+        ));
+    }
+}
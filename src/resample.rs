@@ -0,0 +1,498 @@
+use crate::{
+    consolidation_function::ConsolidationFunction, errors::RRDCachedClientError,
+    fetch::FetchResponse,
+};
+
+/// Ratio of unknown data points accepted in a row when aligning
+/// the responses joined by [FetchResponse::join].
+pub const JOIN_XFILES_FACTOR: f64 = 0.5;
+
+/// Client-side resampling of fetched data.
+///
+/// As in rrdtool, the row at a timestamp holds the value of the interval of
+/// one step ending at this timestamp, and unknown values are NaN.
+impl FetchResponse {
+    /// Consolidate the rows into a coarser step, a multiple of the current one.
+    ///
+    /// As rrdtool does for its archives, the consolidated rows end at multiples
+    /// of the step since the Unix epoch, and a value is unknown when the ratio
+    /// of unknown values in its interval is more than the xfiles factor. Rows
+    /// missing at the edges of the response count as unknown.
+    pub fn downsample(
+        &self,
+        step: usize,
+        consolidation_function: ConsolidationFunction,
+        xfiles_factor: f64,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        self.consolidate(0, step, consolidation_function, xfiles_factor)
+    }
+
+    /// Split the rows into a finer step, a divisor of the current one.
+    ///
+    /// Each value is repeated over the rows of its interval.
+    pub fn upsample(&self, step: usize) -> Result<FetchResponse, RRDCachedClientError> {
+        if step == 0 || self.step == 0 || !self.step.is_multiple_of(step) {
+            return Err(RRDCachedClientError::InvalidResample(format!(
+                "the step {} must divide the step {}",
+                step, self.step
+            )));
+        }
+        let rows_per_interval = self.step / step;
+        let mut data = Vec::with_capacity(self.data.len() * rows_per_interval);
+        for (timestamp, values) in &self.data {
+            for row in (0..rows_per_interval).rev() {
+                let row_timestamp = timestamp.checked_sub(row * step).ok_or_else(|| {
+                    RRDCachedClientError::InvalidResample(format!(
+                        "the row ending at {} starts before the Unix epoch",
+                        timestamp
+                    ))
+                })?;
+                data.push((row_timestamp, values.clone()));
+            }
+        }
+        Ok(FetchResponse {
+            step,
+            data,
+            ..self.clone()
+        })
+    }
+
+    /// Resample the rows to any step, with rows ending at the start plus
+    /// a multiple of the step, up to the end.
+    ///
+    /// The rows are first upsampled to a common divisor of both steps,
+    /// then consolidated like [Self::downsample]. Rows outside the
+    /// fetched range are unknown. Use it to align responses of RRDs
+    /// with different steps.
+    pub fn align(
+        &self,
+        start: usize,
+        end: usize,
+        step: usize,
+        consolidation_function: ConsolidationFunction,
+        xfiles_factor: f64,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        if step == 0 || self.step == 0 || end < start {
+            return Err(RRDCachedClientError::InvalidResample(
+                "the steps must be greater than 0 and the end after the start".to_string(),
+            ));
+        }
+        let common_step = gcd(self.step, step);
+        let fine = if common_step == self.step {
+            self.clone()
+        } else {
+            self.upsample(common_step)?
+        };
+        let consolidated = fine.consolidate(start, step, consolidation_function, xfiles_factor)?;
+
+        let rows = (end - start) / step;
+        let mut consolidated_rows = consolidated.data.into_iter().peekable();
+        let mut data = Vec::with_capacity(rows);
+        for row in 1..=rows {
+            let timestamp = start + row * step;
+            while consolidated_rows
+                .peek()
+                .is_some_and(|(row_timestamp, _)| *row_timestamp < timestamp)
+            {
+                consolidated_rows.next();
+            }
+            match consolidated_rows.peek() {
+                Some((row_timestamp, _)) if *row_timestamp == timestamp => {
+                    data.push(consolidated_rows.next().unwrap());
+                }
+                _ => data.push((timestamp, vec![f64::NAN; self.ds_names.len()])),
+            }
+        }
+        Ok(FetchResponse {
+            start,
+            end: start + rows * step,
+            step,
+            data,
+            ..self.clone()
+        })
+    }
+
+    /// Join the responses of several RRDs into one table, with the data
+    /// sources named `<prefix>:<name>`, like `cpu:user`.
+    ///
+    /// The responses are aligned with [Self::align] on the coarsest of
+    /// their steps, over the range covering all of them, each with its
+    /// consolidation function and [JOIN_XFILES_FACTOR].
+    pub fn join(
+        responses: &[(&str, ConsolidationFunction, FetchResponse)],
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        let step = responses
+            .iter()
+            .map(|(_, _, response)| response.step)
+            .max()
+            .ok_or_else(|| RRDCachedClientError::InvalidResample("nothing to join".to_string()))?;
+        if step == 0 {
+            return Err(RRDCachedClientError::InvalidResample(
+                "the steps must be greater than 0".to_string(),
+            ));
+        }
+        let start = responses
+            .iter()
+            .map(|(_, _, response)| response.start)
+            .min()
+            .unwrap_or(0);
+        let end = responses
+            .iter()
+            .map(|(_, _, response)| response.end)
+            .max()
+            .unwrap_or(0);
+        let start = start - start % step;
+        let end = end.next_multiple_of(step);
+
+        let mut ds_names = Vec::new();
+        let mut data: Vec<(usize, Vec<f64>)> = Vec::new();
+        for (prefix, consolidation_function, response) in responses {
+            let aligned = response.align(
+                start,
+                end,
+                step,
+                *consolidation_function,
+                JOIN_XFILES_FACTOR,
+            )?;
+            ds_names.extend(
+                aligned
+                    .ds_names
+                    .iter()
+                    .map(|name| format!("{}:{}", prefix, name)),
+            );
+            if data.is_empty() {
+                data = aligned.data;
+            } else {
+                for ((_, row), (_, values)) in data.iter_mut().zip(aligned.data) {
+                    row.extend(values);
+                }
+            }
+        }
+        Ok(FetchResponse {
+            flush_version: responses
+                .iter()
+                .map(|(_, _, response)| response.flush_version)
+                .max()
+                .unwrap_or(0),
+            start,
+            end,
+            step,
+            ds_count: ds_names.len(),
+            ds_names,
+            data,
+        })
+    }
+
+    /// Consolidate the rows into intervals of the step ending at the
+    /// origin plus a multiple of the step.
+    fn consolidate(
+        &self,
+        origin: usize,
+        step: usize,
+        consolidation_function: ConsolidationFunction,
+        xfiles_factor: f64,
+    ) -> Result<FetchResponse, RRDCachedClientError> {
+        if consolidation_function.is_holt_winters() {
+            return Err(RRDCachedClientError::InvalidResample(format!(
+                "{} can't be consolidated",
+                consolidation_function.to_str()
+            )));
+        }
+        if !(0.0..=1.0).contains(&xfiles_factor) {
+            return Err(RRDCachedClientError::InvalidResample(
+                "xfiles_factor must be between 0 and 1".to_string(),
+            ));
+        }
+        if step == 0 || self.step == 0 || !step.is_multiple_of(self.step) {
+            return Err(RRDCachedClientError::InvalidResample(format!(
+                "the step {} must be a multiple of the step {}",
+                step, self.step
+            )));
+        }
+        let rows_per_interval = step / self.step;
+        let interval_end = |timestamp: usize| {
+            let offset = timestamp as i64 - origin as i64;
+            let intervals =
+                offset.div_euclid(step as i64) + i64::from(offset.rem_euclid(step as i64) != 0);
+            (origin as i64 + intervals * step as i64) as usize
+        };
+
+        // Values of each data source, per interval
+        let mut intervals: Vec<(usize, Vec<Vec<f64>>)> = Vec::new();
+        for (timestamp, values) in &self.data {
+            let end = interval_end(*timestamp);
+            if intervals.last().map(|(last_end, _)| *last_end) != Some(end) {
+                intervals.push((end, vec![Vec::new(); values.len()]));
+            }
+            let (_, interval_values) = intervals.last_mut().unwrap();
+            for (interval_values, value) in interval_values.iter_mut().zip(values) {
+                interval_values.push(*value);
+            }
+        }
+
+        let data = intervals
+            .into_iter()
+            .map(|(end, values)| {
+                let values = values
+                    .iter()
+                    .map(|values| {
+                        consolidate_values(
+                            values,
+                            rows_per_interval,
+                            consolidation_function,
+                            xfiles_factor,
+                        )
+                    })
+                    .collect();
+                (end, values)
+            })
+            .collect::<Vec<(usize, Vec<f64>)>>();
+        let (start, end) = match (data.first(), data.last()) {
+            (Some((first, _)), Some((last, _))) => {
+                let start = first.checked_sub(step).ok_or_else(|| {
+                    RRDCachedClientError::InvalidResample(format!(
+                        "the row ending at {} starts before the Unix epoch",
+                        first
+                    ))
+                })?;
+                (start, *last)
+            }
+            _ => (self.start, self.end),
+        };
+        Ok(FetchResponse {
+            start,
+            end,
+            step,
+            data,
+            ..self.clone()
+        })
+    }
+}
+
+/// Consolidate the values of an interval of `rows` rows, where the
+/// missing rows are unknown, like rrdtool consolidates primary data points.
+fn consolidate_values(
+    values: &[f64],
+    rows: usize,
+    consolidation_function: ConsolidationFunction,
+    xfiles_factor: f64,
+) -> f64 {
+    let known: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    let unknown = rows.saturating_sub(known.len());
+    if known.is_empty() || unknown as f64 / rows as f64 > xfiles_factor {
+        return f64::NAN;
+    }
+    match consolidation_function {
+        ConsolidationFunction::Min => known.iter().copied().fold(f64::INFINITY, f64::min),
+        ConsolidationFunction::Max => known.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        ConsolidationFunction::Last => known[known.len() - 1],
+        _ => known.iter().sum::<f64>() / known.len() as f64,
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(start: usize, step: usize, values: &[f64]) -> FetchResponse {
+        FetchResponse {
+            flush_version: 1,
+            start,
+            end: start + values.len() * step,
+            step,
+            ds_count: 1,
+            ds_names: vec!["ds1".to_string()],
+            data: values
+                .iter()
+                .enumerate()
+                .map(|(row, value)| (start + (row + 1) * step, vec![*value]))
+                .collect(),
+        }
+    }
+
+    fn values(response: &FetchResponse) -> Vec<(usize, Option<f64>)> {
+        response.column("ds1").unwrap()
+    }
+
+    #[test]
+    fn test_downsample() {
+        let nan = f64::NAN;
+        let fine = response(1000, 10, &[1.0, 2.0, 3.0, 4.0, 5.0, nan, 7.0, nan, nan]);
+
+        let average = fine
+            .downsample(30, ConsolidationFunction::Average, 0.5)
+            .unwrap();
+        assert_eq!(average.step, 30);
+        assert_eq!(average.start, 990);
+        assert_eq!(average.end, 1110);
+        // Intervals ending at multiples of 30: (990, 1020], (1020, 1050], ...
+        assert_eq!(
+            values(&average),
+            vec![
+                (1020, Some(1.5)),
+                (1050, Some(4.0)),
+                (1080, None),
+                (1110, None)
+            ]
+        );
+
+        // (990, 1020] misses the row of 1000, before the response
+        let strict = fine
+            .downsample(30, ConsolidationFunction::Average, 0.0)
+            .unwrap();
+        assert_eq!(
+            values(&strict),
+            vec![(1020, None), (1050, Some(4.0)), (1080, None), (1110, None)]
+        );
+
+        let min = fine
+            .downsample(30, ConsolidationFunction::Min, 0.5)
+            .unwrap();
+        assert_eq!(values(&min)[1], (1050, Some(3.0)));
+        let max = fine
+            .downsample(30, ConsolidationFunction::Max, 0.5)
+            .unwrap();
+        assert_eq!(values(&max)[1], (1050, Some(5.0)));
+        let last = fine
+            .downsample(30, ConsolidationFunction::Last, 1.0)
+            .unwrap();
+        assert_eq!(values(&last)[2], (1080, Some(7.0)));
+    }
+
+    #[test]
+    fn test_downsample_errors() {
+        let fine = response(1000, 10, &[1.0, 2.0, 3.0]);
+        assert!(fine
+            .downsample(25, ConsolidationFunction::Average, 0.5)
+            .is_err());
+        assert!(fine
+            .downsample(0, ConsolidationFunction::Average, 0.5)
+            .is_err());
+        assert!(fine
+            .downsample(30, ConsolidationFunction::HwPredict, 0.5)
+            .is_err());
+        assert!(fine
+            .downsample(30, ConsolidationFunction::Average, 1.5)
+            .is_err());
+
+        // A row at the Unix epoch ends an interval starting before it
+        let mut epoch = response(0, 10, &[1.0]);
+        epoch.data[0].0 = 0;
+        assert!(matches!(
+            epoch.downsample(20, ConsolidationFunction::Average, 0.5),
+            Err(RRDCachedClientError::InvalidResample(_))
+        ));
+    }
+
+    #[test]
+    fn test_upsample() {
+        let coarse = response(990, 30, &[1.0, f64::NAN]);
+        let fine = coarse.upsample(10).unwrap();
+        assert_eq!(fine.step, 10);
+        assert_eq!(fine.start, 990);
+        assert_eq!(fine.end, 1050);
+        assert_eq!(
+            values(&fine),
+            vec![
+                (1000, Some(1.0)),
+                (1010, Some(1.0)),
+                (1020, Some(1.0)),
+                (1030, None),
+                (1040, None),
+                (1050, None)
+            ]
+        );
+        assert!(coarse.upsample(20).is_err());
+
+        // A row ending too close to the epoch can't be split
+        let early = FetchResponse {
+            start: 0,
+            end: 10,
+            data: vec![(10, vec![1.0])],
+            ..coarse.clone()
+        };
+        assert!(matches!(
+            early.upsample(10),
+            Err(RRDCachedClientError::InvalidResample(_))
+        ));
+
+        // Upsampling then downsampling is lossless
+        let back = fine
+            .downsample(30, ConsolidationFunction::Average, 0.0)
+            .unwrap();
+        assert_eq!(values(&back), values(&coarse));
+    }
+
+    #[test]
+    fn test_align() {
+        // Two RRDs with steps of 10s and 15s, aligned on 30s
+        let a = response(990, 10, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let b = response(990, 15, &[10.0, 20.0, 30.0, 40.0]);
+
+        let a = a
+            .align(990, 1050, 30, ConsolidationFunction::Average, 0.5)
+            .unwrap();
+        let b = b
+            .align(990, 1050, 30, ConsolidationFunction::Average, 0.5)
+            .unwrap();
+        assert_eq!(a.step, 30);
+        assert_eq!(b.step, 30);
+        assert_eq!(values(&a), vec![(1020, Some(2.0)), (1050, Some(5.0))]);
+        assert_eq!(values(&b), vec![(1020, Some(15.0)), (1050, Some(35.0))]);
+
+        // Rows outside of the response are unknown
+        let c = response(990, 10, &[1.0, 2.0, 3.0])
+            .align(960, 1050, 30, ConsolidationFunction::Max, 0.5)
+            .unwrap();
+        assert_eq!(
+            values(&c),
+            vec![(990, None), (1020, Some(3.0)), (1050, None)]
+        );
+
+        // Start not aligned on the epoch
+        let d = response(1000, 10, &[1.0, 2.0, 3.0, 4.0])
+            .align(1005, 1035, 15, ConsolidationFunction::Last, 0.5)
+            .unwrap();
+        assert_eq!(values(&d), vec![(1020, Some(2.0)), (1035, Some(4.0))]);
+
+        assert!(response(990, 10, &[1.0])
+            .align(1050, 990, 30, ConsolidationFunction::Average, 0.5)
+            .is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        let cpu = response(990, 10, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let mut load = response(960, 30, &[0.5, 1.5, 2.5]);
+        load.ds_names = vec!["shortterm".to_string()];
+
+        let joined = FetchResponse::join(&[
+            ("cpu", ConsolidationFunction::Max, cpu),
+            ("load", ConsolidationFunction::Average, load),
+        ])
+        .unwrap();
+        assert_eq!(joined.step, 30);
+        assert_eq!(joined.start, 960);
+        assert_eq!(joined.end, 1050);
+        assert_eq!(joined.ds_count, 2);
+        assert_eq!(joined.ds_names, vec!["cpu:ds1", "load:shortterm"]);
+        assert_eq!(
+            joined.column("cpu:ds1").unwrap(),
+            vec![(990, None), (1020, Some(3.0)), (1050, Some(6.0))]
+        );
+        assert_eq!(
+            joined.column("load:shortterm").unwrap(),
+            vec![(990, Some(0.5)), (1020, Some(1.5)), (1050, Some(2.5))]
+        );
+
+        assert!(FetchResponse::join(&[]).is_err());
+    }
+}
@@ -0,0 +1,533 @@
+use std::{fmt, ops, str::FromStr};
+
+use crate::errors::RRDCachedClientError;
+
+/// Maximum number of tokens in the expression of a COMPUTE data source,
+/// `DS_CDEF_MAX_RPN_NODES` in rrdtool minus its end marker.
+pub const MAX_COMPUTE_TOKENS: usize = 15;
+
+/// Operators taking one value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpnUnaryOperator {
+    Sin,
+    Cos,
+    Log,
+    Exp,
+    Sqrt,
+    Atan,
+    Floor,
+    Ceil,
+    Abs,
+    Deg2Rad,
+    Rad2Deg,
+    /// 1 if the value is unknown, 0 otherwise
+    IsUnknown,
+    /// 1 if the value is infinite, 0 otherwise
+    IsInfinite,
+}
+
+/// Operators taking two values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpnBinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    /// Addition where unknown values count as 0
+    AddNan,
+    Pow,
+    Atan2,
+    Min,
+    Max,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+/// Operators taking three values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpnTernaryOperator {
+    /// `A,B,C,IF`: B if A is not 0, C otherwise
+    If,
+    /// `A,B,C,LIMIT`: A if between B and C, unknown otherwise
+    Limit,
+}
+
+impl RpnUnaryOperator {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            RpnUnaryOperator::Sin => "SIN",
+            RpnUnaryOperator::Cos => "COS",
+            RpnUnaryOperator::Log => "LOG",
+            RpnUnaryOperator::Exp => "EXP",
+            RpnUnaryOperator::Sqrt => "SQRT",
+            RpnUnaryOperator::Atan => "ATAN",
+            RpnUnaryOperator::Floor => "FLOOR",
+            RpnUnaryOperator::Ceil => "CEIL",
+            RpnUnaryOperator::Abs => "ABS",
+            RpnUnaryOperator::Deg2Rad => "DEG2RAD",
+            RpnUnaryOperator::Rad2Deg => "RAD2DEG",
+            RpnUnaryOperator::IsUnknown => "UN",
+            RpnUnaryOperator::IsInfinite => "ISINF",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "SIN" => RpnUnaryOperator::Sin,
+            "COS" => RpnUnaryOperator::Cos,
+            "LOG" => RpnUnaryOperator::Log,
+            "EXP" => RpnUnaryOperator::Exp,
+            "SQRT" => RpnUnaryOperator::Sqrt,
+            "ATAN" => RpnUnaryOperator::Atan,
+            "FLOOR" => RpnUnaryOperator::Floor,
+            "CEIL" => RpnUnaryOperator::Ceil,
+            "ABS" => RpnUnaryOperator::Abs,
+            "DEG2RAD" => RpnUnaryOperator::Deg2Rad,
+            "RAD2DEG" => RpnUnaryOperator::Rad2Deg,
+            "UN" => RpnUnaryOperator::IsUnknown,
+            "ISINF" => RpnUnaryOperator::IsInfinite,
+            _ => return None,
+        })
+    }
+}
+
+impl RpnBinaryOperator {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            RpnBinaryOperator::Add => "+",
+            RpnBinaryOperator::Subtract => "-",
+            RpnBinaryOperator::Multiply => "*",
+            RpnBinaryOperator::Divide => "/",
+            RpnBinaryOperator::Modulo => "%",
+            RpnBinaryOperator::AddNan => "ADDNAN",
+            RpnBinaryOperator::Pow => "POW",
+            RpnBinaryOperator::Atan2 => "ATAN2",
+            RpnBinaryOperator::Min => "MIN",
+            RpnBinaryOperator::Max => "MAX",
+            RpnBinaryOperator::LessThan => "LT",
+            RpnBinaryOperator::LessOrEqual => "LE",
+            RpnBinaryOperator::GreaterThan => "GT",
+            RpnBinaryOperator::GreaterOrEqual => "GE",
+            RpnBinaryOperator::Equal => "EQ",
+            RpnBinaryOperator::NotEqual => "NE",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "+" => RpnBinaryOperator::Add,
+            "-" => RpnBinaryOperator::Subtract,
+            "*" => RpnBinaryOperator::Multiply,
+            "/" => RpnBinaryOperator::Divide,
+            "%" => RpnBinaryOperator::Modulo,
+            "ADDNAN" => RpnBinaryOperator::AddNan,
+            "POW" => RpnBinaryOperator::Pow,
+            "ATAN2" => RpnBinaryOperator::Atan2,
+            "MIN" => RpnBinaryOperator::Min,
+            "MAX" => RpnBinaryOperator::Max,
+            "LT" => RpnBinaryOperator::LessThan,
+            "LE" => RpnBinaryOperator::LessOrEqual,
+            "GT" => RpnBinaryOperator::GreaterThan,
+            "GE" => RpnBinaryOperator::GreaterOrEqual,
+            "EQ" => RpnBinaryOperator::Equal,
+            "NE" => RpnBinaryOperator::NotEqual,
+            _ => return None,
+        })
+    }
+}
+
+impl RpnTernaryOperator {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            RpnTernaryOperator::If => "IF",
+            RpnTernaryOperator::Limit => "LIMIT",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "IF" => RpnTernaryOperator::If,
+            "LIMIT" => RpnTernaryOperator::Limit,
+            _ => return None,
+        })
+    }
+}
+
+/// An expression in the Reverse Polish Notation (RPN) of rrdtool,
+/// as used by COMPUTE data sources.
+///
+/// It can be built with the helpers and the arithmetic operators:
+/// `RpnExpression::data_source("bytes") * 8.0` is `bytes,8,*`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpnExpression {
+    Constant(f64),
+
+    /// Value of another data source of the RRD, by name
+    DataSource(String),
+
+    /// `UNKN`
+    Unknown,
+
+    /// `INF`
+    Infinity,
+
+    /// `NEGINF`
+    NegativeInfinity,
+
+    Unary(RpnUnaryOperator, Box<RpnExpression>),
+    Binary(RpnBinaryOperator, Box<RpnExpression>, Box<RpnExpression>),
+    Ternary(
+        RpnTernaryOperator,
+        Box<RpnExpression>,
+        Box<RpnExpression>,
+        Box<RpnExpression>,
+    ),
+}
+
+impl RpnExpression {
+    pub fn data_source(name: &str) -> RpnExpression {
+        RpnExpression::DataSource(name.to_string())
+    }
+
+    pub fn constant(value: f64) -> RpnExpression {
+        RpnExpression::Constant(value)
+    }
+
+    pub fn unary(self, operator: RpnUnaryOperator) -> RpnExpression {
+        RpnExpression::Unary(operator, Box::new(self))
+    }
+
+    pub fn binary<E: Into<RpnExpression>>(
+        self,
+        operator: RpnBinaryOperator,
+        other: E,
+    ) -> RpnExpression {
+        RpnExpression::Binary(operator, Box::new(self), Box::new(other.into()))
+    }
+
+    pub fn min<E: Into<RpnExpression>>(self, other: E) -> RpnExpression {
+        self.binary(RpnBinaryOperator::Min, other)
+    }
+
+    pub fn max<E: Into<RpnExpression>>(self, other: E) -> RpnExpression {
+        self.binary(RpnBinaryOperator::Max, other)
+    }
+
+    /// Addition where unknown values count as 0.
+    pub fn add_nan<E: Into<RpnExpression>>(self, other: E) -> RpnExpression {
+        self.binary(RpnBinaryOperator::AddNan, other)
+    }
+
+    /// The value if it's between the minimum and the maximum, unknown otherwise.
+    pub fn limit<E: Into<RpnExpression>, F: Into<RpnExpression>>(
+        self,
+        minimum: E,
+        maximum: F,
+    ) -> RpnExpression {
+        RpnExpression::Ternary(
+            RpnTernaryOperator::Limit,
+            Box::new(self),
+            Box::new(minimum.into()),
+            Box::new(maximum.into()),
+        )
+    }
+
+    /// `then` if the condition is not 0, `otherwise` otherwise.
+    pub fn if_else<E: Into<RpnExpression>, F: Into<RpnExpression>>(
+        condition: RpnExpression,
+        then: E,
+        otherwise: F,
+    ) -> RpnExpression {
+        RpnExpression::Ternary(
+            RpnTernaryOperator::If,
+            Box::new(condition),
+            Box::new(then.into()),
+            Box::new(otherwise.into()),
+        )
+    }
+
+    /// Names of the data sources used in the expression, in order of appearance.
+    pub fn data_sources(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_data_sources(&mut names);
+        names
+    }
+
+    fn collect_data_sources<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            RpnExpression::DataSource(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            RpnExpression::Unary(_, a) => a.collect_data_sources(names),
+            RpnExpression::Binary(_, a, b) => {
+                a.collect_data_sources(names);
+                b.collect_data_sources(names);
+            }
+            RpnExpression::Ternary(_, a, b, c) => {
+                a.collect_data_sources(names);
+                b.collect_data_sources(names);
+                c.collect_data_sources(names);
+            }
+            RpnExpression::Constant(_)
+            | RpnExpression::Unknown
+            | RpnExpression::Infinity
+            | RpnExpression::NegativeInfinity => {}
+        }
+    }
+
+    /// Check that rrdtool can store the expression of a COMPUTE data source.
+    ///
+    /// rrdtool stores it in a compact form, which only takes integer
+    /// constants between -32768 and 32767, and at most [MAX_COMPUTE_TOKENS] tokens.
+    pub fn validate_compute(&self) -> Result<(), RRDCachedClientError> {
+        self.validate_compute_constants()?;
+        let mut tokens = Vec::new();
+        self.write_tokens(&mut tokens);
+        if tokens.len() > MAX_COMPUTE_TOKENS {
+            return Err(RRDCachedClientError::InvalidRpnExpression(format!(
+                "{} tokens, COMPUTE data sources take at most {}: {}",
+                tokens.len(),
+                MAX_COMPUTE_TOKENS,
+                self
+            )));
+        }
+        Ok(())
+    }
+
+    fn validate_compute_constants(&self) -> Result<(), RRDCachedClientError> {
+        match self {
+            RpnExpression::Constant(value) => {
+                if !value.is_finite()
+                    || value.fract() != 0.0
+                    || *value < i16::MIN as f64
+                    || *value > i16::MAX as f64
+                {
+                    return Err(RRDCachedClientError::InvalidRpnExpression(format!(
+                        "COMPUTE data sources only take integer constants between {} and {}, not {}",
+                        i16::MIN,
+                        i16::MAX,
+                        value
+                    )));
+                }
+                Ok(())
+            }
+            RpnExpression::Unary(_, a) => a.validate_compute_constants(),
+            RpnExpression::Binary(_, a, b) => {
+                a.validate_compute_constants()?;
+                b.validate_compute_constants()
+            }
+            RpnExpression::Ternary(_, a, b, c) => {
+                a.validate_compute_constants()?;
+                b.validate_compute_constants()?;
+                c.validate_compute_constants()
+            }
+            RpnExpression::DataSource(_)
+            | RpnExpression::Unknown
+            | RpnExpression::Infinity
+            | RpnExpression::NegativeInfinity => Ok(()),
+        }
+    }
+
+    fn write_tokens(&self, tokens: &mut Vec<String>) {
+        match self {
+            RpnExpression::Constant(value) => tokens.push(value.to_string()),
+            RpnExpression::DataSource(name) => tokens.push(name.clone()),
+            RpnExpression::Unknown => tokens.push("UNKN".to_string()),
+            RpnExpression::Infinity => tokens.push("INF".to_string()),
+            RpnExpression::NegativeInfinity => tokens.push("NEGINF".to_string()),
+            RpnExpression::Unary(operator, a) => {
+                a.write_tokens(tokens);
+                tokens.push(operator.to_str().to_string());
+            }
+            RpnExpression::Binary(operator, a, b) => {
+                a.write_tokens(tokens);
+                b.write_tokens(tokens);
+                tokens.push(operator.to_str().to_string());
+            }
+            RpnExpression::Ternary(operator, a, b, c) => {
+                a.write_tokens(tokens);
+                b.write_tokens(tokens);
+                c.write_tokens(tokens);
+                tokens.push(operator.to_str().to_string());
+            }
+        }
+    }
+}
+
+impl fmt::Display for RpnExpression {
+    /// Written in the RPN syntax of rrdtool, like `a,b,+,8,*`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens = Vec::new();
+        self.write_tokens(&mut tokens);
+        f.write_str(&tokens.join(","))
+    }
+}
+
+impl FromStr for RpnExpression {
+    type Err = RRDCachedClientError;
+
+    /// Parse the RPN syntax of rrdtool, like `a,b,+,8,*`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| {
+            RRDCachedClientError::InvalidRpnExpression(format!("{}: {}", message, s))
+        };
+        let mut stack: Vec<RpnExpression> = Vec::new();
+        for token in s.split(',') {
+            let expression = if let Some(operator) = RpnUnaryOperator::from_token(token) {
+                let a = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                RpnExpression::Unary(operator, Box::new(a))
+            } else if let Some(operator) = RpnBinaryOperator::from_token(token) {
+                let b = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                let a = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                RpnExpression::Binary(operator, Box::new(a), Box::new(b))
+            } else if let Some(operator) = RpnTernaryOperator::from_token(token) {
+                let c = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                let b = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                let a = stack.pop().ok_or_else(|| invalid("missing operand"))?;
+                RpnExpression::Ternary(operator, Box::new(a), Box::new(b), Box::new(c))
+            } else {
+                match token {
+                    "UNKN" => RpnExpression::Unknown,
+                    "INF" => RpnExpression::Infinity,
+                    "NEGINF" => RpnExpression::NegativeInfinity,
+                    "" => return Err(invalid("empty token")),
+                    _ => match token.parse::<f64>() {
+                        Ok(value) if value.is_finite() => RpnExpression::Constant(value),
+                        _ => {
+                            check_data_source_reference(token)?;
+                            RpnExpression::DataSource(token.to_string())
+                        }
+                    },
+                }
+            };
+            stack.push(expression);
+        }
+        match (stack.pop(), stack.is_empty()) {
+            (Some(expression), true) => Ok(expression),
+            (Some(_), false) => Err(invalid("too many operands")),
+            (None, _) => Err(invalid("empty expression")),
+        }
+    }
+}
+
+/// Check that a token can be a data source name.
+fn check_data_source_reference(token: &str) -> Result<(), RRDCachedClientError> {
+    crate::sanitisation::check_data_source_name(token)
+        .map_err(|_| RRDCachedClientError::InvalidRpnExpression(format!("unknown token {}", token)))
+}
+
+impl From<f64> for RpnExpression {
+    fn from(value: f64) -> Self {
+        RpnExpression::Constant(value)
+    }
+}
+
+macro_rules! impl_rpn_operator {
+    ($trait:ident, $method:ident, $operator:expr) => {
+        impl<E: Into<RpnExpression>> ops::$trait<E> for RpnExpression {
+            type Output = RpnExpression;
+
+            fn $method(self, other: E) -> RpnExpression {
+                self.binary($operator, other)
+            }
+        }
+    };
+}
+
+impl_rpn_operator!(Add, add, RpnBinaryOperator::Add);
+impl_rpn_operator!(Sub, sub, RpnBinaryOperator::Subtract);
+impl_rpn_operator!(Mul, mul, RpnBinaryOperator::Multiply);
+impl_rpn_operator!(Div, div, RpnBinaryOperator::Divide);
+impl_rpn_operator!(Rem, rem, RpnBinaryOperator::Modulo);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_string() {
+        let expression = (RpnExpression::data_source("a") + RpnExpression::data_source("b")) * 8.0;
+        assert_eq!(expression.to_string(), "a,b,+,8,*");
+
+        let expression = RpnExpression::data_source("temp")
+            .limit(-40.0, 60.0)
+            .add_nan(0.0);
+        assert_eq!(expression.to_string(), "temp,-40,60,LIMIT,0,ADDNAN");
+
+        let expression = RpnExpression::if_else(
+            RpnExpression::data_source("a").unary(RpnUnaryOperator::IsUnknown),
+            RpnExpression::Unknown,
+            RpnExpression::data_source("a") / 1024.0,
+        );
+        assert_eq!(expression.to_string(), "a,UN,UNKN,a,1024,/,IF");
+    }
+
+    #[test]
+    fn test_parse() {
+        let expression: RpnExpression = "a,b,+,8,*".parse().unwrap();
+        assert_eq!(
+            expression,
+            (RpnExpression::data_source("a") + RpnExpression::data_source("b")) * 8.0
+        );
+
+        for input in [
+            "a,UN,UNKN,a,1024,/,IF",
+            "temp,-40,60,LIMIT",
+            "a,b,MAX,INF,MIN,NEGINF,GT",
+            "in_bytes,8,*",
+        ] {
+            let expression: RpnExpression = input.parse().unwrap();
+            assert_eq!(expression.to_string(), input);
+        }
+
+        // rrdtool writes the constants with decimals
+        let expression: RpnExpression = "a,8.000000,*".parse().unwrap();
+        assert_eq!(expression.to_string(), "a,8,*");
+
+        assert!("".parse::<RpnExpression>().is_err());
+        assert!("a,+".parse::<RpnExpression>().is_err());
+        assert!("a,b".parse::<RpnExpression>().is_err());
+        assert!("a,,+".parse::<RpnExpression>().is_err());
+        assert!("a,b c,+".parse::<RpnExpression>().is_err());
+    }
+
+    #[test]
+    fn test_validate_compute() {
+        for input in [
+            "a,b,+,8,*",
+            "temp,-40,60,LIMIT,0,ADDNAN",
+            "a,-32768,32767,LIMIT",
+        ] {
+            let expression: RpnExpression = input.parse().unwrap();
+            assert!(expression.validate_compute().is_ok());
+        }
+
+        // The compact form of rrdtool only stores small integers
+        for value in [0.5, -0.25, 32768.0, -32769.0, f64::NAN, f64::INFINITY] {
+            let expression = RpnExpression::data_source("a") * value;
+            assert!(matches!(
+                expression.validate_compute(),
+                Err(RRDCachedClientError::InvalidRpnExpression(_))
+            ));
+        }
+
+        // At most 15 tokens
+        let expression: RpnExpression = "a,1,+,2,+,3,+,4,+,5,+,6,+,7,+".parse().unwrap();
+        assert!(expression.validate_compute().is_ok());
+        let expression = expression + 8.0;
+        assert!(expression.validate_compute().is_err());
+    }
+
+    #[test]
+    fn test_data_sources() {
+        let expression: RpnExpression = "a,b,+,a,*,8,/".parse().unwrap();
+        assert_eq!(expression.data_sources(), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use crate::{errors::RRDCachedClientError, parsers::parse_duration};
+
+/// Units of the rrdtool time-spec syntax, from the largest to the smallest.
+///
+/// As in rrdtool, a month is 31 days and a year is 366 days,
+/// so they are never too short for the retention they describe.
+const UNITS: [(char, u64); 7] = [
+    ('y', 366 * 24 * 60 * 60),
+    ('M', 31 * 24 * 60 * 60),
+    ('w', 7 * 24 * 60 * 60),
+    ('d', 24 * 60 * 60),
+    ('h', 60 * 60),
+    ('m', 60),
+    ('s', 1),
+];
+
+/// A positive duration in whole seconds, as used in RRD schemas.
+///
+/// It can be parsed from the rrdtool time-spec syntax, such as `10s`,
+/// `5m`, `1h`, `1d`, `1w`, `1M` or `1y`. A number without unit is in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RrdDuration {
+    seconds: u64,
+}
+
+impl RrdDuration {
+    pub fn from_secs(seconds: u64) -> Result<RrdDuration, RRDCachedClientError> {
+        if seconds == 0 {
+            return Err(RRDCachedClientError::InvalidDuration(
+                "duration must be greater than 0".to_string(),
+            ));
+        }
+        Ok(RrdDuration { seconds })
+    }
+
+    pub fn from_minutes(minutes: u64) -> Result<RrdDuration, RRDCachedClientError> {
+        RrdDuration::from_unit(minutes, 60)
+    }
+
+    pub fn from_hours(hours: u64) -> Result<RrdDuration, RRDCachedClientError> {
+        RrdDuration::from_unit(hours, 60 * 60)
+    }
+
+    pub fn from_days(days: u64) -> Result<RrdDuration, RRDCachedClientError> {
+        RrdDuration::from_unit(days, 24 * 60 * 60)
+    }
+
+    fn from_unit(value: u64, unit_seconds: u64) -> Result<RrdDuration, RRDCachedClientError> {
+        let seconds = value.checked_mul(unit_seconds).ok_or_else(|| {
+            RRDCachedClientError::InvalidDuration("duration is too large".to_string())
+        })?;
+        RrdDuration::from_secs(seconds)
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.seconds
+    }
+
+    /// Number of times a shorter duration fits in this one,
+    /// such as the number of steps in a resolution.
+    ///
+    /// Fails if this duration isn't a multiple of the shorter duration.
+    pub fn steps_of(&self, step: RrdDuration) -> Result<u64, RRDCachedClientError> {
+        if !self.seconds.is_multiple_of(step.seconds) {
+            return Err(RRDCachedClientError::InvalidDuration(format!(
+                "{} is not a multiple of {}",
+                self, step
+            )));
+        }
+        Ok(self.seconds / step.seconds)
+    }
+}
+
+impl fmt::Display for RrdDuration {
+    /// Written with the largest unit that divides the duration.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, unit_seconds) in UNITS {
+            if self.seconds.is_multiple_of(unit_seconds) {
+                return write!(f, "{}{}", self.seconds / unit_seconds, unit);
+            }
+        }
+        unreachable!("every duration is a multiple of a second")
+    }
+}
+
+impl FromStr for RrdDuration {
+    type Err = RRDCachedClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, unit) = parse_duration(s).map_err(|_| {
+            RRDCachedClientError::InvalidDuration(format!("invalid duration {}", s))
+        })?;
+        let unit_seconds = match unit {
+            Some(unit) => UNITS
+                .iter()
+                .find(|(u, _)| *u == unit)
+                .map(|(_, unit_seconds)| *unit_seconds)
+                .unwrap_or(1),
+            None => 1,
+        };
+        RrdDuration::from_unit(value, unit_seconds)
+    }
+}
+
+impl TryFrom<&str> for RrdDuration {
+    type Error = RRDCachedClientError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<Duration> for RrdDuration {
+    type Error = RRDCachedClientError;
+
+    /// Fails if the duration isn't a whole number of seconds.
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        if duration.subsec_nanos() != 0 {
+            return Err(RRDCachedClientError::InvalidDuration(
+                "duration must be a whole number of seconds".to_string(),
+            ));
+        }
+        RrdDuration::from_secs(duration.as_secs())
+    }
+}
+
+impl From<RrdDuration> for Duration {
+    fn from(duration: RrdDuration) -> Self {
+        Duration::from_secs(duration.seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("300".parse::<RrdDuration>().unwrap().as_secs(), 300);
+        assert_eq!("10s".parse::<RrdDuration>().unwrap().as_secs(), 10);
+        assert_eq!("5m".parse::<RrdDuration>().unwrap().as_secs(), 300);
+        assert_eq!("2h".parse::<RrdDuration>().unwrap().as_secs(), 7200);
+        assert_eq!("1d".parse::<RrdDuration>().unwrap().as_secs(), 86400);
+        assert_eq!("1w".parse::<RrdDuration>().unwrap().as_secs(), 604800);
+        assert_eq!("1M".parse::<RrdDuration>().unwrap().as_secs(), 2678400);
+        assert_eq!("1y".parse::<RrdDuration>().unwrap().as_secs(), 31622400);
+
+        assert!("0".parse::<RrdDuration>().is_err());
+        assert!("0d".parse::<RrdDuration>().is_err());
+        assert!("5x".parse::<RrdDuration>().is_err());
+        assert!("".parse::<RrdDuration>().is_err());
+        assert!("-1d".parse::<RrdDuration>().is_err());
+        assert!("18446744073709551615y".parse::<RrdDuration>().is_err());
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(RrdDuration::from_secs(10).unwrap().to_string(), "10s");
+        assert_eq!(RrdDuration::from_secs(90).unwrap().to_string(), "90s");
+        assert_eq!(RrdDuration::from_minutes(5).unwrap().to_string(), "5m");
+        assert_eq!(RrdDuration::from_hours(48).unwrap().to_string(), "2d");
+        assert_eq!(RrdDuration::from_days(14).unwrap().to_string(), "2w");
+        assert_eq!(RrdDuration::from_days(30).unwrap().to_string(), "30d");
+        assert_eq!(RrdDuration::from_days(366).unwrap().to_string(), "1y");
+    }
+
+    #[test]
+    fn test_steps_of() {
+        let step: RrdDuration = "10s".parse().unwrap();
+        let resolution: RrdDuration = "1m".parse().unwrap();
+        assert_eq!(resolution.steps_of(step).unwrap(), 6);
+
+        let step: RrdDuration = "7s".parse().unwrap();
+        assert!(resolution.steps_of(step).is_err());
+    }
+
+    #[test]
+    fn test_duration_conversions() {
+        let duration = RrdDuration::try_from(Duration::from_secs(60)).unwrap();
+        assert_eq!(duration.as_secs(), 60);
+        assert_eq!(Duration::from(duration), Duration::from_secs(60));
+        assert!(RrdDuration::try_from(Duration::from_millis(1500)).is_err());
+        assert!(RrdDuration::try_from(Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use crate::{errors::RRDCachedClientError, sanitisation::check_rrd_path};
+
+/// A validated path to a RRD file.
+///
+/// The path is relative to the base directory of RRDCached and may contain
+/// sub-directories, like `hosts/server-1/cpu`. The `.rrd` extension is added
+/// if missing, so `cpu` and `cpu.rrd` are the same path.
+///
+/// It can only be constructed from a valid path, so every command
+/// can use it without checking it again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RrdPath {
+    /// Path including the .rrd extension.
+    path: String,
+}
+
+impl RrdPath {
+    /// Create a new RRD path from a name or a relative path,
+    /// with or without the .rrd extension.
+    pub fn new(path: &str) -> Result<RrdPath, RRDCachedClientError> {
+        let name = path.strip_suffix(".rrd").unwrap_or(path);
+        check_rrd_path(name)?;
+        let mut path = String::with_capacity(name.len() + 4);
+        path.push_str(name);
+        path.push_str(".rrd");
+        Ok(RrdPath { path })
+    }
+
+    /// Path as sent to RRDCached, including the .rrd extension.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// Path without the .rrd extension.
+    pub fn name(&self) -> &str {
+        &self.path[..self.path.len() - 4]
+    }
+}
+
+impl fmt::Display for RrdPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl AsRef<str> for RrdPath {
+    fn as_ref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl TryFrom<&str> for RrdPath {
+    type Error = RRDCachedClientError;
+
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        RrdPath::new(path)
+    }
+}
+
+impl TryFrom<String> for RrdPath {
+    type Error = RRDCachedClientError;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        RrdPath::new(&path)
+    }
+}
+
+impl std::str::FromStr for RrdPath {
+    type Err = RRDCachedClientError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        RrdPath::new(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_from_name() {
+        let path = RrdPath::new("test").unwrap();
+        assert_eq!(path.as_str(), "test.rrd");
+        assert_eq!(path.name(), "test");
+        assert_eq!(path.to_string(), "test.rrd");
+    }
+
+    #[test]
+    fn test_new_from_nested_path() {
+        let path = RrdPath::new("hosts/server-1/cpu").unwrap();
+        assert_eq!(path.as_str(), "hosts/server-1/cpu.rrd");
+        assert_eq!(path.name(), "hosts/server-1/cpu");
+    }
+
+    #[test]
+    fn test_new_with_extension() {
+        let path = RrdPath::new("hosts/server-1/cpu.rrd").unwrap();
+        assert_eq!(path.as_str(), "hosts/server-1/cpu.rrd");
+        assert_eq!(path, RrdPath::new("hosts/server-1/cpu").unwrap());
+
+        // Only one extension is stripped
+        assert!(RrdPath::new("test.rrd.rrd").is_err());
+        assert!(RrdPath::new(".rrd").is_err());
+    }
+
+    #[test]
+    fn test_new_invalid() {
+        assert!(RrdPath::new("").is_err());
+        assert!(RrdPath::new("/absolute/path").is_err());
+        assert!(RrdPath::new("../test").is_err());
+        assert!(RrdPath::new("test test").is_err());
+        assert!(RrdPath::new("test\n").is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let path: RrdPath = "test".try_into().unwrap();
+        assert_eq!(path.as_str(), "test.rrd");
+
+        let path: RrdPath = "test".to_string().try_into().unwrap();
+        assert_eq!(path.as_str(), "test.rrd");
+
+        let path: RrdPath = "test.rrd".parse().unwrap();
+        assert_eq!(path.as_str(), "test.rrd");
+
+        let result: Result<RrdPath, _> = "../test".parse();
+        assert!(result.is_err());
+    }
+}
@@ -17,20 +17,83 @@ pub fn check_data_source_name(name: &str) -> Result<(), RRDCachedClientError> {
     Ok(())
 }
 
+/// Maximum length of a RRD path, as PATH_MAX on Linux.
+const RRD_PATH_MAX_LENGTH: usize = 4096;
+
+/// Maximum length of a single path component, as NAME_MAX on Linux.
+const RRD_PATH_COMPONENT_MAX_LENGTH: usize = 255;
+
+/// Check that a RRD path is valid.
+///
+/// The path is relative to the base directory of RRDCached (`-b` option).
+/// It may contain sub-directories separated by `/`, like `hosts/server-1/cpu`.
+///
+/// Absolute paths and `.` or `..` components are rejected, as well as
+/// whitespaces and other characters that would break the protocol.
+///
+/// Does **not** end with .rrd
 pub fn check_rrd_path(name: &str) -> Result<(), RRDCachedClientError> {
-    if name.is_empty() || name.len() > 64 {
-        return Err(RRDCachedClientError::InvalidCreateDataSerie(
-            "name must be between 1 and 64 characters".to_string(),
+    if name.is_empty() || name.len() > RRD_PATH_MAX_LENGTH {
+        return Err(RRDCachedClientError::InvalidRrdPath(
+            "path must be between 1 and 4096 characters".to_string(),
         ));
     }
-    if !name
-        .chars()
-        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
-    {
-        return Err(RRDCachedClientError::InvalidCreateDataSerie(
-            "name must only contain alphanumeric characters and underscores".to_string(),
+    if name.starts_with('/') {
+        return Err(RRDCachedClientError::InvalidRrdPath(
+            "path must be relative to the base directory".to_string(),
+        ));
+    }
+    if name.ends_with(".rrd") {
+        return Err(RRDCachedClientError::InvalidRrdPath(
+            "path must not end with .rrd".to_string(),
         ));
     }
+    check_rrd_path_components(name)
+}
+
+/// Check that a directory of RRDs is valid, for listing.
+///
+/// Same rules as [check_rrd_path], but the name may end with .rrd
+/// and an empty path is the base directory of RRDCached.
+pub fn check_rrd_directory(name: &str) -> Result<(), RRDCachedClientError> {
+    if name.is_empty() {
+        return Ok(());
+    }
+    if name.len() > RRD_PATH_MAX_LENGTH {
+        return Err(RRDCachedClientError::InvalidRrdPath(
+            "path must be between 1 and 4096 characters".to_string(),
+        ));
+    }
+    if name.starts_with('/') {
+        return Err(RRDCachedClientError::InvalidRrdPath(
+            "path must be relative to the base directory".to_string(),
+        ));
+    }
+    check_rrd_path_components(name)
+}
+
+fn check_rrd_path_components(name: &str) -> Result<(), RRDCachedClientError> {
+    for component in name.split('/') {
+        if component.is_empty() || component.len() > RRD_PATH_COMPONENT_MAX_LENGTH {
+            return Err(RRDCachedClientError::InvalidRrdPath(
+                "path components must be between 1 and 255 characters".to_string(),
+            ));
+        }
+        if component == "." || component == ".." {
+            return Err(RRDCachedClientError::InvalidRrdPath(
+                "path must not contain . or .. components".to_string(),
+            ));
+        }
+        if !component
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(RRDCachedClientError::InvalidRrdPath(
+                "path must only contain alphanumeric characters, underscores, dashes, dots and slashes"
+                    .to_string(),
+            ));
+        }
+    }
     Ok(())
 }
 
@@ -86,6 +149,9 @@ mod tests {
         assert!(result.is_err());
 
         let result = check_rrd_path("a".repeat(65).as_str());
+        assert!(result.is_ok());
+
+        let result = check_rrd_path("a".repeat(256).as_str());
         assert!(result.is_err());
 
         let result = check_rrd_path("test!");
@@ -97,4 +163,70 @@ mod tests {
         let result = check_rrd_path("test.rrd");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_check_rrd_path_nested() {
+        let result = check_rrd_path("hosts/server-1/cpu");
+        assert!(result.is_ok());
+
+        let result = check_rrd_path("hosts/server-1/cpu.user");
+        assert!(result.is_ok());
+
+        let result = check_rrd_path("hosts/.hidden");
+        assert!(result.is_ok());
+
+        let result = check_rrd_path("/var/lib/rrdcached/db/test");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("../test");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts/../../test");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("./test");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts//test");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts/");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts/server 1/cpu");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts/server-1\ncpu");
+        assert!(result.is_err());
+
+        let result = check_rrd_path("hosts/server-1/cpu.rrd");
+        assert!(result.is_err());
+
+        let result = check_rrd_path(&format!("{}/test", "a".repeat(255)));
+        assert!(result.is_ok());
+
+        let result = check_rrd_path(&format!("{}a", "a/".repeat(2048)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_check_rrd_directory() {
+        let result = check_rrd_directory("");
+        assert!(result.is_ok());
+
+        let result = check_rrd_directory("hosts/server-1");
+        assert!(result.is_ok());
+
+        let result = check_rrd_directory("archive.rrd");
+        assert!(result.is_ok());
+
+        let result = check_rrd_directory("/hosts");
+        assert!(result.is_err());
+
+        let result = check_rrd_directory("hosts/../..");
+        assert!(result.is_err());
+
+        let result = check_rrd_directory("hosts/server 1");
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,230 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{errors::RRDCachedClientError, parsers::parse_stats_raw_line};
+
+/// Statistics of the server, as returned by the STATS command.
+///
+/// Missing statistics default to 0.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ServerStats {
+    /// Number of nodes currently enqueued in the update queue
+    pub queue_length: u64,
+
+    /// Number of UPDATE commands received
+    pub updates_received: u64,
+
+    /// Number of FLUSH commands received
+    pub flushes_received: u64,
+
+    /// Total number of updates written to disk
+    pub updates_written: u64,
+
+    /// Total number of data sets written to disk
+    pub data_sets_written: u64,
+
+    /// Number of nodes in the cache
+    pub tree_nodes_number: u64,
+
+    /// Depth of the tree used for fast key lookup
+    pub tree_depth: u64,
+
+    /// Total number of bytes written to the journal since startup
+    pub journal_bytes: u64,
+
+    /// Number of times the journal has been rotated since startup
+    pub journal_rotate: u64,
+
+    /// Statistics unknown to this client
+    pub extra: HashMap<String, String>,
+}
+
+/// Difference of the counters between two [ServerStats].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ServerStatsDelta {
+    pub updates_received: u64,
+    pub flushes_received: u64,
+    pub updates_written: u64,
+    pub data_sets_written: u64,
+    pub journal_bytes: u64,
+    pub journal_rotate: u64,
+}
+
+/// Rates per second of the counters between two [ServerStats].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ServerStatsRates {
+    pub updates_received: f64,
+    pub flushes_received: f64,
+    pub updates_written: f64,
+    pub data_sets_written: f64,
+    pub journal_bytes: f64,
+    pub journal_rotate: f64,
+}
+
+/// Difference between two values of a counter.
+///
+/// The counters are reset when the server restarts,
+/// in which case the current value is the difference.
+fn counter_delta(current: u64, previous: u64) -> u64 {
+    if current >= previous {
+        current - previous
+    } else {
+        current
+    }
+}
+
+impl ServerStats {
+    pub fn from_lines(lines: Vec<String>) -> Result<ServerStats, RRDCachedClientError> {
+        let mut stats = ServerStats::default();
+        for line in lines.iter() {
+            let (name, value) = parse_stats_raw_line(line)?;
+            let field = match name {
+                "QueueLength" => &mut stats.queue_length,
+                "UpdatesReceived" => &mut stats.updates_received,
+                "FlushesReceived" => &mut stats.flushes_received,
+                "UpdatesWritten" => &mut stats.updates_written,
+                "DataSetsWritten" => &mut stats.data_sets_written,
+                "TreeNodesNumber" => &mut stats.tree_nodes_number,
+                "TreeDepth" => &mut stats.tree_depth,
+                "JournalBytes" => &mut stats.journal_bytes,
+                "JournalRotate" => &mut stats.journal_rotate,
+                _ => {
+                    stats.extra.insert(name.to_string(), value.to_string());
+                    continue;
+                }
+            };
+            *field = value
+                .parse()
+                .map_err(|_| RRDCachedClientError::Parsing(format!("Unable to parse {}", name)))?;
+        }
+        Ok(stats)
+    }
+
+    /// Difference of the counters since a previous snapshot.
+    pub fn delta_since(&self, previous: &ServerStats) -> ServerStatsDelta {
+        ServerStatsDelta {
+            updates_received: counter_delta(self.updates_received, previous.updates_received),
+            flushes_received: counter_delta(self.flushes_received, previous.flushes_received),
+            updates_written: counter_delta(self.updates_written, previous.updates_written),
+            data_sets_written: counter_delta(self.data_sets_written, previous.data_sets_written),
+            journal_bytes: counter_delta(self.journal_bytes, previous.journal_bytes),
+            journal_rotate: counter_delta(self.journal_rotate, previous.journal_rotate),
+        }
+    }
+
+    /// Rates per second of the counters since a previous snapshot,
+    /// taken `elapsed` time ago.
+    pub fn rates_since(&self, previous: &ServerStats, elapsed: Duration) -> ServerStatsRates {
+        self.delta_since(previous).rates(elapsed)
+    }
+}
+
+impl ServerStatsDelta {
+    /// Rates per second, over the elapsed time.
+    ///
+    /// The rates are 0 if no time elapsed.
+    pub fn rates(&self, elapsed: Duration) -> ServerStatsRates {
+        let seconds = elapsed.as_secs_f64();
+        let rate = |delta: u64| {
+            if seconds > 0.0 {
+                delta as f64 / seconds
+            } else {
+                0.0
+            }
+        };
+        ServerStatsRates {
+            updates_received: rate(self.updates_received),
+            flushes_received: rate(self.flushes_received),
+            updates_written: rate(self.updates_written),
+            data_sets_written: rate(self.data_sets_written),
+            journal_bytes: rate(self.journal_bytes),
+            journal_rotate: rate(self.journal_rotate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_lines(updates_received: u64, journal_bytes: u64) -> Vec<String> {
+        vec![
+            "QueueLength: 2\n".to_string(),
+            format!("UpdatesReceived: {}\n", updates_received),
+            "FlushesReceived: 3\n".to_string(),
+            "UpdatesWritten: 13\n".to_string(),
+            "DataSetsWritten: 390\n".to_string(),
+            "TreeNodesNumber: 13\n".to_string(),
+            "TreeDepth: 4\n".to_string(),
+            format!("JournalBytes: {}\n", journal_bytes),
+            "JournalRotate: 0\n".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_successful_parse() {
+        let stats = ServerStats::from_lines(stats_lines(30, 190)).unwrap();
+        assert_eq!(
+            stats,
+            ServerStats {
+                queue_length: 2,
+                updates_received: 30,
+                flushes_received: 3,
+                updates_written: 13,
+                data_sets_written: 390,
+                tree_nodes_number: 13,
+                tree_depth: 4,
+                journal_bytes: 190,
+                journal_rotate: 0,
+                extra: HashMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_extra_and_missing() {
+        let input = vec![
+            "UpdatesReceived: 30\n".to_string(),
+            "Uptime: 1234\n".to_string(),
+            "Version: 1.7.2\n".to_string(),
+        ];
+        let stats = ServerStats::from_lines(input).unwrap();
+        assert_eq!(stats.updates_received, 30);
+        assert_eq!(stats.queue_length, 0);
+        assert_eq!(stats.extra.get("Uptime").unwrap(), "1234");
+        assert_eq!(stats.extra.get("Version").unwrap(), "1.7.2");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let input = vec!["UpdatesReceived: abc\n".to_string()];
+        assert!(ServerStats::from_lines(input).is_err());
+
+        let input = vec!["UpdatesReceived: -1\n".to_string()];
+        assert!(ServerStats::from_lines(input).is_err());
+
+        let input = vec!["UpdatesReceived 30\n".to_string()];
+        assert!(ServerStats::from_lines(input).is_err());
+    }
+
+    #[test]
+    fn test_delta_and_rates() {
+        let previous = ServerStats::from_lines(stats_lines(30, 190)).unwrap();
+        let current = ServerStats::from_lines(stats_lines(50, 390)).unwrap();
+
+        let delta = current.delta_since(&previous);
+        assert_eq!(delta.updates_received, 20);
+        assert_eq!(delta.journal_bytes, 200);
+        assert_eq!(delta.flushes_received, 0);
+
+        let rates = current.rates_since(&previous, Duration::from_secs(10));
+        assert_eq!(rates.updates_received, 2.0);
+        assert_eq!(rates.journal_bytes, 20.0);
+
+        let rates = delta.rates(Duration::ZERO);
+        assert_eq!(rates.updates_received, 0.0);
+
+        // The server restarted
+        let delta = previous.delta_since(&current);
+        assert_eq!(delta.updates_received, 30);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{errors::RRDCachedClientError, now::now_timestamp};
+
+/// Timestamp of an update.
+///
+/// Timestamps are compared by their value, so `Seconds(1)` equals
+/// `Precise(Duration::from_secs(1))`.
+#[derive(Debug, Clone, Copy)]
+pub enum UpdateTimestamp {
+    /// Current time of the server, sent as `N`.
+    ///
+    /// Prefer this over the client time, which may drift from the server time.
+    Now,
+
+    /// Seconds since the UNIX epoch.
+    Seconds(usize),
+
+    /// Duration since the UNIX epoch, with sub-second precision.
+    ///
+    /// RRDtool supports up to microsecond precision, the rest is truncated.
+    Precise(Duration),
+}
+
+impl UpdateTimestamp {
+    /// Current time of the client in seconds.
+    ///
+    /// For the versions of RRDCached that reject `N`, as the RRDtool
+    /// client library does. The time depends on the clock of the client,
+    /// prefer [UpdateTimestamp::Now].
+    pub fn client_now() -> Result<UpdateTimestamp, RRDCachedClientError> {
+        Ok(UpdateTimestamp::Seconds(now_timestamp()?))
+    }
+
+    /// Duration since the UNIX epoch, or `None` for [UpdateTimestamp::Now].
+    pub fn as_duration(&self) -> Option<Duration> {
+        match self {
+            UpdateTimestamp::Now => None,
+            UpdateTimestamp::Seconds(seconds) => Some(Duration::from_secs(*seconds as u64)),
+            UpdateTimestamp::Precise(duration) => {
+                Some(Duration::from_micros(duration.as_micros() as u64))
+            }
+        }
+    }
+
+    /// Build a timestamp from fractional seconds, as printed by the server.
+    pub(crate) fn from_secs_f64(seconds: f64) -> UpdateTimestamp {
+        let duration = Duration::from_micros((seconds * 1_000_000.0).round() as u64);
+        if duration.subsec_micros() == 0 {
+            UpdateTimestamp::Seconds(duration.as_secs() as usize)
+        } else {
+            UpdateTimestamp::Precise(duration)
+        }
+    }
+}
+
+impl PartialEq for UpdateTimestamp {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (UpdateTimestamp::Now, UpdateTimestamp::Now) => true,
+            (UpdateTimestamp::Now, _) | (_, UpdateTimestamp::Now) => false,
+            _ => self.as_duration() == other.as_duration(),
+        }
+    }
+}
+
+impl Eq for UpdateTimestamp {}
+
+impl Hash for UpdateTimestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_duration().hash(state);
+    }
+}
+
+impl PartialOrd for UpdateTimestamp {
+    /// [UpdateTimestamp::Now] can't be compared with other timestamps.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.as_duration(), other.as_duration()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            (None, None) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UpdateTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateTimestamp::Now => f.write_str("N"),
+            UpdateTimestamp::Seconds(seconds) => write!(f, "{}", seconds),
+            UpdateTimestamp::Precise(duration) => {
+                let micros = duration.subsec_micros();
+                if micros == 0 {
+                    write!(f, "{}", duration.as_secs())
+                } else {
+                    let fraction = format!("{:06}", micros);
+                    write!(
+                        f,
+                        "{}.{}",
+                        duration.as_secs(),
+                        fraction.trim_end_matches('0')
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl From<usize> for UpdateTimestamp {
+    fn from(seconds: usize) -> Self {
+        UpdateTimestamp::Seconds(seconds)
+    }
+}
+
+impl From<Duration> for UpdateTimestamp {
+    fn from(duration: Duration) -> Self {
+        UpdateTimestamp::Precise(duration)
+    }
+}
+
+impl TryFrom<SystemTime> for UpdateTimestamp {
+    type Error = RRDCachedClientError;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        time.duration_since(UNIX_EPOCH)
+            .map(UpdateTimestamp::Precise)
+            .map_err(|_| RRDCachedClientError::SystemTimeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_timestamp_to_string() {
+        assert_eq!(UpdateTimestamp::Now.to_string(), "N");
+        assert_eq!(
+            UpdateTimestamp::Seconds(1700000000).to_string(),
+            "1700000000"
+        );
+        assert_eq!(
+            UpdateTimestamp::Precise(Duration::from_millis(1700000000250)).to_string(),
+            "1700000000.25"
+        );
+        assert_eq!(
+            UpdateTimestamp::Precise(Duration::new(1700000000, 1_000)).to_string(),
+            "1700000000.000001"
+        );
+        // Nanoseconds are truncated
+        assert_eq!(
+            UpdateTimestamp::Precise(Duration::new(1700000000, 999)).to_string(),
+            "1700000000"
+        );
+    }
+
+    #[test]
+    fn test_update_timestamp_ordering() {
+        let a = UpdateTimestamp::Seconds(1700000000);
+        let b = UpdateTimestamp::Precise(Duration::from_millis(1700000000250));
+        let c = UpdateTimestamp::Precise(Duration::from_secs(1700000000));
+        assert!(a < b);
+        assert_eq!(a.partial_cmp(&c), Some(Ordering::Equal));
+        assert_eq!(a, c);
+        assert_eq!(a.partial_cmp(&UpdateTimestamp::Now), None);
+    }
+
+    #[test]
+    fn test_update_timestamp_from() {
+        assert_eq!(
+            UpdateTimestamp::from(1700000000),
+            UpdateTimestamp::Seconds(1700000000)
+        );
+        assert_eq!(
+            UpdateTimestamp::from_secs_f64(1700000000.0),
+            UpdateTimestamp::Seconds(1700000000)
+        );
+        assert_eq!(
+            UpdateTimestamp::from_secs_f64(1700000000.25),
+            UpdateTimestamp::Precise(Duration::from_millis(1700000000250))
+        );
+        let now = UpdateTimestamp::try_from(SystemTime::now()).unwrap();
+        assert!(now > UpdateTimestamp::Seconds(1700000000));
+    }
+
+    #[test]
+    fn test_update_timestamp_client_now() {
+        let now = UpdateTimestamp::client_now().unwrap();
+        assert!(now > UpdateTimestamp::Seconds(1700000000));
+        assert!(matches!(now, UpdateTimestamp::Seconds(_)));
+        assert_ne!(now, UpdateTimestamp::Now);
+        assert_eq!(UpdateTimestamp::Now, UpdateTimestamp::Now);
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+/// A value sent to a data source in an update.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateValue {
+    /// A floating point number, NaN is sent as unknown.
+    Number(f64),
+
+    /// A signed integer, sent without loss of precision.
+    ///
+    /// Useful for DERIVE data sources above 2^53.
+    Integer(i64),
+
+    /// An unsigned integer, sent without loss of precision.
+    ///
+    /// Useful for COUNTER data sources, which wrap around at 2^32 or 2^64.
+    Counter(u64),
+
+    /// Unknown value, sent as `U`.
+    Unknown,
+}
+
+impl fmt::Display for UpdateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateValue::Number(value) if value.is_nan() => f.write_str("U"),
+            UpdateValue::Number(value) => write!(f, "{}", value),
+            UpdateValue::Integer(value) => write!(f, "{}", value),
+            UpdateValue::Counter(value) => write!(f, "{}", value),
+            UpdateValue::Unknown => f.write_str("U"),
+        }
+    }
+}
+
+impl From<f64> for UpdateValue {
+    fn from(value: f64) -> Self {
+        UpdateValue::Number(value)
+    }
+}
+
+impl From<i64> for UpdateValue {
+    fn from(value: i64) -> Self {
+        UpdateValue::Integer(value)
+    }
+}
+
+impl From<u64> for UpdateValue {
+    fn from(value: u64) -> Self {
+        UpdateValue::Counter(value)
+    }
+}
+
+impl From<Option<f64>> for UpdateValue {
+    fn from(value: Option<f64>) -> Self {
+        match value {
+            Some(value) => UpdateValue::Number(value),
+            None => UpdateValue::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_value_to_string() {
+        assert_eq!(UpdateValue::Number(4.2).to_string(), "4.2");
+        assert_eq!(UpdateValue::Number(-1.0).to_string(), "-1");
+        assert_eq!(UpdateValue::Number(f64::NAN).to_string(), "U");
+        assert_eq!(UpdateValue::Number(f64::INFINITY).to_string(), "inf");
+        assert_eq!(UpdateValue::Unknown.to_string(), "U");
+        assert_eq!(
+            UpdateValue::Integer(-9007199254740993).to_string(),
+            "-9007199254740993"
+        );
+        assert_eq!(
+            UpdateValue::Counter(u64::MAX).to_string(),
+            "18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn test_update_value_from() {
+        assert_eq!(UpdateValue::from(4.2), UpdateValue::Number(4.2));
+        assert_eq!(UpdateValue::from(-42i64), UpdateValue::Integer(-42));
+        assert_eq!(UpdateValue::from(42u64), UpdateValue::Counter(42));
+        assert_eq!(UpdateValue::from(Some(4.2)), UpdateValue::Number(4.2));
+        assert_eq!(UpdateValue::from(None), UpdateValue::Unknown);
+    }
+}
@@ -1,9 +1,19 @@
 use rrdcached_client::{
     batch_update::BatchUpdate,
     consolidation_function::ConsolidationFunction,
-    create::{CreateArguments, CreateDataSource, CreateDataSourceType, CreateRoundRobinArchive},
+    create::{
+        CreateArchive, CreateArguments, CreateDataSource, CreateDataSourceType,
+        CreateRoundRobinArchive,
+    },
+    create_builder::RetentionPreset,
+    holt_winters::{
+        CreateDevPredictArchive, CreateFailuresArchive, CreateHwPredictArchive,
+        CreateSeasonalArchive,
+    },
     now::now_timestamp,
-    RRDCachedClient,
+    rpn::RpnExpression,
+    update_timestamp::UpdateTimestamp,
+    RRDCachedClient, RRDCachedHandle, RrdPath,
 };
 use tokio::net::TcpStream;
 
@@ -31,7 +41,7 @@ async fn test_create() {
 
     client
         .create(CreateArguments {
-            path: "integration-test-create".to_string(),
+            path: RrdPath::new("integration-test-create").unwrap(),
             data_sources: vec![
                 CreateDataSource {
                     name: "ds1".to_string(),
@@ -39,6 +49,7 @@ async fn test_create() {
                     maximum: None,
                     heartbeat: 10,
                     serie_type: CreateDataSourceType::Gauge,
+                    expression: None,
                 },
                 CreateDataSource {
                     name: "ds2".to_string(),
@@ -46,108 +57,347 @@ async fn test_create() {
                     maximum: Some(100.0),
                     heartbeat: 10,
                     serie_type: CreateDataSourceType::Gauge,
+                    expression: None,
                 },
             ],
             round_robin_archives: vec![
-                CreateRoundRobinArchive {
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
                     consolidation_function: ConsolidationFunction::Average,
                     xfiles_factor: 0.5,
                     steps: 1,
                     rows: 10,
-                },
-                CreateRoundRobinArchive {
+                    step: None,
+                }),
+                CreateArchive::Consolidated(CreateRoundRobinArchive {
                     consolidation_function: ConsolidationFunction::Average,
                     xfiles_factor: 0.5,
                     steps: 10,
                     rows: 10,
-                },
+                    step: None,
+                }),
             ],
             start_timestamp: 1609459200,
             step_seconds: 1,
+            no_overwrite: false,
+            sources: vec![],
+            template: None,
         })
         .await
         .unwrap();
 }
 
-async fn create_simple_rrd(client: &mut RRDCachedClient<TcpStream>, name: String) {
+fn simple_rrd_arguments(path: &RrdPath) -> CreateArguments {
+    CreateArguments {
+        path: path.clone(),
+        data_sources: vec![CreateDataSource {
+            name: "ds1".to_string(),
+            minimum: None,
+            maximum: None,
+            heartbeat: 10,
+            serie_type: CreateDataSourceType::Gauge,
+            expression: None,
+        }],
+        round_robin_archives: vec![CreateArchive::Consolidated(CreateRoundRobinArchive {
+            consolidation_function: ConsolidationFunction::Average,
+            xfiles_factor: 0.5,
+            steps: 1,
+            rows: 100,
+            step: None,
+        })],
+        start_timestamp: 1609459200,
+        step_seconds: 1,
+        no_overwrite: false,
+        sources: vec![],
+        template: None,
+    }
+}
+
+async fn create_simple_rrd(client: &mut RRDCachedClient<TcpStream>, path: &RrdPath) {
+    client.create(simple_rrd_arguments(path)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_update() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+
+    let path = RrdPath::new("test-integrations-update").unwrap();
+
+    create_simple_rrd(&mut client, &path).await;
     client
-        .create(CreateArguments {
-            path: name,
-            data_sources: vec![CreateDataSource {
-                name: "ds1".to_string(),
-                minimum: None,
-                maximum: None,
-                heartbeat: 10,
-                serie_type: CreateDataSourceType::Gauge,
-            }],
-            round_robin_archives: vec![CreateRoundRobinArchive {
-                consolidation_function: ConsolidationFunction::Average,
-                xfiles_factor: 0.5,
-                steps: 1,
-                rows: 100,
-            }],
-            start_timestamp: 1609459200,
-            step_seconds: 1,
-        })
+        .update_one(&path, UpdateTimestamp::Now, 4.2)
         .await
         .unwrap();
 }
 
 #[tokio::test]
-async fn test_update() {
+async fn test_double_create() {
     let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
 
-    create_simple_rrd(&mut client, "test-integrations-update".to_string()).await;
+    let path = RrdPath::new("test-integrations-double-create").unwrap();
+
+    create_simple_rrd(&mut client, &path).await;
+    let timestamp_last = client.last(&path).await.unwrap();
     client
-        .update_one("test-integrations-update", None, 4.2)
+        .update_one(&path, UpdateTimestamp::Now, 4.2)
         .await
         .unwrap();
+    let new_timestamp = client.last(&path).await.unwrap();
+
+    assert!(new_timestamp > timestamp_last);
+
+    create_simple_rrd(&mut client, &path).await;
+    let not_overwritten_timestamp = client.last(&path).await.unwrap();
+    assert_eq!(not_overwritten_timestamp, new_timestamp);
 }
 
 #[tokio::test]
-async fn test_double_create() {
+async fn test_batch() {
     let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
 
-    create_simple_rrd(&mut client, "test-integrations-double-create".to_string()).await;
-    let timestamp_last = client
-        .last("test-integrations-double-create")
+    let path = RrdPath::new("test-integrations-batch").unwrap();
+
+    create_simple_rrd(&mut client, &path).await;
+
+    let now = now_timestamp().unwrap();
+    let commands = vec![
+        BatchUpdate::new(path.clone(), UpdateTimestamp::Seconds(now - 2), vec![1.0]).unwrap(),
+        BatchUpdate::new(path.clone(), UpdateTimestamp::Now, vec![2.0]).unwrap(),
+    ];
+    client.batch(commands).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_nested_path() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
+
+    // The nested directory must exist in the base directory of RRDCached
+    let path = RrdPath::new("nested/test-integrations-nested").unwrap();
+    create_simple_rrd(&mut client, &path).await;
     client
-        .update_one("test-integrations-double-create", None, 4.2)
+        .update_one(&path, UpdateTimestamp::Now, 4.2)
         .await
         .unwrap();
-    let new_timestamp = client
-        .last("test-integrations-double-create")
+    client.flush(&path).await.unwrap();
+    let timestamp = client.last(&path).await.unwrap();
+    assert!(timestamp > 1609459200);
+
+    // Traversal outside the base directory can't be expressed
+    assert!(RrdPath::new("../test-integrations-nested").is_err());
+}
+
+#[tokio::test]
+async fn test_create_no_overwrite() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
 
-    assert!(new_timestamp > timestamp_last);
+    let path = RrdPath::new("test-integrations-no-overwrite").unwrap();
+    create_simple_rrd(&mut client, &path).await;
 
-    create_simple_rrd(&mut client, "test-integrations-double-create".to_string()).await;
-    let not_overwritten_timestamp = client
-        .last("test-integrations-double-create")
+    let result = client
+        .create(CreateArguments {
+            no_overwrite: true,
+            ..simple_rrd_arguments(&path)
+        })
+        .await;
+    assert!(result.is_err());
+
+    // The connection is still usable
+    client.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_create_with_source() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
-    assert_eq!(not_overwritten_timestamp, new_timestamp);
+
+    let source = RrdPath::new("test-integrations-source").unwrap();
+    create_simple_rrd(&mut client, &source).await;
+    let now = now_timestamp().unwrap();
+    client
+        .update_one(&source, UpdateTimestamp::Seconds(now - 1), 4.2)
+        .await
+        .unwrap();
+    client.flush(&source).await.unwrap();
+
+    let path = RrdPath::new("test-integrations-with-source").unwrap();
+    client
+        .create(CreateArguments {
+            sources: vec![source.clone()],
+            ..simple_rrd_arguments(&path)
+        })
+        .await
+        .unwrap();
+    let info = client.info(&path).await.unwrap();
+    assert!(info.data_source("ds1").is_some());
+
+    // The sources must exist
+    let result = client
+        .create(CreateArguments {
+            sources: vec![RrdPath::new("test-integrations-missing-source").unwrap()],
+            ..simple_rrd_arguments(&RrdPath::new("test-integrations-with-missing-source").unwrap())
+        })
+        .await;
+    assert!(result.is_err());
 }
 
 #[tokio::test]
-async fn test_batch() {
+async fn test_create_with_template() {
     let mut client = RRDCachedClient::connect_tcp("localhost:42217")
         .await
         .unwrap();
 
-    create_simple_rrd(&mut client, "test-integrations-batch".to_string()).await;
+    let template = RrdPath::new("test-integrations-template").unwrap();
+    create_simple_rrd(&mut client, &template).await;
+
+    let path = RrdPath::new("test-integrations-from-template").unwrap();
+    client
+        .create(CreateArguments {
+            data_sources: vec![],
+            round_robin_archives: vec![],
+            template: Some(template.clone()),
+            ..simple_rrd_arguments(&path)
+        })
+        .await
+        .unwrap();
 
+    let info = client.info(&path).await.unwrap();
+    let arguments = CreateArguments::from_info(path, &info).unwrap();
+    assert!(arguments.same_schema(&simple_rrd_arguments(&template)));
+}
+
+#[tokio::test]
+async fn test_create_holt_winters() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+
+    let path = RrdPath::new("test-integrations-holt-winters").unwrap();
+    let seasonal = CreateSeasonalArchive {
+        seasonal_period: 60,
+        gamma: 0.1,
+        predict_archive: 1,
+        smoothing_window: None,
+    };
+    let arguments = CreateArguments {
+        round_robin_archives: vec![
+            CreateArchive::Consolidated(CreateRoundRobinArchive {
+                consolidation_function: ConsolidationFunction::Average,
+                xfiles_factor: 0.5,
+                steps: 1,
+                rows: 100,
+                step: None,
+            }),
+            CreateArchive::HwPredict(CreateHwPredictArchive {
+                rows: 100,
+                alpha: 0.1,
+                beta: 0.0035,
+                seasonal_period: 60,
+                seasonal_archive: Some(2),
+            }),
+            CreateArchive::Seasonal(seasonal.clone()),
+            CreateArchive::DevPredict(CreateDevPredictArchive {
+                rows: 100,
+                dev_seasonal_archive: 4,
+            }),
+            CreateArchive::DevSeasonal(seasonal),
+            CreateArchive::Failures(CreateFailuresArchive {
+                rows: 100,
+                threshold: 7,
+                window_length: 9,
+                dev_seasonal_archive: 4,
+            }),
+        ],
+        ..simple_rrd_arguments(&path)
+    };
+    client.create(arguments.clone()).await.unwrap();
+
+    let info = client.info(&path).await.unwrap();
+    let actual = CreateArguments::from_info(path, &info).unwrap();
+    assert_eq!(arguments.schema_differences(&actual), vec![]);
+}
+
+#[tokio::test]
+async fn test_create_compute() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+
+    let path = RrdPath::new("test-integrations-compute").unwrap();
+    let mut arguments = simple_rrd_arguments(&path);
+    arguments.data_sources.push(CreateDataSource::compute(
+        "ds1_bits",
+        RpnExpression::data_source("ds1") * 8.0,
+    ));
+    client.create(arguments.clone()).await.unwrap();
+
+    let info = client.info(&path).await.unwrap();
+    let actual = CreateArguments::from_info(path, &info).unwrap();
+    assert_eq!(arguments.schema_differences(&actual), vec![]);
+}
+
+#[tokio::test]
+async fn test_create_with_builder() {
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+
+    let arguments = CreateArguments::builder("test-integrations-builder")
+        .gauge("temp", Some(-40.0), Some(60.0))
+        .preset(RetentionPreset::HighResolution)
+        .build()
+        .unwrap();
+    client.create(arguments.clone()).await.unwrap();
+
+    let info = client.info(&arguments.path).await.unwrap();
+    let actual = CreateArguments::from_info(arguments.path.clone(), &info).unwrap();
+    assert_eq!(arguments.schema_differences(&actual), vec![]);
+}
+
+#[tokio::test]
+async fn test_shared_handle() {
+    let handle = RRDCachedHandle::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+
+    let mut client = RRDCachedClient::connect_tcp("localhost:42217")
+        .await
+        .unwrap();
+    let paths: Vec<RrdPath> = (0..10)
+        .map(|i| RrdPath::new(&format!("test-integrations-handle-{}", i)).unwrap())
+        .collect();
+    for path in &paths {
+        create_simple_rrd(&mut client, path).await;
+    }
+
+    // Many tasks share the same connection
     let now = now_timestamp().unwrap();
-    let commands = vec![
-        BatchUpdate::new("test-integrations-batch", Some(now - 2), vec![1.0]).unwrap(),
-        BatchUpdate::new("test-integrations-batch", None, vec![2.0]).unwrap(),
-    ];
-    client.batch(commands).await.unwrap();
+    let tasks: Vec<_> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let handle = handle.clone();
+            let path = path.clone();
+            tokio::spawn(async move {
+                handle
+                    .update_one(&path, UpdateTimestamp::Seconds(now - i), i as f64)
+                    .await
+                    .unwrap();
+                assert_eq!(handle.last(&path).await.unwrap(), now - i);
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    handle.ping().await.unwrap();
 }